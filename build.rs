@@ -60,6 +60,7 @@ fn main() {
         .allowlist_type("MS.*")
         .allowlist_var("MS_.*")
         .allowlist_var("HPT.*")
+        .allowlist_var("DE_.*")
         .allowlist_function("ms_.*")
         .allowlist_function("msr_.*")
         .allowlist_function("mst_.*")
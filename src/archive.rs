@@ -0,0 +1,539 @@
+//! Archives with a layout given by a path template
+//!
+//! A `PathTemplate` maps a channel and time to a file path relative to the
+//! archive root.  The following tokens are replaced:
+//!
+//! - `%n` - network code
+//! - `%s` - station code
+//! - `%l` - location code
+//! - `%c` - channel code
+//! - `%q` - data quality code
+//! - `%Y` - four digit year
+//! - `%m` - two digit month
+//! - `%d` - two digit day of month
+//! - `%j` - three digit day of year
+//! - `%H` - two digit hour
+//! - `%%` - a literal `%`
+//!
+//! Templates for the SDS and IRIS BUD layouts are provided.
+//!
+//! ```
+//! use miniseed::archive::{Archive, PathTemplate};
+//! use miniseed::str_to_utc;
+//!
+//! let t = str_to_utc("2010-02-27T06:30:00").unwrap();
+//! let bud = Archive::bud("bud");
+//! assert_eq!(bud.path("IU", "ANMO", "00", "BHZ", 'D', &t).to_str(),
+//!            Some("bud/IU/ANMO/ANMO.IU.00.BHZ.2010.058"));
+//!
+//! let hourly = Archive::new("data", PathTemplate::new("%n.%s.%l.%c.%Y%m%d%H"));
+//! assert_eq!(hourly.path("IU", "ANMO", "00", "BHZ", 'D', &t).to_str(),
+//!            Some("data/IU.ANMO.00.BHZ.2010022706"));
+//! ```
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::TimeZone;
+use chrono::Timelike;
+use chrono::Utc;
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use cut::Boundary;
+use {ms_input, ms_output, ms_record, Selection, Trace};
+
+/// Time span covered by a single file of a template
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Hour,
+    Day,
+    Month,
+    Year,
+    /// Files are not split by time
+    Unbounded,
+}
+
+impl Period {
+    /// Return the start of the period containing `t`
+    pub fn start(&self, t: &DateTime<Utc>) -> DateTime<Utc> {
+        let d = t.naive_utc().date();
+        let day = match *self {
+            Period::Hour => return Utc.from_utc_datetime(&d.and_hms_opt(t.hour(), 0, 0).unwrap()),
+            Period::Day => d,
+            Period::Month => NaiveDate::from_ymd_opt(d.year(), d.month(), 1).unwrap(),
+            Period::Year => NaiveDate::from_ymd_opt(d.year(), 1, 1).unwrap(),
+            Period::Unbounded => return *t,
+        };
+        Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+    }
+    /// Return the start of the period following the one containing `t`
+    ///
+    /// Returns None for Period::Unbounded
+    pub fn next(&self, t: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let t0 = self.start(t);
+        let d = t0.naive_utc().date();
+        let day = match *self {
+            Period::Hour => return Some(t0 + Duration::hours(1)),
+            Period::Day => return Some(t0 + Duration::days(1)),
+            Period::Month if d.month() == 12 => NaiveDate::from_ymd_opt(d.year() + 1, 1, 1),
+            Period::Month => NaiveDate::from_ymd_opt(d.year(), d.month() + 1, 1),
+            Period::Year => NaiveDate::from_ymd_opt(d.year() + 1, 1, 1),
+            Period::Unbounded => return None,
+        };
+        Some(Utc.from_utc_datetime(&day?.and_hms_opt(0, 0, 0).unwrap()))
+    }
+}
+
+/// File path template
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTemplate {
+    template: String,
+}
+
+impl PathTemplate {
+    /// SDS layout, `%Y/%n/%s/%c.D/%n.%s.%l.%c.D.%Y.%j`
+    pub const SDS: &'static str = "%Y/%n/%s/%c.D/%n.%s.%l.%c.D.%Y.%j";
+    /// IRIS BUD layout, `%n/%s/%s.%n.%l.%c.%Y.%j`
+    pub const BUD: &'static str = "%n/%s/%s.%n.%l.%c.%Y.%j";
+
+    /// Create a new template
+    pub fn new(template: &str) -> PathTemplate {
+        PathTemplate {
+            template: template.to_string(),
+        }
+    }
+    /// Return the template string
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+    /// Return the time span covered by each file
+    pub fn period(&self) -> Period {
+        let t = &self.template;
+        if t.contains("%H") {
+            Period::Hour
+        } else if t.contains("%j") || t.contains("%d") {
+            Period::Day
+        } else if t.contains("%m") {
+            Period::Month
+        } else if t.contains("%Y") {
+            Period::Year
+        } else {
+            Period::Unbounded
+        }
+    }
+    /// Replace tokens with channel codes and the time `t`
+    ///
+    /// If `t` is None, time tokens are replaced by `*`
+    pub fn render(
+        &self,
+        net: &str,
+        sta: &str,
+        loc: &str,
+        cha: &str,
+        quality: &str,
+        t: Option<&DateTime<Utc>>,
+    ) -> String {
+        let mut out = String::new();
+        let mut chars = self.template.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let token = match chars.next() {
+                Some(v) => v,
+                None => {
+                    out.push('%');
+                    break;
+                }
+            };
+            let v = match (token, t) {
+                ('n', _) => net.to_string(),
+                ('s', _) => sta.to_string(),
+                ('l', _) => loc.to_string(),
+                ('c', _) => cha.to_string(),
+                ('q', _) => quality.to_string(),
+                ('%', _) => "%".to_string(),
+                ('Y', Some(t)) => format!("{:04}", t.year()),
+                ('m', Some(t)) => format!("{:02}", t.month()),
+                ('d', Some(t)) => format!("{:02}", t.day()),
+                ('j', Some(t)) => format!("{:03}", t.ordinal()),
+                ('H', Some(t)) => format!("{:02}", t.hour()),
+                ('Y', None) | ('m', None) | ('d', None) | ('j', None) | ('H', None) => {
+                    "*".to_string()
+                }
+                (v, _) => format!("%{}", v),
+            };
+            out.push_str(&v);
+        }
+        out
+    }
+}
+
+/// Archive reader
+///
+/// ```no_run
+/// use miniseed::archive::Archive;
+/// use miniseed::{str_to_utc, Selection};
+///
+/// let bud = Archive::bud("/data/bud");
+/// let mut sel = Selection::new();
+/// sel.add("IU_ANMO_00_BH?", None, None);
+/// let t0 = str_to_utc("2010-02-27T06:30:00").unwrap();
+/// let t1 = str_to_utc("2010-02-28T06:30:00").unwrap();
+/// for input in bud.inputs(&sel, &t0, &t1) {
+///     for rec in input {
+///         println!("{}", rec);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Archive {
+    root: PathBuf,
+    template: PathTemplate,
+}
+
+impl Archive {
+    /// Create an archive at `root` with a path template
+    pub fn new<S: AsRef<Path>>(root: S, template: PathTemplate) -> Archive {
+        Archive {
+            root: root.as_ref().to_path_buf(),
+            template,
+        }
+    }
+    /// Create an SDS archive at `root`
+    pub fn sds<S: AsRef<Path>>(root: S) -> Archive {
+        Archive::new(root, PathTemplate::new(PathTemplate::SDS))
+    }
+    /// Create an IRIS BUD archive at `root`
+    pub fn bud<S: AsRef<Path>>(root: S) -> Archive {
+        Archive::new(root, PathTemplate::new(PathTemplate::BUD))
+    }
+    /// Return the archive root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    /// Return the path template
+    pub fn template(&self) -> &PathTemplate {
+        &self.template
+    }
+    /// Return the path of the file for a channel, quality and time
+    pub fn path(
+        &self,
+        net: &str,
+        sta: &str,
+        loc: &str,
+        cha: &str,
+        quality: char,
+        t: &DateTime<Utc>,
+    ) -> PathBuf {
+        let q = quality.to_string();
+        self.root
+            .join(self.template.render(net, sta, loc, cha, &q, Some(t)))
+    }
+    /// Return the path of the file for a record
+    pub fn record_path(&self, rec: &ms_record) -> PathBuf {
+        self.path(
+            &rec.network(),
+            &rec.station(),
+            &rec.location(),
+            &rec.channel(),
+            rec.dataquality().chars().next().unwrap_or('D'),
+            &rec.start(),
+        )
+    }
+    fn glob(&self, sel: &Selection, t: Option<&DateTime<Utc>>, files: &mut Vec<PathBuf>) {
+        for e in sel.entries() {
+            let n = &e.nslc;
+            let q = e.quality.map(|q| q.to_string()).unwrap_or("?".to_string());
+            let pattern = self
+                .root
+                .join(self.template.render(&n[0], &n[1], &n[2], &n[3], &q, t));
+            if let Ok(paths) = glob::glob(&pattern.to_string_lossy()) {
+                files.extend(paths.filter_map(|p| p.ok()).filter(|p| p.is_file()));
+            }
+        }
+    }
+    /// Return all existing files matching the selection at any time
+    pub fn all_files(&self, sel: &Selection) -> Vec<PathBuf> {
+        let mut files = vec![];
+        self.glob(sel, None, &mut files);
+        files.sort();
+        files.dedup();
+        files
+    }
+    /// Return the existing files which may contain data matching the
+    /// selection between `start` and `end`
+    ///
+    /// The period before `start` is included, as records starting before
+    /// a period boundary are stored in the file of the previous period
+    pub fn files(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<PathBuf> {
+        let period = self.template.period();
+        let mut files = vec![];
+        if period == Period::Unbounded {
+            self.glob(sel, None, &mut files);
+        } else {
+            let mut t = period.start(&(period.start(start) - Duration::seconds(1)));
+            while t <= *end {
+                self.glob(sel, Some(&t), &mut files);
+                t = period.next(&t).unwrap();
+            }
+        }
+        files.sort();
+        files.dedup();
+        files
+    }
+    /// Return inputs for files matching the selection between `start`
+    /// and `end`, each restricted to the selection and window
+    pub fn inputs(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<ms_input> {
+        let mut window = Selection::new();
+        for e in sel.entries() {
+            window.add_with_quality(&e.pattern(), e.quality, Some(*start), Some(*end));
+        }
+        self.files(sel, start, end)
+            .into_iter()
+            .map(|file| ms_input::open(file).with_selection(window.clone()))
+            .collect()
+    }
+    /// Read records matching the selection between `start` and `end`
+    ///
+    /// Time windows in the selection are also applied
+    pub fn records(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<ms_record> {
+        let mut out = vec![];
+        for input in self.inputs(sel, start, end) {
+            out.extend(input.filter(|rec| sel.matches(rec)));
+        }
+        out
+    }
+    /// Read traces matching the selection between `start` and `end`
+    ///
+    /// Records are merged into contiguous traces across files and
+    /// trimmed to the window `[start, end)`
+    pub fn traces(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<Trace> {
+        let recs = self.records(sel, start, end);
+        Trace::from_records(&recs)
+            .iter()
+            .filter_map(|tr| tr.trim(start, end, Boundary::Inclusive))
+            .collect()
+    }
+}
+
+struct OpenFile {
+    path: PathBuf,
+    output: ms_output,
+    last_used: Instant,
+}
+
+/// Archive writer
+///
+/// Records are appended to the file of their start time.  A bounded
+/// number of files are kept open, with the least recently used file
+/// closed when the limit is reached.
+///
+/// ```no_run
+/// use miniseed::ms_input;
+/// use miniseed::archive::{Archive, ArchiveWriter};
+///
+/// let mut bud = ArchiveWriter::new(Archive::bud("/data/bud"));
+/// for rec in ms_input::open("tests/multiple.seed") {
+///     bud.write(&rec).unwrap();
+/// }
+/// bud.close().unwrap();
+/// ```
+pub struct ArchiveWriter {
+    archive: Archive,
+    max_open: usize,
+    split: bool,
+    period: Option<Period>,
+    truncate: bool,
+    files: Vec<OpenFile>,
+    paths: Vec<PathBuf>,
+    written: HashSet<PathBuf>,
+}
+
+impl ArchiveWriter {
+    /// Create a writer for an archive
+    ///
+    /// By default up to 64 files are kept open and records are not split
+    pub fn new(archive: Archive) -> ArchiveWriter {
+        ArchiveWriter {
+            archive,
+            max_open: 64,
+            split: false,
+            period: None,
+            truncate: false,
+            files: vec![],
+            paths: vec![],
+            written: HashSet::new(),
+        }
+    }
+    /// Return the archive
+    pub fn archive(&self) -> &Archive {
+        &self.archive
+    }
+    /// Set the maximum number of open files
+    pub fn with_max_open(mut self, max_open: usize) -> ArchiveWriter {
+        self.max_open = max_open.max(1);
+        self
+    }
+    /// Split records which straddle a file period boundary
+    ///
+    /// Split records are repacked with the original record length,
+    /// encoding and header flags
+    pub fn with_split(mut self, split: bool) -> ArchiveWriter {
+        self.split = split;
+        self
+    }
+    /// Set the period at which records are split, by default the
+    /// period of the archive template
+    pub fn with_period(mut self, period: Period) -> ArchiveWriter {
+        self.period = Some(period);
+        self
+    }
+    /// Truncate existing files when first written to, rather than
+    /// appending to them
+    pub fn with_truncate(mut self, truncate: bool) -> ArchiveWriter {
+        self.truncate = truncate;
+        self
+    }
+    /// Return the number of open files
+    pub fn num_open(&self) -> usize {
+        self.files.len()
+    }
+    /// Return the files written to, in the order first written
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+    /// Write a record to its file
+    pub fn write(&mut self, rec: &ms_record) -> io::Result<()> {
+        let period = self
+            .period
+            .unwrap_or_else(|| self.archive.template.period());
+        let (t0, t1) = (rec.start(), rec.end());
+        if self.split && period != Period::Unbounded && period.start(&t0) != period.start(&t1) {
+            if let Some(tr) = Trace::from_record(rec) {
+                let enc = if tr.data.can_encode(rec.encoding()) {
+                    rec.encoding()
+                } else {
+                    tr.data.default_encoding()
+                };
+                let mut a = tr.start;
+                while a < tr.end1() {
+                    let b = period.next(&a).unwrap_or(tr.end1());
+                    if let Some(part) = tr.trim(&a, &b, Boundary::Inclusive) {
+                        for buf in part.pack_like(rec, rec.reclen(), enc) {
+                            let r = ms_record::parse(&buf);
+                            self.output(&r)?.write_raw(&buf)?;
+                        }
+                    }
+                    a = b;
+                }
+                return Ok(());
+            }
+        }
+        self.output(rec)?.write(rec)
+    }
+    fn output(&mut self, rec: &ms_record) -> io::Result<&mut ms_output> {
+        let path = self.archive.record_path(rec);
+        let i = match self.files.iter().position(|f| f.path == path) {
+            Some(i) => i,
+            None => {
+                if self.files.len() >= self.max_open {
+                    let lru = self
+                        .files
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|&(_, f)| f.last_used)
+                        .map(|(i, _)| i)
+                        .unwrap();
+                    self.files.swap_remove(lru).output.flush()?;
+                }
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let output = if self.written.contains(&path) {
+                    ms_output::append(&path)?
+                } else {
+                    self.written.insert(path.clone());
+                    self.paths.push(path.clone());
+                    if self.truncate {
+                        ms_output::open(&path)?
+                    } else {
+                        ms_output::append(&path)?
+                    }
+                };
+                // Records are written as read, keeping their blockettes,
+                // flags and sequence numbers
+                let output = output.with_passthrough(true);
+                self.files.push(OpenFile {
+                    path,
+                    output,
+                    last_used: Instant::now(),
+                });
+                self.files.len() - 1
+            }
+        };
+        let f = &mut self.files[i];
+        f.last_used = Instant::now();
+        Ok(&mut f.output)
+    }
+    /// Flush all open files
+    pub fn flush(&mut self) -> io::Result<()> {
+        for f in self.files.iter_mut() {
+            f.output.flush()?;
+        }
+        Ok(())
+    }
+    /// Close files which have not been written to within `idle`
+    ///
+    /// Closed files stay in paths() and are appended to if written
+    /// again.  Returns the number of files closed
+    pub fn close_idle(&mut self, idle: std::time::Duration) -> io::Result<usize> {
+        let now = Instant::now();
+        let mut n = 0;
+        let mut i = 0;
+        while i < self.files.len() {
+            if now.duration_since(self.files[i].last_used) >= idle {
+                self.files.swap_remove(i).output.flush()?;
+                n += 1;
+            } else {
+                i += 1;
+            }
+        }
+        Ok(n)
+    }
+    /// Flush and close all open files
+    pub fn close(&mut self) -> io::Result<()> {
+        for mut f in self.files.drain(..) {
+            f.output.flush()?;
+        }
+        Ok(())
+    }
+}
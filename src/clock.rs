@@ -0,0 +1,344 @@
+//! Clock corrections
+//!
+//! A `ClockCorrection` gives the offset, in seconds, to add to the
+//! timestamps of matching channels as a constant or as a piecewise-linear
+//! function of time.  Corrections are applied to records either by
+//! setting the time correction field of the fixed header, which readers
+//! add to the start time, or by rewriting the start time.  Each change is
+//! described by an `AuditEntry`.
+//!
+//! ```
+//! use miniseed::clock::{CorrectionTable, Method};
+//! use miniseed::{ms_record, str_to_utc};
+//!
+//! let table = CorrectionTable::parse("
+//!     # NET_STA_LOC_CHA   TIME                  OFFSET
+//!     PN_PPNAF_*_*        2016-10-30T00:00:00   0.0
+//!     PN_PPNAF_*_*        2016-10-31T00:00:00   2.0
+//! ").unwrap();
+//!
+//! let rec = ms_record::read("tests/sample.miniseed");
+//! let (rec, audit) = table.apply_record(&rec, Method::Shift).unwrap();
+//! let audit = audit.unwrap();
+//! assert_eq!(audit.start.to_string(), "2016-10-30 18:02:58.230 UTC");
+//! assert_eq!(rec.start(), str_to_utc("2016-10-30T18:02:59.7341").unwrap());
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::TimeZone;
+use chrono::Utc;
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use edit::HeaderEditor;
+use index::MsIndex;
+use selection::wildcard_match;
+use {ms_record, str_to_utc, utc_to_f64, Trace};
+
+/// Activity flag bit set when the time correction has been applied
+pub const TIME_CORRECTION_APPLIED: u8 = 0x02;
+
+/// How a correction is applied to a record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// Add the correction to the time correction field of the header,
+    /// the start time is unchanged and readers apply the correction
+    Header,
+    /// Rewrite the start time, including any correction not yet applied,
+    /// and mark the time correction field as applied
+    Shift,
+}
+
+/// Clock correction for the channels matching a pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockCorrection {
+    /// Pattern `NET_STA_LOC_CHA` with `?` and `*` wildcards
+    pub pattern: String,
+    points: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl ClockCorrection {
+    /// Create a correction without any offsets
+    pub fn new(pattern: &str) -> ClockCorrection {
+        ClockCorrection {
+            pattern: pattern.to_string(),
+            points: vec![],
+        }
+    }
+    /// Create a constant correction of `offset` seconds
+    pub fn constant(pattern: &str, offset: f64) -> ClockCorrection {
+        let mut c = ClockCorrection::new(pattern);
+        c.points.push((Utc.timestamp_opt(0, 0).unwrap(), offset));
+        c
+    }
+    /// Add the offset in seconds at time `t`
+    ///
+    /// Offsets are interpolated linearly between points, and held
+    /// constant before the first and after the last point
+    pub fn add_point(&mut self, t: DateTime<Utc>, offset: f64) {
+        self.points.push((t, offset));
+        self.points.sort_by_key(|p| p.0);
+    }
+    /// Return the points of the correction
+    pub fn points(&self) -> &[(DateTime<Utc>, f64)] {
+        &self.points
+    }
+    /// Return true if the correction applies to the channel `id`
+    pub fn matches(&self, id: &str) -> bool {
+        wildcard_match(&self.pattern, id)
+    }
+    /// Return the offset in seconds at time `t`
+    ///
+    /// ```
+    /// # use miniseed::clock::ClockCorrection;
+    /// # use miniseed::str_to_utc;
+    /// let mut c = ClockCorrection::new("*");
+    /// c.add_point(str_to_utc("2020-01-01").unwrap(), 0.0);
+    /// c.add_point(str_to_utc("2020-01-03").unwrap(), 1.0);
+    /// assert_eq!(c.offset_at(&str_to_utc("2019-06-01").unwrap()), 0.0);
+    /// assert_eq!(c.offset_at(&str_to_utc("2020-01-02").unwrap()), 0.5);
+    /// assert_eq!(c.offset_at(&str_to_utc("2021-01-01").unwrap()), 1.0);
+    /// ```
+    pub fn offset_at(&self, t: &DateTime<Utc>) -> f64 {
+        let p = &self.points;
+        match p.iter().position(|&(pt, _)| pt > *t) {
+            None => p.last().map_or(0.0, |x| x.1),
+            Some(0) => p[0].1,
+            Some(i) => {
+                let (t0, v0) = p[i - 1];
+                let (t1, v1) = p[i];
+                let f = (utc_to_f64(t) - utc_to_f64(&t0)) / (utc_to_f64(&t1) - utc_to_f64(&t0));
+                v0 + f * (v1 - v0)
+            }
+        }
+    }
+}
+
+/// Record of a correction applied to a record or trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Channel id `NET_STA_LOC_CHA`
+    pub id: String,
+    pub method: Method,
+    /// Start time before the correction
+    pub start: DateTime<Utc>,
+    /// Start time after the correction, including corrections applied
+    /// by readers
+    pub corrected: DateTime<Utc>,
+    /// Correction added in seconds
+    pub offset: f64,
+    /// Time correction field before and after, in 0.0001 seconds, None
+    /// for traces
+    pub time_correct: Option<(i32, i32)>,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt = "%Y-%m-%dT%H:%M:%S%.6f";
+        write!(
+            f,
+            "{} {:?} {} {} {:.6}",
+            self.id,
+            self.method,
+            self.start.format(fmt),
+            self.corrected.format(fmt),
+            self.offset
+        )?;
+        if let Some((a, b)) = self.time_correct {
+            write!(f, " {} {}", a, b)?;
+        }
+        Ok(())
+    }
+}
+
+fn micros(secs: f64) -> Duration {
+    Duration::microseconds((secs * 1e6).round() as i64)
+}
+
+/// Table of clock corrections
+///
+/// The first correction matching a channel is used
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorrectionTable {
+    corrections: Vec<ClockCorrection>,
+}
+
+impl CorrectionTable {
+    /// Create an empty table
+    pub fn new() -> CorrectionTable {
+        CorrectionTable {
+            corrections: vec![],
+        }
+    }
+    /// Add a correction
+    pub fn add(&mut self, correction: ClockCorrection) {
+        self.corrections.push(correction);
+    }
+    /// Return the corrections
+    pub fn corrections(&self) -> &[ClockCorrection] {
+        &self.corrections
+    }
+    /// Return the correction for the channel `id`
+    pub fn find(&self, id: &str) -> Option<&ClockCorrection> {
+        self.corrections.iter().find(|c| c.matches(id))
+    }
+    /// Read a table from a file, see parse()
+    pub fn from_file<S: AsRef<Path>>(file: S) -> io::Result<CorrectionTable> {
+        let mut s = String::new();
+        File::open(file)?.read_to_string(&mut s)?;
+        CorrectionTable::parse(&s)
+    }
+    /// Parse a table, one point per line
+    ///
+    /// ```text
+    /// NET_STA_LOC_CHA TIME OFFSET
+    /// ```
+    ///
+    /// The pattern may contain `?` and `*` wildcards, and `OFFSET` is in
+    /// seconds.  Lines with the same pattern form a piecewise-linear
+    /// correction, a single line is a constant correction.  Blank lines
+    /// and lines beginning with `#` are ignored.
+    pub fn parse(text: &str) -> io::Result<CorrectionTable> {
+        let mut table = CorrectionTable::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("correction line {}: {}: {}", i + 1, msg, line),
+                )
+            };
+            let v: Vec<&str> = line.split_whitespace().collect();
+            if v.len() != 3 {
+                return Err(bad("expected 3 fields"));
+            }
+            let t = str_to_utc(v[1]).ok_or_else(|| bad("invalid time"))?;
+            let offset: f64 = v[2].parse().map_err(|_| bad("invalid offset"))?;
+            match table.corrections.iter_mut().find(|c| c.pattern == v[0]) {
+                Some(c) => c.add_point(t, offset),
+                None => {
+                    let mut c = ClockCorrection::new(v[0]);
+                    c.add_point(t, offset);
+                    table.add(c);
+                }
+            }
+        }
+        Ok(table)
+    }
+    /// Apply the correction to the record at the start of `buf`
+    ///
+    /// Returns None if the buffer does not begin with a record or no
+    /// correction matches.  The `Header` method fails if the record
+    /// already has an applied time correction.
+    pub fn apply_bytes(&self, buf: &mut [u8], method: Method) -> io::Result<Option<AuditEntry>> {
+        let mut ed = match HeaderEditor::new(buf) {
+            Some(ed) => ed,
+            None => return Ok(None),
+        };
+        let h = ed.header();
+        let id = h.id();
+        let corr = match self.find(&id) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let start = h.corrected_start();
+        let offset = corr.offset_at(&start);
+        let before = h.time_correct;
+        let after = before + (offset * 1e4).round() as i32;
+        let applied = h.act_flags & TIME_CORRECTION_APPLIED != 0;
+        let corrected = match method {
+            Method::Header => {
+                if applied {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}: time correction already applied", id),
+                    ));
+                }
+                ed.set_time_correction(after);
+                start + micros(offset)
+            }
+            Method::Shift => {
+                // Remove the previous correction from the start time,
+                // whether applied or not, then apply the total correction
+                let base = start - Duration::microseconds(before as i64 * 100);
+                let t = base + Duration::microseconds(after as i64 * 100);
+                let t = ed.set_start(&t);
+                ed.set_time_correction(after);
+                ed.set_act_flags(h.act_flags | TIME_CORRECTION_APPLIED);
+                t
+            }
+        };
+        Ok(Some(AuditEntry {
+            id,
+            method,
+            start,
+            corrected,
+            offset,
+            time_correct: Some((before, after)),
+        }))
+    }
+    /// Apply the correction to a record and return the corrected record
+    ///
+    /// The record bytes are edited without re-encoding, see apply_bytes()
+    pub fn apply_record(
+        &self,
+        rec: &ms_record,
+        method: Method,
+    ) -> io::Result<(ms_record, Option<AuditEntry>)> {
+        let mut buf = rec.raw_bytes().to_vec();
+        let audit = self.apply_bytes(&mut buf, method)?;
+        Ok((ms_record::parse(&buf), audit))
+    }
+    /// Shift the start time of a trace by the correction at its start
+    ///
+    /// Returns None if no correction matches
+    pub fn apply_trace(&self, tr: &mut Trace) -> Option<AuditEntry> {
+        let id = tr.id();
+        let offset = self.find(&id)?.offset_at(&tr.start);
+        let start = tr.start;
+        tr.start = start + micros(offset);
+        Some(AuditEntry {
+            id,
+            method: Method::Shift,
+            start,
+            corrected: tr.start,
+            offset,
+            time_correct: None,
+        })
+    }
+    /// Apply the corrections to the records of `input` and write all
+    /// records to `output`
+    ///
+    /// Data which are not miniSEED records are dropped.  Returns the
+    /// audit entries of the corrected records.
+    pub fn apply_file<S: AsRef<Path>, P: AsRef<Path>>(
+        &self,
+        input: S,
+        output: P,
+        method: Method,
+    ) -> io::Result<Vec<AuditEntry>> {
+        let idx = MsIndex::build(&input)?;
+        let mut fp = File::open(&input)?;
+        let mut out = BufWriter::new(File::create(output)?);
+        let mut audit = vec![];
+        for e in idx.entries() {
+            let mut buf = vec![0u8; e.reclen];
+            fp.seek(SeekFrom::Start(e.offset))?;
+            fp.read_exact(&mut buf)?;
+            if let Some(a) = self.apply_bytes(&mut buf, method)? {
+                audit.push(a);
+            }
+            out.write_all(&buf)?;
+        }
+        out.flush()?;
+        Ok(audit)
+    }
+}
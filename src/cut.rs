@@ -0,0 +1,123 @@
+//! Time-window cutting with sample-accurate trimming
+//!
+//! Records are merged into contiguous traces and trimmed to a `[start, end)`
+//! window.  The `Boundary` determines which samples are kept at the edges
+//! of the window.
+//!
+//! ```
+//! use miniseed::{ms_input, str_to_utc};
+//! use miniseed::cut::{cut, Boundary};
+//!
+//! let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+//! let t0 = str_to_utc("2010-02-27T06:30:10").unwrap();
+//! let t1 = str_to_utc("2010-02-27T06:30:20").unwrap();
+//! let traces = cut(&recs, &t0, &t1, Boundary::Inclusive);
+//! assert_eq!(traces.len(), 1);
+//! assert_eq!(traces[0].npts(), 200);
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use {ms_record, Trace};
+
+/// Sample selection at the edges of a cut window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    /// Keep samples nearest to the window start and end, the first
+    /// sample may lie up to half a sample before the window start
+    Nearest,
+    /// Keep only samples within the window, `start <= t < end`
+    Inclusive,
+}
+
+// Tolerance, as a fraction of a sample, for timing rounding errors
+const SAMPLE_TOL: f64 = 1e-6;
+
+impl Boundary {
+    fn index(&self, x: f64) -> f64 {
+        match *self {
+            Boundary::Nearest => x.round(),
+            Boundary::Inclusive => (x - SAMPLE_TOL).ceil(),
+        }
+    }
+}
+
+impl Trace {
+    /// Trim the trace to the window `[start, end)`
+    ///
+    /// Returns None if no samples fall within the window
+    ///
+    /// ```
+    /// # use miniseed::{ms_record, str_to_utc, Trace};
+    /// # use miniseed::cut::Boundary;
+    /// let rec = ms_record::read("tests/sample.miniseed");
+    /// let tr = Trace::from_record(&rec).unwrap();
+    /// let t0 = str_to_utc("2016-10-30T18:02:59").unwrap();
+    /// let t1 = str_to_utc("2016-10-30T18:03:00").unwrap();
+    /// let cut = tr.trim(&t0, &t1, Boundary::Inclusive).unwrap();
+    /// assert_eq!(cut.npts(), 100);
+    /// assert_eq!(cut.start.to_string(), "2016-10-30 18:02:59 UTC");
+    /// ```
+    pub fn trim(
+        &self,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        boundary: Boundary,
+    ) -> Option<Trace> {
+        let n = self.npts() as f64;
+        let offset = |t: &DateTime<Utc>| {
+            let dt = t.signed_duration_since(self.start);
+            dt.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6 * self.samprate
+        };
+        let i0 = boundary.index(offset(start)).max(0.0).min(n);
+        let i1 = boundary.index(offset(end)).max(0.0).min(n);
+        if i1 <= i0 {
+            return None;
+        }
+        let (i0, i1) = (i0 as usize, i1 as usize);
+        let mut tr = self.clone();
+        tr.start = self.time_of(i0);
+        tr.data = self.data.slice(i0, i1);
+        Some(tr)
+    }
+}
+
+/// Cut records to the window `[start, end)` and return the trimmed traces
+///
+/// Records are merged into contiguous traces before trimming, see
+/// Trace::from_records()
+pub fn cut(
+    records: &[ms_record],
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+    boundary: Boundary,
+) -> Vec<Trace> {
+    Trace::from_records(records)
+        .iter()
+        .filter_map(|tr| tr.trim(start, end, boundary))
+        .collect()
+}
+
+/// Cut records to the window `[start, end)` and repack the trimmed traces
+///
+/// The first and last samples of the returned records lie within the
+/// window as determined by `boundary`.  Records are packed with length
+/// `reclen`, and `encoding` or the default encoding for the sample type
+/// if None.
+pub fn cut_records(
+    records: &[ms_record],
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+    boundary: Boundary,
+    reclen: usize,
+    encoding: Option<u32>,
+) -> Vec<ms_record> {
+    cut(records, start, end, boundary)
+        .iter()
+        .flat_map(|tr| {
+            let enc = encoding.unwrap_or_else(|| tr.data.default_encoding());
+            tr.pack(reclen, enc)
+        })
+        .collect()
+}
@@ -0,0 +1,312 @@
+//! DataLink client
+//!
+//! A client writes packets, with optional acknowledgement, or positions
+//! its read position in the server's ring, selects streams with MATCH
+//! and REJECT patterns and then reads packets one at a time with READ
+//! or continuously with STREAM.
+//!
+//! ```no_run
+//! use miniseed::datalink::DataLinkClient;
+//!
+//! let mut client = DataLinkClient::connect("localhost:16000", "example").unwrap();
+//! client.match_streams("^IU_ANMO_.*/MSEED$").unwrap();
+//! client.position_earliest().unwrap();
+//! client.stream().unwrap();
+//! for rec in client.take(10) {
+//!     println!("{}", rec);
+//! }
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::io;
+use std::net::TcpStream;
+
+use super::{read_frame, stream_id, write_frame, Frame};
+use {hptime_to_utc, ms_record, utc_to_hptime};
+
+/// Packet read from a DataLink server
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataLinkPacket {
+    /// Stream id, e.g. `IU_ANMO_00_BHZ/MSEED`
+    pub stream_id: String,
+    /// Packet id assigned by the server
+    pub id: u64,
+    /// Time the packet was added to the ring
+    pub time: DateTime<Utc>,
+    /// Time of the first sample
+    pub start: DateTime<Utc>,
+    /// Time of the last sample
+    pub end: DateTime<Utc>,
+    pub data: Vec<u8>,
+}
+
+impl DataLinkPacket {
+    /// Parse a `PACKET` response
+    fn from_frame(frame: &Frame) -> io::Result<DataLinkPacket> {
+        let v = frame.fields();
+        let bad = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid DataLink packet header: {}", frame.header),
+            )
+        };
+        if v.len() != 7 || v[0] != "PACKET" {
+            return Err(bad());
+        }
+        let hp = |s: &str| s.parse::<i64>().map(hptime_to_utc).map_err(|_| bad());
+        Ok(DataLinkPacket {
+            stream_id: v[1].to_string(),
+            id: v[2].parse().map_err(|_| bad())?,
+            time: hp(v[3])?,
+            start: hp(v[4])?,
+            end: hp(v[5])?,
+            data: frame.data.clone(),
+        })
+    }
+    /// Return true if the packet is a miniSEED record
+    pub fn is_mseed(&self) -> bool {
+        self.stream_id.ends_with("/MSEED")
+    }
+    /// Return the miniSEED record in the packet, None for other packets
+    /// and invalid records
+    pub fn record(&self) -> Option<ms_record> {
+        if self.is_mseed() {
+            ms_record::try_parse(&self.data).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Return the value and message of an `OK` response
+fn response(frame: &Frame) -> io::Result<(i64, String)> {
+    let v = frame.fields();
+    let value = v.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+    match frame.command() {
+        "OK" => Ok((value, frame.text())),
+        "ERROR" => Err(io::Error::new(io::ErrorKind::InvalidData, frame.text())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected DataLink response: {}", frame.header),
+        )),
+    }
+}
+
+/// DataLink client
+pub struct DataLinkClient {
+    stream: TcpStream,
+    client_id: String,
+    server_id: String,
+    streaming: bool,
+}
+
+impl DataLinkClient {
+    /// Connect to the server at `addr`, `host:port`, and identify as
+    /// `client_id`
+    pub fn connect(addr: &str, client_id: &str) -> io::Result<DataLinkClient> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let mut c = DataLinkClient {
+            stream,
+            client_id: client_id.to_string(),
+            server_id: String::new(),
+            streaming: false,
+        };
+        let resp = c.request(&Frame::new(&format!("ID {}", client_id), &[]))?;
+        if resp.command() != "ID" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected DataLink response: {}", resp.header),
+            ));
+        }
+        c.server_id = resp.header[2..].trim().to_string();
+        Ok(c)
+    }
+    /// Return the server identification and capabilities
+    pub fn server_id(&self) -> &str {
+        &self.server_id
+    }
+    /// Return the maximum packet size of the server
+    pub fn packet_size(&self) -> Option<usize> {
+        self.capabilities()
+            .find(|c| c.starts_with("PACKETSIZE:"))
+            .and_then(|c| c[11..].parse().ok())
+    }
+    /// Return true if the server accepts WRITE
+    pub fn can_write(&self) -> bool {
+        self.capabilities().any(|c| c == "WRITE")
+    }
+    fn capabilities(&self) -> std::str::SplitWhitespace<'_> {
+        match self.server_id.find("::") {
+            Some(i) => self.server_id[i + 2..].split_whitespace(),
+            None => "".split_whitespace(),
+        }
+    }
+    // Send a frame and read the response
+    fn request(&mut self, frame: &Frame) -> io::Result<Frame> {
+        if self.streaming {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DataLink client is streaming",
+            ));
+        }
+        write_frame(&mut self.stream, frame)?;
+        read_frame(&mut self.stream)
+    }
+    // Send a POSITION command and return the packet id positioned to
+    fn position(&mut self, args: &str) -> io::Result<u64> {
+        let resp = self.request(&Frame::new(&format!("POSITION {}", args), &[]))?;
+        response(&resp).map(|(id, _)| id as u64)
+    }
+    /// Position to read the packets after packet `id`
+    ///
+    /// If `time` is given it must match the time the packet was added
+    pub fn position_set(&mut self, id: u64, time: Option<&DateTime<Utc>>) -> io::Result<u64> {
+        let t = time.map_or(-1, utc_to_hptime);
+        self.position(&format!("SET {} {}", id, t))
+    }
+    /// Position to read from the earliest packet in the ring
+    pub fn position_earliest(&mut self) -> io::Result<u64> {
+        self.position("SET EARLIEST")
+    }
+    /// Position to read only packets added from now on
+    pub fn position_latest(&mut self) -> io::Result<u64> {
+        self.position("SET LATEST")
+    }
+    /// Position to read from the first packet starting at or after `t`
+    pub fn position_after(&mut self, t: &DateTime<Utc>) -> io::Result<u64> {
+        self.position(&format!("AFTER {}", utc_to_hptime(t)))
+    }
+    /// Only read streams whose id matches the regular expression
+    /// `pattern`, all streams if empty
+    ///
+    /// Returns the number of streams in the ring which match
+    pub fn match_streams(&mut self, pattern: &str) -> io::Result<u64> {
+        let frame = Frame::new(&format!("MATCH {}", pattern.len()), pattern.as_bytes());
+        response(&self.request(&frame)?).map(|(n, _)| n as u64)
+    }
+    /// Do not read streams whose id matches the regular expression
+    /// `pattern`, none if empty
+    ///
+    /// Returns the number of streams in the ring which match
+    pub fn reject_streams(&mut self, pattern: &str) -> io::Result<u64> {
+        let frame = Frame::new(&format!("REJECT {}", pattern.len()), pattern.as_bytes());
+        response(&self.request(&frame)?).map(|(n, _)| n as u64)
+    }
+    /// Read the packet with id `id`
+    pub fn read(&mut self, id: u64) -> io::Result<DataLinkPacket> {
+        let resp = self.request(&Frame::new(&format!("READ {}", id), &[]))?;
+        if resp.command() == "PACKET" {
+            return DataLinkPacket::from_frame(&resp);
+        }
+        response(&resp)?;
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected DataLink response: {}", resp.header),
+        ))
+    }
+    /// Write a record, returning the packet id if `ack` is requested
+    pub fn write(&mut self, rec: &ms_record, ack: bool) -> io::Result<Option<u64>> {
+        self.write_packet(
+            &stream_id(rec),
+            &rec.start(),
+            &rec.end(),
+            rec.raw_bytes(),
+            ack,
+        )
+    }
+    /// Write a packet, returning the packet id if `ack` is requested
+    pub fn write_packet(
+        &mut self,
+        stream_id: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        data: &[u8],
+        ack: bool,
+    ) -> io::Result<Option<u64>> {
+        let header = format!(
+            "WRITE {} {} {} {} {}",
+            stream_id,
+            utc_to_hptime(start),
+            utc_to_hptime(end),
+            if ack { "A" } else { "N" },
+            data.len()
+        );
+        let frame = Frame::new(&header, data);
+        if !ack {
+            write_frame(&mut self.stream, &frame)?;
+            return Ok(None);
+        }
+        response(&self.request(&frame)?).map(|(id, _)| Some(id as u64))
+    }
+    /// Request server information, e.g. `STATUS` or `STREAMS`, and
+    /// return the XML response
+    pub fn info(&mut self, kind: &str) -> io::Result<String> {
+        let resp = self.request(&Frame::new(&format!("INFO {}", kind), &[]))?;
+        if resp.command() == "INFO" {
+            return Ok(resp.text());
+        }
+        response(&resp)?;
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected DataLink response: {}", resp.header),
+        ))
+    }
+    /// Start streaming packets from the read position
+    ///
+    /// Only next_packet(), keepalive() and end_stream() may be used while
+    /// streaming
+    pub fn stream(&mut self) -> io::Result<()> {
+        write_frame(&mut self.stream, &Frame::new("STREAM", &[]))?;
+        self.streaming = true;
+        Ok(())
+    }
+    /// Stop streaming
+    ///
+    /// Packets already sent are still returned by next_packet(), which
+    /// returns None once the server confirms the end of the stream
+    pub fn end_stream(&mut self) -> io::Result<()> {
+        write_frame(&mut self.stream, &Frame::new("ENDSTREAM", &[]))
+    }
+    /// Send a keepalive while streaming
+    pub fn keepalive(&mut self) -> io::Result<()> {
+        let frame = Frame::new(&format!("ID {}", self.client_id), &[]);
+        write_frame(&mut self.stream, &frame)
+    }
+    /// Return the next packet while streaming, blocking until one arrives
+    ///
+    /// Returns None when not streaming
+    pub fn next_packet(&mut self) -> io::Result<Option<DataLinkPacket>> {
+        while self.streaming {
+            let frame = read_frame(&mut self.stream)?;
+            match frame.command() {
+                "PACKET" => return DataLinkPacket::from_frame(&frame).map(Some),
+                "ENDSTREAM" => self.streaming = false,
+                "ID" => {}
+                _ => {
+                    response(&frame)?;
+                }
+            }
+        }
+        Ok(None)
+    }
+    /// Return the next miniSEED record while streaming, other packets
+    /// are skipped and invalid records give an `InvalidData` error
+    pub fn next_record(&mut self) -> io::Result<Option<ms_record>> {
+        while let Some(pkt) = self.next_packet()? {
+            if pkt.is_mseed() {
+                return ms_record::try_parse(&pkt.data).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for DataLinkClient {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        self.next_record().ok().and_then(|r| r)
+    }
+}
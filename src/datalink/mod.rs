@@ -0,0 +1,247 @@
+//! DataLink protocol support
+//!
+//! DataLink is the protocol of ringserver for writing packets to and
+//! reading packets from a ring buffer.  Each message is a frame of `DL`,
+//! a one byte header length, the ASCII header and, for some commands, a
+//! data payload whose length is the last field of the header.  miniSEED
+//! records are packets with the stream id `NET_STA_LOC_CHA/MSEED`.
+//!
+//! ```
+//! use miniseed::datalink::{DataLinkClient, DataLinkServer};
+//! use miniseed::ms_input;
+//!
+//! let server = DataLinkServer::new();
+//! let addr = server.spawn("127.0.0.1:0").unwrap();
+//!
+//! let mut client = DataLinkClient::connect(&addr.to_string(), "example").unwrap();
+//! for rec in ms_input::open("tests/multiple.seed").take(3) {
+//!     client.write(&rec, true).unwrap();
+//! }
+//! let pkt = client.read(1).unwrap();
+//! assert_eq!(pkt.stream_id, "IU_ANMO_00_BHZ/MSEED");
+//! assert_eq!(server.len(), 3);
+//! ```
+
+use std::io;
+use std::io::{Read, Write};
+
+use ms_record;
+
+pub mod client;
+mod regex;
+pub mod server;
+
+pub use self::client::{DataLinkClient, DataLinkPacket};
+pub use self::regex::Regex;
+pub use self::server::DataLinkServer;
+
+/// Default DataLink port
+pub const DEFAULT_PORT: u16 = 16000;
+
+/// Largest payload read from a peer by default, 16 MiB
+pub const MAX_PAYLOAD: usize = 1 << 24;
+
+/// Return the stream id of a record, `NET_STA_LOC_CHA/MSEED`
+///
+/// ```
+/// # use miniseed::ms_record;
+/// # use miniseed::datalink::stream_id;
+/// let rec = ms_record::read("tests/sample.miniseed");
+/// assert_eq!(stream_id(&rec), "PN_PPNAF_00_HHZ/MSEED");
+/// ```
+pub fn stream_id(rec: &ms_record) -> String {
+    format!("{}/MSEED", rec.id())
+}
+
+/// DataLink message, a header and a data payload
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub header: String,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Create a frame
+    pub fn new(header: &str, data: &[u8]) -> Frame {
+        Frame {
+            header: header.to_string(),
+            data: data.to_vec(),
+        }
+    }
+    /// Return the fields of the header
+    pub fn fields(&self) -> Vec<&str> {
+        self.header.split_whitespace().collect()
+    }
+    /// Return the command or response, the first field of the header
+    pub fn command(&self) -> &str {
+        self.header.split_whitespace().next().unwrap_or("")
+    }
+    /// Return the data payload as text
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+    /// Return the encoded frame
+    ///
+    /// Fails if the header is longer than 255 bytes
+    ///
+    /// ```
+    /// # use miniseed::datalink::Frame;
+    /// let f = Frame::new("OK 1 2", b"hi");
+    /// assert_eq!(f.encode().unwrap(), b"DL\x06OK 1 2hi");
+    /// assert_eq!(Frame::parse(b"DL\x06OK 1 2hi").unwrap(), Some((f, 11)));
+    /// ```
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        let n = self.header.len();
+        if n > 255 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "DataLink header longer than 255 bytes",
+            ));
+        }
+        let mut buf = b"DL".to_vec();
+        buf.push(n as u8);
+        buf.extend_from_slice(self.header.as_bytes());
+        buf.extend_from_slice(&self.data);
+        Ok(buf)
+    }
+    /// Parse a frame at the start of `buf`
+    ///
+    /// Returns the frame and its length, None if the frame is incomplete,
+    /// and an error if `buf` does not begin with `DL` or the payload is
+    /// larger than MAX_PAYLOAD
+    pub fn parse(buf: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+        parse_frame(buf, MAX_PAYLOAD)
+    }
+}
+
+// Parse a frame with a payload of at most `max` bytes, checking the size
+// as soon as the header is complete
+fn parse_frame(buf: &[u8], max: usize) -> io::Result<Option<(Frame, usize)>> {
+    if buf.len() < 3 {
+        return Ok(None);
+    }
+    if &buf[..2] != b"DL" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid DataLink frame signature",
+        ));
+    }
+    let n = 3 + buf[2] as usize;
+    if buf.len() < n {
+        return Ok(None);
+    }
+    let header = String::from_utf8_lossy(&buf[3..n]).into_owned();
+    let size = payload_len(&header, max)?;
+    if buf.len() < n + size {
+        return Ok(None);
+    }
+    let frame = Frame {
+        header,
+        data: buf[n..n + size].to_vec(),
+    };
+    Ok(Some((frame, n + size)))
+}
+
+/// Return the payload length given in a header, which must be at most
+/// `max`
+fn payload_len(header: &str, max: usize) -> io::Result<usize> {
+    let v: Vec<&str> = header.split_whitespace().collect();
+    let has_size = match v.first() {
+        Some(&"WRITE") | Some(&"PACKET") | Some(&"OK") | Some(&"ERROR") | Some(&"MATCH")
+        | Some(&"REJECT") => true,
+        // Responses to INFO requests have a size, requests do not
+        Some(&"INFO") => v.len() == 3 && v[2].parse::<usize>().is_ok(),
+        _ => false,
+    };
+    if !has_size {
+        return Ok(0);
+    }
+    let size: usize = v.last().and_then(|s| s.parse().ok()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid DataLink payload size: {}", header),
+        )
+    })?;
+    if size > max {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("DataLink payload of {} bytes is larger than {}", size, max),
+        ));
+    }
+    Ok(size)
+}
+
+/// Incremental framing of DataLink messages from a byte stream
+#[derive(Debug, Clone)]
+pub struct FrameReader {
+    buf: Vec<u8>,
+    max_payload: usize,
+}
+
+impl Default for FrameReader {
+    fn default() -> FrameReader {
+        FrameReader::new()
+    }
+}
+
+impl FrameReader {
+    /// Create an empty reader
+    pub fn new() -> FrameReader {
+        FrameReader {
+            buf: vec![],
+            max_payload: MAX_PAYLOAD,
+        }
+    }
+    /// Fail on frames with a payload larger than `max` bytes, MAX_PAYLOAD
+    /// by default
+    pub fn with_max_payload(mut self, max: usize) -> FrameReader {
+        self.max_payload = max;
+        self
+    }
+    /// Add bytes received
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+    /// Return the bytes received but not yet read
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+    /// Return the next complete frame
+    ///
+    /// The payload size is checked before the payload is buffered
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        match parse_frame(&self.buf, self.max_payload)? {
+            Some((frame, n)) => {
+                self.buf.drain(..n);
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read a frame, blocking until it is complete
+///
+/// Fails if the payload is larger than MAX_PAYLOAD
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Frame> {
+    let mut buf = vec![0u8; 3];
+    r.read_exact(&mut buf)?;
+    if &buf[..2] != b"DL" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid DataLink frame signature",
+        ));
+    }
+    let mut header = vec![0u8; buf[2] as usize];
+    r.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header).into_owned();
+    let mut data = vec![0u8; payload_len(&header, MAX_PAYLOAD)?];
+    r.read_exact(&mut data)?;
+    Ok(Frame { header, data })
+}
+
+/// Write a frame
+pub fn write_frame<W: Write>(w: &mut W, frame: &Frame) -> io::Result<()> {
+    w.write_all(&frame.encode()?)?;
+    w.flush()
+}
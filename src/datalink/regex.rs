@@ -0,0 +1,237 @@
+//! Stream id patterns
+//!
+//! DataLink servers select streams with POSIX extended regular
+//! expressions.  `Regex` supports the subset used for stream ids:
+//! literals, `.`, bracket expressions `[...]` and `[^...]` with ranges,
+//! the quantifiers `*`, `+`, `?` and `{m,n}`, groups `(...)`,
+//! alternation `|`, the anchors `^` and `$`, and `\` escapes.  Counts in
+//! `{m,n}` are limited to 255, the POSIX `RE_DUP_MAX`.
+
+use std::collections::BTreeSet;
+use std::io;
+
+// Largest count allowed in `{m,n}`
+const MAX_REPEAT: usize = 255;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+    Start,
+    End,
+}
+
+/// Compiled regular expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regex {
+    pattern: String,
+    alts: Vec<Vec<Node>>,
+}
+
+impl Regex {
+    /// Compile a pattern
+    ///
+    /// ```
+    /// # use miniseed::datalink::Regex;
+    /// let re = Regex::new("^(IU|II)_ANMO_.*/MSEED$").unwrap();
+    /// assert!(re.is_match("IU_ANMO_00_BHZ/MSEED"));
+    /// assert!(!re.is_match("GE_ANMO_00_BHZ/MSEED"));
+    /// assert!(Regex::new("BH[ZNE]").unwrap().is_match("IU_ANMO_00_BHZ/MSEED"));
+    /// assert!(Regex::new("(IU").is_err());
+    /// ```
+    pub fn new(pattern: &str) -> io::Result<Regex> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut pos = 0;
+        let alts = parse_alts(&chars, &mut pos)?;
+        if pos != chars.len() {
+            return Err(bad(pattern, "unmatched )"));
+        }
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            alts,
+        })
+    }
+    /// Return the pattern
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+    /// Return true if the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let t: Vec<char> = text.chars().collect();
+        (0..=t.len()).any(|i| !alts_ends(&self.alts, &t, i).is_empty())
+    }
+}
+
+fn bad(pattern: &str, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid pattern: {}: {}", msg, pattern),
+    )
+}
+
+fn parse_alts(p: &[char], pos: &mut usize) -> io::Result<Vec<Vec<Node>>> {
+    let pattern: String = p.iter().collect();
+    let mut alts = vec![vec![]];
+    while *pos < p.len() {
+        let c = p[*pos];
+        *pos += 1;
+        let node = match c {
+            '|' => {
+                alts.push(vec![]);
+                continue;
+            }
+            ')' => {
+                *pos -= 1;
+                break;
+            }
+            '(' => {
+                let g = parse_alts(p, pos)?;
+                if *pos >= p.len() || p[*pos] != ')' {
+                    return Err(bad(&pattern, "unmatched ("));
+                }
+                *pos += 1;
+                Node::Group(g)
+            }
+            '[' => parse_class(p, pos).ok_or_else(|| bad(&pattern, "unmatched ["))?,
+            '\\' => {
+                let c = *p.get(*pos).ok_or_else(|| bad(&pattern, "trailing \\"))?;
+                *pos += 1;
+                Node::Char(c)
+            }
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '*' | '+' | '?' | '{' => return Err(bad(&pattern, "nothing to repeat")),
+            c => Node::Char(c),
+        };
+        let node = parse_repeat(p, pos, node).ok_or_else(|| bad(&pattern, "invalid repeat"))?;
+        alts.last_mut().unwrap().push(node);
+    }
+    Ok(alts)
+}
+
+fn parse_class(p: &[char], pos: &mut usize) -> Option<Node> {
+    let neg = p.get(*pos) == Some(&'^');
+    if neg {
+        *pos += 1;
+    }
+    let mut ranges = vec![];
+    let mut first = true;
+    loop {
+        let c = *p.get(*pos)?;
+        *pos += 1;
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        if p.get(*pos) == Some(&'-') && matches!(p.get(*pos + 1), Some(&e) if e != ']') {
+            ranges.push((c, p[*pos + 1]));
+            *pos += 2;
+        } else {
+            ranges.push((c, c));
+        }
+    }
+    Some(Node::Class(ranges, neg))
+}
+
+fn parse_repeat(p: &[char], pos: &mut usize, node: Node) -> Option<Node> {
+    let (min, max) = match p.get(*pos) {
+        Some('*') => (0, None),
+        Some('+') => (1, None),
+        Some('?') => (0, Some(1)),
+        Some('{') => {
+            let end = p[*pos..].iter().position(|&c| c == '}')? + *pos;
+            let s: String = p[*pos + 1..end].iter().collect();
+            let v: Vec<&str> = s.split(',').collect();
+            let min: usize = v[0].trim().parse().ok()?;
+            let max = match v.len() {
+                1 => Some(min),
+                2 if v[1].trim().is_empty() => None,
+                2 => Some(v[1].trim().parse().ok()?),
+                _ => return None,
+            };
+            if min > MAX_REPEAT || matches!(max, Some(m) if m > MAX_REPEAT || m < min) {
+                return None;
+            }
+            *pos = end;
+            (min, max)
+        }
+        _ => return Some(node),
+    };
+    *pos += 1;
+    parse_repeat(p, pos, Node::Repeat(Box::new(node), min, max))
+}
+
+// Positions in `t` where a match of the alternatives starting at `i` ends
+fn alts_ends(alts: &[Vec<Node>], t: &[char], i: usize) -> BTreeSet<usize> {
+    let mut out = BTreeSet::new();
+    for seq in alts {
+        let mut cur: BTreeSet<usize> = [i].iter().cloned().collect();
+        for node in seq {
+            cur = cur.iter().flat_map(|&j| node_ends(node, t, j)).collect();
+            if cur.is_empty() {
+                break;
+            }
+        }
+        out.extend(cur);
+    }
+    out
+}
+
+fn node_ends(node: &Node, t: &[char], i: usize) -> BTreeSet<usize> {
+    let mut out = BTreeSet::new();
+    match *node {
+        Node::Char(c) => {
+            if t.get(i) == Some(&c) {
+                out.insert(i + 1);
+            }
+        }
+        Node::Any => {
+            if i < t.len() {
+                out.insert(i + 1);
+            }
+        }
+        Node::Class(ref ranges, neg) => {
+            if let Some(&c) = t.get(i) {
+                if ranges.iter().any(|&(a, b)| a <= c && c <= b) != neg {
+                    out.insert(i + 1);
+                }
+            }
+        }
+        Node::Group(ref alts) => out = alts_ends(alts, t, i),
+        Node::Repeat(ref node, min, max) => {
+            let mut cur: BTreeSet<usize> = [i].iter().cloned().collect();
+            let mut seen = BTreeSet::new();
+            let mut n = 0;
+            loop {
+                if n >= min {
+                    out.extend(cur.iter().cloned());
+                }
+                if matches!(max, Some(m) if n >= m) || cur.is_empty() {
+                    break;
+                }
+                // Stop once repetitions reach no new positions
+                if n >= min && cur.iter().all(|j| seen.contains(j)) {
+                    break;
+                }
+                seen.extend(cur.iter().cloned());
+                cur = cur.iter().flat_map(|&j| node_ends(node, t, j)).collect();
+                n += 1;
+            }
+        }
+        Node::Start => {
+            if i == 0 {
+                out.insert(i);
+            }
+        }
+        Node::End => {
+            if i == t.len() {
+                out.insert(i);
+            }
+        }
+    }
+    out
+}
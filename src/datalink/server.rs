@@ -0,0 +1,447 @@
+//! Minimal DataLink server
+//!
+//! Packets written by clients, or inserted directly, are kept in an in
+//! memory ring of bounded size with increasing packet ids starting at 1.
+//! Clients read packets with READ or STREAM, positioned with POSITION
+//! and selected with MATCH and REJECT.  The server is intended for local
+//! testing and small pipelines rather than as a replacement for
+//! ringserver.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{stream_id, write_frame, Frame, FrameReader, Regex};
+use header::record_length;
+use {hptime_to_utc, ms_record, utc_to_hptime};
+
+/// Interval between checks for commands and new packets
+const TICK: Duration = Duration::from_millis(50);
+
+/// Packet in the ring, times are high precision times
+struct Stored {
+    id: u64,
+    stream_id: String,
+    time: i64,
+    start: i64,
+    end: i64,
+    data: Vec<u8>,
+}
+
+impl Stored {
+    fn frame(&self) -> Frame {
+        let header = format!(
+            "PACKET {} {} {} {} {} {}",
+            self.stream_id,
+            self.id,
+            self.time,
+            self.start,
+            self.end,
+            self.data.len()
+        );
+        Frame::new(&header, &self.data)
+    }
+}
+
+struct Ring {
+    packets: VecDeque<Stored>,
+    next_id: u64,
+    capacity: usize,
+}
+
+impl Ring {
+    fn get(&self, id: u64) -> Option<&Stored> {
+        let first = self.packets.front()?.id;
+        if id < first {
+            return None;
+        }
+        self.packets.get((id - first) as usize)
+    }
+    fn streams(&self) -> Vec<&str> {
+        let mut v: Vec<&str> = self.packets.iter().map(|p| p.stream_id.as_str()).collect();
+        v.sort();
+        v.dedup();
+        v
+    }
+}
+
+/// In-process DataLink server
+///
+/// Clones share the same ring
+#[derive(Clone)]
+pub struct DataLinkServer {
+    ring: Arc<Mutex<Ring>>,
+    packet_size: usize,
+    started: DateTime<Utc>,
+}
+
+impl Default for DataLinkServer {
+    fn default() -> DataLinkServer {
+        DataLinkServer::new()
+    }
+}
+
+impl DataLinkServer {
+    /// Create a server holding up to 10000 packets of up to 512 bytes
+    pub fn new() -> DataLinkServer {
+        DataLinkServer {
+            ring: Arc::new(Mutex::new(Ring {
+                packets: VecDeque::new(),
+                next_id: 1,
+                capacity: 10000,
+            })),
+            packet_size: 512,
+            started: Utc::now(),
+        }
+    }
+    /// Set the number of packets kept, the oldest are removed first
+    pub fn with_capacity(self, capacity: usize) -> DataLinkServer {
+        self.ring.lock().unwrap().capacity = capacity;
+        self
+    }
+    /// Set the maximum packet size in bytes
+    pub fn with_packet_size(mut self, packet_size: usize) -> DataLinkServer {
+        self.packet_size = packet_size;
+        self
+    }
+    /// Return the number of packets in the ring
+    pub fn len(&self) -> usize {
+        self.ring.lock().unwrap().packets.len()
+    }
+    /// Return true if the ring is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Add a packet to the ring and return its id
+    pub fn insert(
+        &self,
+        stream_id: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        data: &[u8],
+    ) -> u64 {
+        self.insert_hp(stream_id, utc_to_hptime(start), utc_to_hptime(end), data)
+    }
+    /// Add a record to the ring and return its packet id
+    pub fn insert_record(&self, rec: &ms_record) -> u64 {
+        self.insert(&stream_id(rec), &rec.start(), &rec.end(), rec.raw_bytes())
+    }
+    fn insert_hp(&self, stream_id: &str, start: i64, end: i64, data: &[u8]) -> u64 {
+        let mut ring = self.ring.lock().unwrap();
+        let id = ring.next_id;
+        ring.next_id += 1;
+        ring.packets.push_back(Stored {
+            id,
+            stream_id: stream_id.to_string(),
+            time: utc_to_hptime(&Utc::now()),
+            start,
+            end,
+            data: data.to_vec(),
+        });
+        while ring.packets.len() > ring.capacity {
+            ring.packets.pop_front();
+        }
+        id
+    }
+    /// Accept connections, each served by a separate thread
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                let _ = Session::new(server, stream).and_then(|mut s| s.run());
+            });
+        }
+        Ok(())
+    }
+    /// Bind to `addr` and serve connections in a background thread
+    ///
+    /// Returns the address bound, useful with port 0
+    pub fn spawn(&self, addr: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || server.serve(listener));
+        Ok(local)
+    }
+    fn info(&self, kind: &str) -> Option<String> {
+        let ring = self.ring.lock().unwrap();
+        let fmt = "%Y-%m-%d %H:%M:%S%.6f";
+        let mut xml = String::from(
+            "<?xml version=\"1.0\"?>\n<DataLink Version=\"miniseed-rs\" ServerID=\"miniseed-rs\">\n",
+        );
+        match kind {
+            "STATUS" => {
+                xml += &format!(
+                    "<Status StartTime=\"{}\" PacketSize=\"{}\" MaximumPackets=\"{}\" TotalPackets=\"{}\" EarliestPacketID=\"{}\" LatestPacketID=\"{}\"/>\n",
+                    self.started.format(fmt),
+                    self.packet_size,
+                    ring.capacity,
+                    ring.packets.len(),
+                    ring.packets.front().map_or(0, |p| p.id),
+                    ring.packets.back().map_or(0, |p| p.id)
+                );
+            }
+            "STREAMS" => {
+                let streams = ring.streams();
+                xml += &format!("<StreamList TotalStreams=\"{}\">\n", streams.len());
+                for s in streams {
+                    let first = ring.packets.iter().find(|p| p.stream_id == s).unwrap();
+                    let last = ring
+                        .packets
+                        .iter()
+                        .rev()
+                        .find(|p| p.stream_id == s)
+                        .unwrap();
+                    xml += &format!(
+                        "<Stream Name=\"{}\" EarliestPacketID=\"{}\" EarliestPacketDataStartTime=\"{}\" LatestPacketID=\"{}\" LatestPacketDataEndTime=\"{}\"/>\n",
+                        s,
+                        first.id,
+                        hptime_to_utc(first.start).format(fmt),
+                        last.id,
+                        hptime_to_utc(last.end).format(fmt)
+                    );
+                }
+                xml += "</StreamList>\n";
+            }
+            _ => return None,
+        }
+        xml += "</DataLink>\n";
+        Some(xml)
+    }
+}
+
+/// Connection to a client
+struct Session {
+    server: DataLinkServer,
+    stream: TcpStream,
+    reader: FrameReader,
+    /// Id of the next packet to stream
+    next: u64,
+    matcher: Option<Regex>,
+    rejecter: Option<Regex>,
+    streaming: bool,
+}
+
+impl Session {
+    fn new(server: DataLinkServer, stream: TcpStream) -> io::Result<Session> {
+        stream.set_read_timeout(Some(TICK))?;
+        stream.set_nodelay(true)?;
+        let next = server.ring.lock().unwrap().next_id;
+        let reader = FrameReader::new().with_max_payload(server.packet_size);
+        Ok(Session {
+            server,
+            stream,
+            reader,
+            next,
+            matcher: None,
+            rejecter: None,
+            streaming: false,
+        })
+    }
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let mut tmp = [0u8; 4096];
+            match self.stream.read(&mut tmp) {
+                Ok(0) => return Ok(()),
+                Ok(n) => self.reader.feed(&tmp[..n]),
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e),
+            }
+            loop {
+                match self.reader.next_frame() {
+                    Ok(Some(frame)) => self.command(&frame)?,
+                    Ok(None) => break,
+                    Err(e) => {
+                        // The rest of the stream cannot be framed
+                        let _ = self.reply(false, 0, &e.to_string());
+                        return Err(e);
+                    }
+                }
+            }
+            if self.streaming {
+                self.pump()?;
+            }
+        }
+    }
+    fn send(&mut self, frame: &Frame) -> io::Result<()> {
+        write_frame(&mut self.stream, frame)
+    }
+    fn reply(&mut self, ok: bool, value: u64, msg: &str) -> io::Result<()> {
+        let header = format!(
+            "{} {} {}",
+            if ok { "OK" } else { "ERROR" },
+            value,
+            msg.len()
+        );
+        self.send(&Frame::new(&header, msg.as_bytes()))
+    }
+    fn wanted(&self, stream_id: &str) -> bool {
+        !matches!(self.matcher, Some(ref m) if !m.is_match(stream_id))
+            && !matches!(self.rejecter, Some(ref r) if r.is_match(stream_id))
+    }
+    fn command(&mut self, frame: &Frame) -> io::Result<()> {
+        let v = frame.fields();
+        match frame.command() {
+            "ID" => {
+                let id = format!(
+                    "ID DataLink miniseed-rs :: DLPROTO:1.0 PACKETSIZE:{} WRITE",
+                    self.server.packet_size
+                );
+                self.send(&Frame::new(&id, &[]))
+            }
+            "ENDSTREAM" => {
+                self.streaming = false;
+                self.send(&Frame::new("ENDSTREAM", &[]))
+            }
+            _ if self.streaming => Ok(()),
+            "STREAM" => {
+                self.streaming = true;
+                Ok(())
+            }
+            "POSITION" => self.position(&v),
+            "MATCH" | "REJECT" => {
+                let text = frame.text();
+                let re = if text.is_empty() {
+                    None
+                } else {
+                    match Regex::new(&text) {
+                        Ok(re) => Some(re),
+                        Err(e) => return self.reply(false, 0, &e.to_string()),
+                    }
+                };
+                let n = match re {
+                    Some(ref re) => {
+                        let ring = self.server.ring.lock().unwrap();
+                        ring.streams().iter().filter(|s| re.is_match(s)).count()
+                    }
+                    None => 0,
+                };
+                if frame.command() == "MATCH" {
+                    self.matcher = re;
+                } else {
+                    self.rejecter = re;
+                }
+                self.reply(true, n as u64, &format!("{} streams selected", n))
+            }
+            "READ" => {
+                let id = v.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+                let pkt = self.server.ring.lock().unwrap().get(id).map(|p| p.frame());
+                match pkt {
+                    Some(f) => self.send(&f),
+                    None => self.reply(false, 0, "Packet not found"),
+                }
+            }
+            "WRITE" => self.write(frame),
+            "INFO" => {
+                let kind = v.get(1).cloned().unwrap_or("");
+                match self.server.info(kind) {
+                    Some(xml) => {
+                        let header = format!("INFO {} {}", kind, xml.len());
+                        self.send(&Frame::new(&header, xml.as_bytes()))
+                    }
+                    None => self.reply(false, 0, "Unsupported INFO type"),
+                }
+            }
+            _ => self.reply(false, 0, "Unrecognized command"),
+        }
+    }
+    fn position(&mut self, v: &[&str]) -> io::Result<()> {
+        let result = {
+            let ring = self.server.ring.lock().unwrap();
+            match (v.get(1).cloned(), v.get(2).cloned()) {
+                (Some("SET"), Some("EARLIEST")) => {
+                    let id = ring.packets.front().map_or(0, |p| p.id);
+                    Ok((id, ring.packets.front().map_or(ring.next_id, |p| p.id)))
+                }
+                (Some("SET"), Some("LATEST")) => {
+                    let id = ring.packets.back().map_or(0, |p| p.id);
+                    Ok((id, ring.next_id))
+                }
+                (Some("SET"), Some(id)) => {
+                    let time = v.get(3).and_then(|s| s.parse::<i64>().ok()).unwrap_or(-1);
+                    match id.parse().ok().and_then(|id| ring.get(id)) {
+                        Some(p) if time == -1 || time == p.time => Ok((p.id, p.id + 1)),
+                        Some(_) => Err("Packet time does not match"),
+                        None => Err("Packet not found"),
+                    }
+                }
+                (Some("AFTER"), Some(t)) => {
+                    let t: i64 = t.parse().unwrap_or(i64::MAX);
+                    match ring.packets.iter().find(|p| p.start >= t) {
+                        Some(p) => Ok((p.id, p.id)),
+                        None => Err("No packet found"),
+                    }
+                }
+                _ => Err("Unrecognized POSITION command"),
+            }
+        };
+        match result {
+            Ok((id, next)) => {
+                self.next = next;
+                self.reply(true, id, &format!("Positioned to packet ID {}", id))
+            }
+            Err(msg) => self.reply(false, 0, msg),
+        }
+    }
+    fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        let v = frame.fields();
+        if v.len() != 6 {
+            return self.reply(false, 0, "Invalid WRITE command");
+        }
+        let ack = v[4].contains('A');
+        let start = v[2].parse::<i64>();
+        let end = v[3].parse::<i64>();
+        let err = if start.is_err() || end.is_err() {
+            Some("Invalid packet times")
+        } else if v[1].ends_with("/MSEED")
+            && record_length(&frame.data, true) != Some(frame.data.len())
+        {
+            Some("Packet is not a miniSEED record")
+        } else {
+            None
+        };
+        if let Some(msg) = err {
+            return if ack {
+                self.reply(false, 0, msg)
+            } else {
+                Ok(())
+            };
+        }
+        let id = self
+            .server
+            .insert_hp(v[1], start.unwrap(), end.unwrap(), &frame.data);
+        if ack {
+            self.reply(true, id, "Packet accepted")?;
+        }
+        Ok(())
+    }
+    // Send the packets added since the last call
+    fn pump(&mut self) -> io::Result<()> {
+        let frames: Vec<Frame> = {
+            let ring = self.server.ring.lock().unwrap();
+            let first = ring.packets.front().map_or(ring.next_id, |p| p.id);
+            let next = std::cmp::max(self.next, first);
+            self.next = ring.next_id;
+            ring.packets
+                .iter()
+                .skip((next - first) as usize)
+                .filter(|p| self.wanted(&p.stream_id))
+                .map(|p| p.frame())
+                .collect()
+        };
+        for f in &frames {
+            self.send(f)?;
+        }
+        Ok(())
+    }
+}
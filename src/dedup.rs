@@ -0,0 +1,310 @@
+//! Sorting and deduplication of records, similar to dataselect
+//!
+//! Records are sorted by id and time and exact duplicates are dropped.
+//! Where records of the same channel overlap, the record with the highest
+//! priority is kept, either the best data quality (`Q > D > R > M`) or
+//! the longest record.  Lower priority records which are completely
+//! overlapped are removed, and partially overlapped records may
+//! optionally be trimmed to the samples not already covered.
+//!
+//! ```
+//! use miniseed::ms_input;
+//! use miniseed::dedup::Dedup;
+//!
+//! let mut recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+//! recs.extend(ms_input::open("tests/multiple.seed").take(10));
+//! let (recs, stats) = Dedup::new().process(recs);
+//! assert_eq!(recs.len(), 1243);
+//! assert_eq!(stats.duplicates, 10);
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use std::io;
+use std::path::Path;
+
+use {ms_input, ms_output, ms_record, Trace};
+
+/// Record priority used to resolve overlaps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    /// Prefer the best data quality, then the longest record
+    Quality,
+    /// Prefer the longest record, then the best data quality
+    Longest,
+}
+
+/// Return the rank of a data quality code, higher is better
+///
+/// `Q > D > R > M`, unknown codes rank lowest
+pub fn quality_rank(q: char) -> u8 {
+    match q {
+        'Q' => 4,
+        'D' => 3,
+        'R' => 2,
+        'M' => 1,
+        _ => 0,
+    }
+}
+
+/// Counts of records handled by Dedup
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DedupStats {
+    /// Records read
+    pub read: usize,
+    /// Exact duplicates removed
+    pub duplicates: usize,
+    /// Records removed as completely overlapped
+    pub pruned: usize,
+    /// Records trimmed to the samples not overlapped
+    pub trimmed: usize,
+    /// Records returned or written
+    pub written: usize,
+}
+
+/// Header values of a record used for sorting and overlap checks
+struct Key {
+    id: String,
+    quality: char,
+    start: DateTime<Utc>,
+    end1: DateTime<Utc>,
+    samprate: f64,
+    npts: usize,
+}
+
+impl Key {
+    fn new(rec: &ms_record) -> Key {
+        Key {
+            id: rec.id(),
+            quality: rec.dataquality().chars().next().unwrap_or('D'),
+            start: rec.start(),
+            end1: rec.end1(),
+            samprate: rec.ptr().samprate,
+            npts: rec.npts(),
+        }
+    }
+    fn time_of(&self, i: usize) -> DateTime<Utc> {
+        self.start + Duration::microseconds((i as f64 / self.samprate * 1e6).round() as i64)
+    }
+    fn same_header(&self, other: &Key) -> bool {
+        self.id == other.id
+            && self.quality == other.quality
+            && self.start == other.start
+            && self.npts == other.npts
+            && self.samprate == other.samprate
+    }
+}
+
+/// Record sorting and deduplication
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    priority: Priority,
+    prune_samples: bool,
+}
+
+impl Default for Dedup {
+    fn default() -> Dedup {
+        Dedup::new()
+    }
+}
+
+impl Dedup {
+    /// Create with quality priority and record level pruning
+    pub fn new() -> Dedup {
+        Dedup {
+            priority: Priority::Quality,
+            prune_samples: false,
+        }
+    }
+    /// Set the priority used to resolve overlaps
+    pub fn with_priority(mut self, priority: Priority) -> Dedup {
+        self.priority = priority;
+        self
+    }
+    /// Trim partially overlapped records at the sample level
+    ///
+    /// Trimmed records are repacked with their original record length
+    /// and, if compatible, encoding
+    pub fn with_sample_prune(mut self, prune: bool) -> Dedup {
+        self.prune_samples = prune;
+        self
+    }
+    /// Sort and deduplicate records
+    ///
+    /// Records are returned sorted by id, start time and quality
+    pub fn process(&self, records: Vec<ms_record>) -> (Vec<ms_record>, DedupStats) {
+        let mut stats = DedupStats {
+            read: records.len(),
+            ..DedupStats::default()
+        };
+        let mut recs: Vec<(Key, ms_record)> =
+            records.into_iter().map(|r| (Key::new(&r), r)).collect();
+        recs.sort_by(|a, b| {
+            (&a.0.id, a.0.start, a.0.end1, a.0.quality).cmp(&(
+                &b.0.id,
+                b.0.start,
+                b.0.end1,
+                b.0.quality,
+            ))
+        });
+
+        let mut out: Vec<(Key, ms_record)> = vec![];
+        let mut group: Vec<(Key, ms_record)> = vec![];
+        for (key, rec) in recs {
+            // Records with the same start are sorted together, but not
+            // necessarily next to their duplicates
+            let duplicate = group
+                .iter()
+                .rev()
+                .take_while(|g| g.0.id == key.id && g.0.start == key.start)
+                .any(|g| g.0.same_header(&key) && same_data(&g.1, &rec));
+            if duplicate {
+                stats.duplicates += 1;
+                continue;
+            }
+            if let Some(last) = group.last() {
+                if last.0.id != key.id {
+                    let g = std::mem::take(&mut group);
+                    out.extend(self.resolve(g, &mut stats));
+                }
+            }
+            group.push((key, rec));
+        }
+        out.extend(self.resolve(group, &mut stats));
+        out.sort_by(|a, b| {
+            (&a.0.id, a.0.start, a.0.quality).cmp(&(&b.0.id, b.0.start, b.0.quality))
+        });
+        stats.written = out.len();
+        (out.into_iter().map(|(_, r)| r).collect(), stats)
+    }
+    /// Sort and deduplicate the records of a set of files and write the
+    /// result to `output`
+    ///
+    /// Records which are not trimmed are written unchanged
+    pub fn files<S: AsRef<Path>, P: AsRef<Path>>(
+        &self,
+        inputs: &[S],
+        output: P,
+    ) -> io::Result<DedupStats> {
+        let recs: Vec<ms_record> = inputs.iter().flat_map(ms_input::open).collect();
+        let (recs, stats) = self.process(recs);
+        let mut out = ms_output::open(output)?.with_passthrough(true);
+        for rec in &recs {
+            out.write(rec)?;
+        }
+        out.flush()?;
+        Ok(stats)
+    }
+    // Resolve overlaps between the records of a single id
+    fn resolve(
+        &self,
+        mut group: Vec<(Key, ms_record)>,
+        stats: &mut DedupStats,
+    ) -> Vec<(Key, ms_record)> {
+        let by_quality = |a: &Key, b: &Key| quality_rank(b.quality).cmp(&quality_rank(a.quality));
+        let by_length = |a: &Key, b: &Key| (b.end1 - b.start).cmp(&(a.end1 - a.start));
+        match self.priority {
+            Priority::Quality => {
+                group.sort_by(|a, b| by_quality(&a.0, &b.0).then(by_length(&a.0, &b.0)))
+            }
+            Priority::Longest => {
+                group.sort_by(|a, b| by_length(&a.0, &b.0).then(by_quality(&a.0, &b.0)))
+            }
+        }
+        let mut covered: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+        let mut out = vec![];
+        for (key, rec) in group {
+            let mask = overlapped(&key, &covered);
+            let n = mask.iter().filter(|&&m| m).count();
+            if n == 0 || (n < key.npts && !self.prune_samples) {
+                add_interval(&mut covered, key.start, key.end1);
+                out.push((key, rec));
+                continue;
+            }
+            if n == key.npts {
+                stats.pruned += 1;
+                continue;
+            }
+            let tr = match Trace::from_record(&rec) {
+                Some(tr) => tr,
+                None => {
+                    stats.pruned += 1;
+                    continue;
+                }
+            };
+            stats.trimmed += 1;
+            let enc = if tr.data.can_encode(rec.encoding()) {
+                rec.encoding()
+            } else {
+                tr.data.default_encoding()
+            };
+            let mut i = 0;
+            while i < mask.len() {
+                if mask[i] {
+                    i += 1;
+                    continue;
+                }
+                let i0 = i;
+                while i < mask.len() && !mask[i] {
+                    i += 1;
+                }
+                let mut piece = tr.clone();
+                piece.start = tr.time_of(i0);
+                piece.data = tr.data.slice(i0, i);
+                add_interval(&mut covered, piece.start, piece.end1());
+                for r in piece.pack(rec.reclen(), enc) {
+                    out.push((Key::new(&r), r));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Return true if two records have identical data samples
+fn same_data(a: &ms_record, b: &ms_record) -> bool {
+    match (Trace::from_record(a), Trace::from_record(b)) {
+        (Some(a), Some(b)) => a.data == b.data,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Return, for each sample of a record, whether it lies within the
+/// covered intervals to within half a sample
+fn overlapped(key: &Key, covered: &[(DateTime<Utc>, DateTime<Utc>)]) -> Vec<bool> {
+    let half = Duration::microseconds((0.5 / key.samprate * 1e6) as i64);
+    let hits: Vec<_> = covered
+        .iter()
+        .filter(|&&(s, e)| s < key.end1 && e > key.start)
+        .collect();
+    (0..key.npts)
+        .map(|i| {
+            let t = key.time_of(i);
+            hits.iter().any(|&&(s, e)| s - half <= t && t < e - half)
+        })
+        .collect()
+}
+
+/// Add an interval to a sorted list of disjoint intervals
+fn add_interval(
+    covered: &mut Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) {
+    covered.push((start, end));
+    covered.sort();
+    let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+    for &(s, e) in covered.iter() {
+        if let Some(last) = merged.last_mut() {
+            if s <= last.1 {
+                last.1 = std::cmp::max(last.1, e);
+                continue;
+            }
+        }
+        merged.push((s, e));
+    }
+    *covered = merged;
+}
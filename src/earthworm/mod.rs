@@ -0,0 +1,22 @@
+//! Earthworm support
+//!
+//! Earthworm passes waveforms between modules as TRACEBUF2 packets,
+//! which are converted to and from traces and miniSEED records here, and
+//! serves recent packets from wave servers, which are read with a
+//! WaveServerV client.
+//!
+//! ```
+//! use miniseed::earthworm::TraceBuf;
+//! use miniseed::{ms_input, Trace};
+//!
+//! let recs: Vec<_> = ms_input::open("tests/multiple.seed").take(2).collect();
+//! let tbs: Vec<TraceBuf> = recs.iter().flat_map(TraceBuf::from_record).collect();
+//! let npts: usize = tbs.iter().map(TraceBuf::npts).sum();
+//! assert_eq!(npts, Trace::from_records(&recs)[0].npts());
+//! ```
+
+pub mod tracebuf;
+pub mod wave_server;
+
+pub use self::tracebuf::TraceBuf;
+pub use self::wave_server::{MenuEntry, WaveServerClient};
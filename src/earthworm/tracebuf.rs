@@ -0,0 +1,345 @@
+//! Earthworm TRACEBUF2 packets
+//!
+//! A TRACEBUF2 packet is a 64 byte header followed by the samples,
+//! 16 or 32 bit integers or 32 or 64 bit floats in the byte order of the
+//! sending machine.  The header holds the pin number, number of samples,
+//! the times of the first and last samples and the sample rate, followed
+//! by the station, network, channel and location codes, the version,
+//! the data type, e.g. `s4` for big endian or `i4` for little endian 32
+//! bit integers, and the quality.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::io;
+
+use {hptime_to_utc, ms_record, utc_to_hptime, Samples, Trace};
+
+/// Length of the TRACEBUF2 header
+pub const HEADER_LEN: usize = 64;
+
+/// Largest TRACEBUF2 packet including the header
+pub const MAX_TRACEBUF_SIZ: usize = 4096;
+
+/// Convert seconds from epoch to a time, rounded to microseconds
+pub fn epoch_to_utc(t: f64) -> DateTime<Utc> {
+    hptime_to_utc((t * 1e6).round() as i64)
+}
+
+/// Convert a time to seconds from epoch
+pub fn utc_to_epoch(t: &DateTime<Utc>) -> f64 {
+    utc_to_hptime(t) as f64 / 1e6
+}
+
+/// TRACEBUF2 packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceBuf {
+    pub pinno: i32,
+    pub station: String,
+    pub network: String,
+    pub channel: String,
+    /// Location code, empty for `--`
+    pub location: String,
+    /// Time of the first sample
+    pub start: DateTime<Utc>,
+    /// Sample rate in Hz
+    pub samprate: f64,
+    /// Quality flags
+    pub quality: [u8; 2],
+    pub data: Samples,
+}
+
+fn code(buf: &[u8]) -> String {
+    let n = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..n]).trim().to_string()
+}
+
+fn put_code(out: &mut Vec<u8>, s: &str, len: usize) {
+    let b = s.as_bytes();
+    let n = b.len().min(len - 1);
+    out.extend_from_slice(&b[..n]);
+    out.resize(out.len() + len - n, 0);
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Return whether a data type is big endian and its sample size in bytes
+pub fn data_type(dtype: &str) -> io::Result<(bool, usize)> {
+    match dtype {
+        "s4" | "t4" => Ok((true, 4)),
+        "i4" | "f4" => Ok((false, 4)),
+        "s2" => Ok((true, 2)),
+        "i2" => Ok((false, 2)),
+        "t8" => Ok((true, 8)),
+        "f8" => Ok((false, 8)),
+        _ => Err(bad(format!("unknown TRACEBUF2 data type: {:?}", dtype))),
+    }
+}
+
+/// Return the length in bytes of the packet starting at `buf`, which
+/// must hold at least the header
+pub fn packet_len(buf: &[u8]) -> io::Result<usize> {
+    if buf.len() < HEADER_LEN {
+        return Err(bad(format!("TRACEBUF2 header of {} bytes", buf.len())));
+    }
+    let (big, size) = data_type(&code(&buf[57..60]))?;
+    let b = [buf[4], buf[5], buf[6], buf[7]];
+    let nsamp = if big {
+        i32::from_be_bytes(b)
+    } else {
+        i32::from_le_bytes(b)
+    };
+    if nsamp < 0 {
+        return Err(bad(format!("TRACEBUF2 packet with {} samples", nsamp)));
+    }
+    Ok(HEADER_LEN + nsamp as usize * size)
+}
+
+impl TraceBuf {
+    /// Parse a packet
+    ///
+    /// ```
+    /// # use miniseed::earthworm::TraceBuf;
+    /// # use miniseed::{str_to_utc, Samples};
+    /// let tb = TraceBuf {
+    ///     pinno: 0,
+    ///     station: "ANMO".to_string(),
+    ///     network: "IU".to_string(),
+    ///     channel: "BHZ".to_string(),
+    ///     location: "".to_string(),
+    ///     start: str_to_utc("2010-02-27T06:30:00").unwrap(),
+    ///     samprate: 20.0,
+    ///     quality: [0, 0],
+    ///     data: Samples::Int(vec![1, 2, 3]),
+    /// };
+    /// let buf = tb.to_bytes(true).unwrap();
+    /// assert_eq!(buf.len(), 64 + 12);
+    /// assert_eq!(&buf[57..60], b"s4\0");
+    /// assert_eq!(TraceBuf::parse(&buf).unwrap(), tb);
+    /// assert_eq!(TraceBuf::parse(&tb.to_bytes(false).unwrap()).unwrap(), tb);
+    /// ```
+    pub fn parse(buf: &[u8]) -> io::Result<TraceBuf> {
+        if buf.len() < HEADER_LEN {
+            return Err(bad(format!("TRACEBUF2 packet of {} bytes", buf.len())));
+        }
+        let dtype = code(&buf[57..60]);
+        let (big, size) = data_type(&dtype)?;
+        let u32_at = |i: usize| {
+            let b = [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+            if big {
+                u32::from_be_bytes(b)
+            } else {
+                u32::from_le_bytes(b)
+            }
+        };
+        let u64_at = |i: usize| {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&buf[i..i + 8]);
+            if big {
+                u64::from_be_bytes(b)
+            } else {
+                u64::from_le_bytes(b)
+            }
+        };
+        let nsamp = u32_at(4) as i32;
+        if nsamp < 0 || buf.len() < HEADER_LEN + nsamp as usize * size {
+            return Err(bad(format!(
+                "TRACEBUF2 packet of {} bytes with {} samples",
+                buf.len(),
+                nsamp
+            )));
+        }
+        let n = nsamp as usize;
+        let d = HEADER_LEN;
+        let data = match (dtype.as_bytes()[0], size) {
+            (_, 2) => Samples::Int(
+                (0..n)
+                    .map(|i| {
+                        let b = [buf[d + 2 * i], buf[d + 2 * i + 1]];
+                        if big {
+                            i16::from_be_bytes(b) as i32
+                        } else {
+                            i16::from_le_bytes(b) as i32
+                        }
+                    })
+                    .collect(),
+            ),
+            (b's', _) | (b'i', _) => {
+                Samples::Int((0..n).map(|i| u32_at(d + 4 * i) as i32).collect())
+            }
+            (_, 4) => Samples::Float((0..n).map(|i| f32::from_bits(u32_at(d + 4 * i))).collect()),
+            _ => Samples::Double((0..n).map(|i| f64::from_bits(u64_at(d + 8 * i))).collect()),
+        };
+        let loc = code(&buf[52..55]);
+        Ok(TraceBuf {
+            pinno: u32_at(0) as i32,
+            station: code(&buf[32..39]),
+            network: code(&buf[39..48]),
+            channel: code(&buf[48..52]),
+            location: if loc == "--" { String::new() } else { loc },
+            start: epoch_to_utc(f64::from_bits(u64_at(8))),
+            samprate: f64::from_bits(u64_at(24)),
+            quality: [buf[60], buf[61]],
+            data,
+        })
+    }
+    /// Return the encoded packet, in big endian byte order if `big` is
+    /// true and little endian otherwise
+    ///
+    /// Integer samples are written as 32 bit integers.  ASCII samples
+    /// cannot be written and give an `InvalidInput` error.
+    pub fn to_bytes(&self, big: bool) -> io::Result<Vec<u8>> {
+        let u32b = |v: u32| {
+            if big {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let u64b = |v: u64| {
+            if big {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let (dtype, size) = match (&self.data, big) {
+            (&Samples::Int(_), true) => ("s4", 4),
+            (&Samples::Int(_), false) => ("i4", 4),
+            (&Samples::Float(_), true) => ("t4", 4),
+            (&Samples::Float(_), false) => ("f4", 4),
+            (&Samples::Double(_), true) => ("t8", 8),
+            (&Samples::Double(_), false) => ("f8", 8),
+            (&Samples::Ascii(_), _) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "ASCII samples cannot be written to TRACEBUF2: {}",
+                        self.id()
+                    ),
+                ))
+            }
+        };
+        let mut out = Vec::with_capacity(HEADER_LEN + self.npts() * size);
+        out.extend_from_slice(&u32b(self.pinno as u32));
+        out.extend_from_slice(&u32b(self.npts() as u32));
+        out.extend_from_slice(&u64b(utc_to_epoch(&self.start).to_bits()));
+        out.extend_from_slice(&u64b(utc_to_epoch(&self.end()).to_bits()));
+        out.extend_from_slice(&u64b(self.samprate.to_bits()));
+        put_code(&mut out, &self.station, 7);
+        put_code(&mut out, &self.network, 9);
+        put_code(&mut out, &self.channel, 4);
+        let loc = if self.location.is_empty() {
+            "--"
+        } else {
+            &self.location
+        };
+        put_code(&mut out, loc, 3);
+        out.extend_from_slice(b"20");
+        put_code(&mut out, dtype, 3);
+        out.extend_from_slice(&self.quality);
+        out.extend_from_slice(&[0, 0]);
+        match self.data {
+            Samples::Int(ref y) => y
+                .iter()
+                .for_each(|&v| out.extend_from_slice(&u32b(v as u32))),
+            Samples::Float(ref y) => y
+                .iter()
+                .for_each(|&v| out.extend_from_slice(&u32b(v.to_bits()))),
+            Samples::Double(ref y) => y
+                .iter()
+                .for_each(|&v| out.extend_from_slice(&u64b(v.to_bits()))),
+            Samples::Ascii(_) => {}
+        }
+        Ok(out)
+    }
+    /// Return the number of samples
+    pub fn npts(&self) -> usize {
+        self.data.len()
+    }
+    /// Return the time of the last sample
+    pub fn end(&self) -> DateTime<Utc> {
+        self.to_trace().end()
+    }
+    /// Return the identifier `NET_STA_LOC_CHA`
+    pub fn id(&self) -> String {
+        format!(
+            "{}_{}_{}_{}",
+            self.network, self.station, self.location, self.channel
+        )
+    }
+    /// Convert to a trace with quality `D`
+    pub fn to_trace(&self) -> Trace {
+        Trace {
+            network: self.network.clone(),
+            station: self.station.clone(),
+            location: self.location.clone(),
+            channel: self.channel.clone(),
+            quality: 'D',
+            start: self.start,
+            samprate: self.samprate,
+            data: self.data.clone(),
+        }
+    }
+    /// Split a trace into packets of at most MAX_TRACEBUF_SIZ bytes
+    ///
+    /// ```
+    /// # use miniseed::earthworm::TraceBuf;
+    /// # use miniseed::{str_to_utc, Samples, Trace};
+    /// let tr = Trace {
+    ///     network: "IU".to_string(),
+    ///     station: "ANMO".to_string(),
+    ///     location: "00".to_string(),
+    ///     channel: "BHZ".to_string(),
+    ///     quality: 'D',
+    ///     start: str_to_utc("2010-02-27T06:30:00").unwrap(),
+    ///     samprate: 20.0,
+    ///     data: Samples::Int((0..2000).collect()),
+    /// };
+    /// let tbs = TraceBuf::from_trace(&tr);
+    /// assert_eq!(tbs.len(), 2);
+    /// assert_eq!(tbs[0].npts(), 1008);
+    /// assert_eq!(tbs[1].start, tr.time_of(1008));
+    /// ```
+    pub fn from_trace(tr: &Trace) -> Vec<TraceBuf> {
+        let size = match tr.data {
+            Samples::Double(_) => 8,
+            Samples::Ascii(_) => return vec![],
+            _ => 4,
+        };
+        let max = (MAX_TRACEBUF_SIZ - HEADER_LEN) / size;
+        let n = tr.npts();
+        (0..n)
+            .step_by(max)
+            .map(|i0| TraceBuf {
+                pinno: 0,
+                station: tr.station.clone(),
+                network: tr.network.clone(),
+                channel: tr.channel.clone(),
+                location: tr.location.clone(),
+                start: tr.time_of(i0),
+                samprate: tr.samprate,
+                quality: [0, 0],
+                data: tr.data.slice(i0, (i0 + max).min(n)),
+            })
+            .collect()
+    }
+    /// Convert a record into packets
+    ///
+    /// Returns no packets if the record has no decoded samples
+    pub fn from_record(rec: &ms_record) -> Vec<TraceBuf> {
+        Trace::from_record(rec)
+            .map(|tr| TraceBuf::from_trace(&tr))
+            .unwrap_or_default()
+    }
+    /// Pack into miniSEED records of length `reclen` bytes
+    ///
+    /// `encoding` is a libmseed data encoding or None for the default
+    /// encoding of the sample type
+    pub fn to_records(&self, reclen: usize, encoding: Option<u32>) -> Vec<ms_record> {
+        let enc = encoding.unwrap_or_else(|| self.data.default_encoding());
+        self.to_trace().pack(reclen, enc)
+    }
+}
@@ -0,0 +1,302 @@
+//! WaveServerV client
+//!
+//! A wave server keeps tanks of recent TRACEBUF2 packets for each
+//! channel.  Requests are single lines starting with a request id which
+//! is echoed in the reply: `MENU` lists the channels and the times
+//! available, `GETSCNLRAW` returns the packets overlapping a window and
+//! `GETSCNL` the samples as ASCII text with gaps filled.  Times are
+//! seconds from epoch.  Each request is made on a new connection.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::tracebuf::{epoch_to_utc, packet_len, utc_to_epoch, TraceBuf, MAX_TRACEBUF_SIZ};
+use {Samples, Trace};
+
+/// Channel listed by `MENU`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuEntry {
+    pub pinno: i32,
+    pub network: String,
+    pub station: String,
+    /// Location code, empty for `--`
+    pub location: String,
+    pub channel: String,
+    /// Time of the oldest sample in the tank
+    pub start: DateTime<Utc>,
+    /// Time of the newest sample in the tank
+    pub end: DateTime<Utc>,
+    /// TRACEBUF2 data type, e.g. `s4`
+    pub datatype: String,
+}
+
+impl MenuEntry {
+    /// Return the identifier `NET_STA_LOC_CHA`
+    pub fn id(&self) -> String {
+        format!(
+            "{}_{}_{}_{}",
+            self.network, self.station, self.location, self.channel
+        )
+    }
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Convert an id `NET_STA_LOC_CHA` to the `STA CHA NET LOC` of a request
+fn scnl(id: &str) -> io::Result<String> {
+    let v: Vec<&str> = id.split('_').collect();
+    if v.len() != 4 || v.iter().enumerate().any(|(i, s)| i != 2 && s.is_empty()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid channel id: {:?}", id),
+        ));
+    }
+    let loc = if v[2].is_empty() { "--" } else { v[2] };
+    Ok(format!("{} {} {} {}", v[1], v[3], v[0], loc))
+}
+
+fn from_location(loc: &str) -> String {
+    if loc == "--" {
+        String::new()
+    } else {
+        loc.to_string()
+    }
+}
+
+fn parse_time(s: &str) -> io::Result<DateTime<Utc>> {
+    s.parse::<f64>()
+        .map(epoch_to_utc)
+        .map_err(|_| bad(format!("invalid wave server time: {:?}", s)))
+}
+
+/// Return an error for a reply flag other than `F`
+///
+/// `FL`, `FR` and `FG` mean the window is before, after or in a gap in
+/// the tank and give an empty reply instead
+fn check_flag(flag: &str, line: &str) -> io::Result<bool> {
+    match flag {
+        "F" => Ok(true),
+        "FL" | "FR" | "FG" => Ok(false),
+        "FN" => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("channel not found: {}", line),
+        )),
+        _ => Err(bad(format!("wave server error: {}", line))),
+    }
+}
+
+/// WaveServerV client
+///
+/// ```no_run
+/// # extern crate chrono;
+/// # extern crate miniseed;
+/// # fn main() {
+/// use chrono::Duration;
+/// use miniseed::earthworm::WaveServerClient;
+///
+/// let client = WaveServerClient::new("localhost:16022");
+/// for entry in client.menu().unwrap() {
+///     let start = entry.end - Duration::seconds(60);
+///     let traces = client.traces(&entry.id(), &start, &entry.end).unwrap();
+///     for tr in traces {
+///         println!("{} {} {}", tr.id(), tr.start, tr.npts());
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WaveServerClient {
+    addr: String,
+    timeout: Duration,
+    max_bytes: usize,
+}
+
+impl WaveServerClient {
+    /// Create a client for the server at `addr`, `host:port`
+    pub fn new(addr: &str) -> WaveServerClient {
+        WaveServerClient {
+            addr: addr.to_string(),
+            timeout: Duration::from_secs(30),
+            max_bytes: 4096 * MAX_TRACEBUF_SIZ,
+        }
+    }
+    /// Fail requests after `timeout` without a reply, 30 s by default
+    pub fn with_timeout(mut self, timeout: Duration) -> WaveServerClient {
+        self.timeout = timeout;
+        self
+    }
+    /// Refuse raw replies of more than `max_bytes` of packets, 4096
+    /// packets of MAX_TRACEBUF_SIZ by default
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> WaveServerClient {
+        self.max_bytes = max_bytes;
+        self
+    }
+    // Send a request and return the reader for the reply after checking
+    // that the reply starts with the request id
+    fn request(&self, req: &str) -> io::Result<(BufReader<TcpStream>, String)> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.write_all(req.as_bytes())?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "wave server closed the connection",
+            ));
+        }
+        let line = line.trim().to_string();
+        let id = req.split_whitespace().nth(1).unwrap_or("");
+        if line.split_whitespace().next() != Some(id) {
+            return Err(bad(format!("unexpected wave server reply: {}", line)));
+        }
+        Ok((reader, line))
+    }
+    /// List the channels on the server
+    pub fn menu(&self) -> io::Result<Vec<MenuEntry>> {
+        let (_, line) = self.request("MENU: menu SCNL\n")?;
+        let v: Vec<&str> = line.split_whitespace().skip(1).collect();
+        let entries = v.chunks_exact(8);
+        if !entries.remainder().is_empty() {
+            return Err(bad(format!("invalid wave server menu: {}", line)));
+        }
+        entries
+            .map(|e| -> io::Result<MenuEntry> {
+                Ok(MenuEntry {
+                    pinno: e[0]
+                        .parse()
+                        .map_err(|_| bad(format!("invalid wave server menu: {}", line)))?,
+                    station: e[1].to_string(),
+                    channel: e[2].to_string(),
+                    network: e[3].to_string(),
+                    location: from_location(e[4]),
+                    start: parse_time(e[5])?,
+                    end: parse_time(e[6])?,
+                    datatype: e[7].to_string(),
+                })
+            })
+            .collect()
+    }
+    /// Request the TRACEBUF2 packets of channel `id`, `NET_STA_LOC_CHA`,
+    /// overlapping `start` to `end`
+    ///
+    /// Returns no packets if the window is outside the tank or in a gap,
+    /// and an `InvalidData` error if the reply is larger than the
+    /// maximum set by with_max_bytes()
+    pub fn get_raw(
+        &self,
+        id: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> io::Result<Vec<TraceBuf>> {
+        let req = format!(
+            "GETSCNLRAW: raw {} {:.6} {:.6}\n",
+            scnl(id)?,
+            utc_to_epoch(start),
+            utc_to_epoch(end)
+        );
+        let (mut reader, line) = self.request(&req)?;
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.len() < 7 {
+            return Err(bad(format!("invalid wave server reply: {}", line)));
+        }
+        if !check_flag(v[6], &line)? {
+            return Ok(vec![]);
+        }
+        let nbytes = v
+            .get(10)
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| bad(format!("invalid wave server reply: {}", line)))?;
+        if nbytes > self.max_bytes {
+            return Err(bad(format!(
+                "wave server reply of {} bytes is too large",
+                nbytes
+            )));
+        }
+        let mut buf = vec![0; nbytes];
+        reader.read_exact(&mut buf)?;
+        let mut packets = Vec::new();
+        let mut i = 0;
+        while i < buf.len() {
+            let n = packet_len(&buf[i..])?;
+            packets.push(TraceBuf::parse(&buf[i..])?);
+            i += n;
+        }
+        Ok(packets)
+    }
+    /// Request the samples of channel `id` from `start` to `end` as
+    /// text, with gaps filled with `fill`
+    ///
+    /// Returns None if the window is outside the tank or in a gap
+    pub fn get_ascii(
+        &self,
+        id: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        fill: i32,
+    ) -> io::Result<Option<Trace>> {
+        let req = format!(
+            "GETSCNL: ascii {} {:.6} {:.6} {}\n",
+            scnl(id)?,
+            utc_to_epoch(start),
+            utc_to_epoch(end),
+            fill
+        );
+        let (_, line) = self.request(&req)?;
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.len() < 7 {
+            return Err(bad(format!("invalid wave server reply: {}", line)));
+        }
+        if !check_flag(v[6], &line)? {
+            return Ok(None);
+        }
+        if v.len() < 10 {
+            return Err(bad(format!("invalid wave server reply: {}", line)));
+        }
+        let samprate = v[9]
+            .parse::<f64>()
+            .map_err(|_| bad(format!("invalid wave server reply: {}", line)))?;
+        let ints: Result<Vec<i32>, _> = v[10..].iter().map(|s| s.parse()).collect();
+        let data = match ints {
+            Ok(y) => Samples::Int(y),
+            Err(_) => Samples::Double(
+                v[10..]
+                    .iter()
+                    .map(|s| s.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| bad(format!("invalid wave server samples: {}", line)))?,
+            ),
+        };
+        Ok(Some(Trace {
+            network: v[4].to_string(),
+            station: v[2].to_string(),
+            location: from_location(v[5]),
+            channel: v[3].to_string(),
+            quality: 'D',
+            start: parse_time(v[8])?,
+            samprate,
+            data,
+        }))
+    }
+    /// Request the packets of channel `id` overlapping `start` to `end`
+    /// and return them merged into traces
+    pub fn traces(
+        &self,
+        id: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> io::Result<Vec<Trace>> {
+        let packets = self.get_raw(id, start, end)?;
+        Ok(Trace::merge(
+            packets.iter().map(TraceBuf::to_trace).collect(),
+        ))
+    }
+}
@@ -0,0 +1,385 @@
+//! In-place editing of record headers
+//!
+//! Fixed header fields and blockette values are patched directly in the
+//! record bytes, in the byte order of the record, without decoding the
+//! data samples.  Data frames are preserved bit-for-bit.
+//!
+//! ```
+//! use miniseed::edit::HeaderEditor;
+//! use miniseed::ms_record;
+//! use std::fs::File;
+//! use std::io::Read;
+//!
+//! let mut buf = vec![];
+//! File::open("tests/sample.miniseed").unwrap().read_to_end(&mut buf).unwrap();
+//! {
+//!     let mut ed = HeaderEditor::new(&mut buf).unwrap();
+//!     ed.set_network("XX").unwrap();
+//!     ed.set_location("").unwrap();
+//!     ed.set_quality('Q').unwrap();
+//! }
+//! let rec = ms_record::parse(&buf);
+//! assert_eq!(rec.id(), "XX_PPNAF__HHZ");
+//! assert_eq!(rec.dataquality(), "Q");
+//! ```
+//!
+//! A table of rename rules may be applied to whole files:
+//!
+//! ```
+//! use miniseed::edit::RenameRules;
+//!
+//! let rules = RenameRules::parse("PN PPNAF 00 HH?  XX * -- *").unwrap();
+//! let out = std::env::temp_dir().join("miniseed-rename-doc.mseed");
+//! let n = rules.apply_file("tests/sample.miniseed", &out).unwrap();
+//! assert_eq!(n, 1);
+//! ```
+
+use chrono::DateTime;
+use chrono::Datelike;
+use chrono::Duration;
+use chrono::Timelike;
+use chrono::Utc;
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use header::RawHeader;
+use index::MsIndex;
+use selection::wildcard_match;
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Editor of the header of a single record
+pub struct HeaderEditor<'a> {
+    buf: &'a mut [u8],
+    hdr: RawHeader,
+}
+
+impl<'a> HeaderEditor<'a> {
+    /// Create an editor for the record at the start of `buf`
+    ///
+    /// Returns None if the buffer does not begin with a record header
+    pub fn new(buf: &'a mut [u8]) -> Option<HeaderEditor<'a>> {
+        let hdr = RawHeader::parse(buf)?;
+        Some(HeaderEditor { buf, hdr })
+    }
+    /// Return the current header values
+    pub fn header(&self) -> RawHeader {
+        RawHeader::parse(self.buf).unwrap_or_else(|| self.hdr.clone())
+    }
+    fn set_code(&mut self, start: usize, len: usize, code: &str, name: &str) -> io::Result<()> {
+        if code.len() > len || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid_input(format!("invalid {} code: {:?}", name, code)));
+        }
+        let bytes = code.as_bytes();
+        for i in 0..len {
+            self.buf[start + i] = *bytes.get(i).unwrap_or(&b' ');
+        }
+        Ok(())
+    }
+    fn put_u16(&mut self, i: usize, v: u16) {
+        let b = if self.hdr.swapped {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        };
+        self.buf[i..i + 2].copy_from_slice(&b);
+    }
+    fn put_u32(&mut self, i: usize, v: u32) {
+        let b = if self.hdr.swapped {
+            v.to_le_bytes()
+        } else {
+            v.to_be_bytes()
+        };
+        self.buf[i..i + 4].copy_from_slice(&b);
+    }
+    fn blockette(&self, btype: u16) -> io::Result<usize> {
+        self.hdr
+            .blockettes(self.buf)
+            .iter()
+            .find(|&&(t, off)| t == btype && off + 8 <= self.buf.len())
+            .map(|&(_, off)| off)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("record has no blockette {}", btype),
+                )
+            })
+    }
+    /// Set the network code, up to 2 characters
+    pub fn set_network(&mut self, net: &str) -> io::Result<()> {
+        self.set_code(18, 2, net, "network")
+    }
+    /// Set the station code, up to 5 characters
+    pub fn set_station(&mut self, sta: &str) -> io::Result<()> {
+        self.set_code(8, 5, sta, "station")
+    }
+    /// Set the location code, up to 2 characters
+    pub fn set_location(&mut self, loc: &str) -> io::Result<()> {
+        self.set_code(13, 2, loc, "location")
+    }
+    /// Set the channel code, up to 3 characters
+    pub fn set_channel(&mut self, cha: &str) -> io::Result<()> {
+        self.set_code(15, 3, cha, "channel")
+    }
+    /// Set the data quality code, one of `D`, `R`, `Q` or `M`
+    pub fn set_quality(&mut self, quality: char) -> io::Result<()> {
+        if !"DRQM".contains(quality) {
+            return Err(invalid_input(format!(
+                "invalid quality code: {:?}",
+                quality
+            )));
+        }
+        self.buf[6] = quality as u8;
+        Ok(())
+    }
+    /// Set the sequence number, up to 999999
+    pub fn set_sequence_number(&mut self, seq: u32) -> io::Result<()> {
+        if seq > 999_999 {
+            return Err(invalid_input(format!("invalid sequence number: {}", seq)));
+        }
+        self.buf[0..6].copy_from_slice(format!("{:06}", seq).as_bytes());
+        Ok(())
+    }
+    /// Set the activity flags
+    pub fn set_act_flags(&mut self, flags: u8) {
+        self.buf[36] = flags;
+    }
+    /// Set the I/O and clock flags
+    pub fn set_io_flags(&mut self, flags: u8) {
+        self.buf[37] = flags;
+    }
+    /// Set the data quality flags
+    pub fn set_dq_flags(&mut self, flags: u8) {
+        self.buf[38] = flags;
+    }
+    /// Set the time correction in units of 0.0001 seconds
+    pub fn set_time_correction(&mut self, correct: i32) {
+        self.put_u32(40, correct as u32);
+    }
+    /// Set the start time and return the time stored in the header
+    ///
+    /// The fixed header stores 0.0001 second resolution, remaining
+    /// microseconds are stored in blockette 1001 if present, otherwise
+    /// the time is rounded to the nearest 0.0001 second
+    pub fn set_start(&mut self, t: &DateTime<Utc>) -> DateTime<Utc> {
+        let b1001 = self.blockette(1001).ok();
+        let mut t = *t - Duration::nanoseconds(t.timestamp_subsec_nanos() as i64 % 1000);
+        if b1001.is_none() {
+            let rem = (t.timestamp_subsec_micros() % 100) as i64;
+            t += Duration::microseconds(if rem < 50 { -rem } else { 100 - rem });
+        }
+        let us = t.timestamp_subsec_micros() % 1_000_000;
+        let year = t.year() as u16;
+        let day = t.ordinal() as u16;
+        self.put_u16(20, year);
+        self.put_u16(22, day);
+        self.buf[24] = t.hour() as u8;
+        self.buf[25] = t.minute() as u8;
+        self.buf[26] = t.second().min(59) as u8;
+        self.put_u16(28, (us / 100) as u16);
+        if let Some(off) = b1001 {
+            self.buf[off + 5] = (us % 100) as u8;
+        }
+        t
+    }
+    /// Set the actual sample rate of blockette 100
+    pub fn set_b100_samprate(&mut self, samprate: f32) -> io::Result<()> {
+        let off = self.blockette(100)?;
+        self.put_u32(off + 4, samprate.to_bits());
+        Ok(())
+    }
+    /// Set the timing quality of blockette 1001, 0 to 100 percent
+    pub fn set_timing_quality(&mut self, quality: u8) -> io::Result<()> {
+        let off = self.blockette(1001)?;
+        self.buf[off + 4] = quality;
+        Ok(())
+    }
+    /// Set the microseconds of blockette 1001, -50 to 99
+    pub fn set_b1001_usec(&mut self, usec: i8) -> io::Result<()> {
+        let off = self.blockette(1001)?;
+        self.buf[off + 5] = usec as u8;
+        Ok(())
+    }
+}
+
+/// Rename rule replacing the codes of matching records
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameRule {
+    /// Network, Station, Location and Channel patterns to match
+    pub from: [String; 4],
+    /// Replacement codes, None keeps the existing code
+    pub to: [Option<String>; 4],
+    /// Replacement quality code, None keeps the existing code
+    pub quality: Option<char>,
+}
+
+impl RenameRule {
+    /// Return true if the rule matches the codes
+    pub fn matches(&self, net: &str, sta: &str, loc: &str, cha: &str) -> bool {
+        wildcard_match(&self.from[0], net)
+            && wildcard_match(&self.from[1], sta)
+            && wildcard_match(&self.from[2], loc)
+            && wildcard_match(&self.from[3], cha)
+    }
+    /// Apply the rule to the header of a record
+    pub fn apply(&self, ed: &mut HeaderEditor) -> io::Result<()> {
+        if let Some(ref v) = self.to[0] {
+            ed.set_network(v)?;
+        }
+        if let Some(ref v) = self.to[1] {
+            ed.set_station(v)?;
+        }
+        if let Some(ref v) = self.to[2] {
+            ed.set_location(v)?;
+        }
+        if let Some(ref v) = self.to[3] {
+            ed.set_channel(v)?;
+        }
+        if let Some(q) = self.quality {
+            ed.set_quality(q)?;
+        }
+        Ok(())
+    }
+}
+
+/// Table of rename rules
+///
+/// The first matching rule is applied to each record
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RenameRules {
+    rules: Vec<RenameRule>,
+}
+
+impl RenameRules {
+    /// Create an empty table
+    pub fn new() -> RenameRules {
+        RenameRules { rules: vec![] }
+    }
+    /// Add a rule
+    pub fn add(&mut self, rule: RenameRule) {
+        self.rules.push(rule);
+    }
+    /// Return the rules
+    pub fn rules(&self) -> &[RenameRule] {
+        &self.rules
+    }
+    /// Read rules from a file, see parse()
+    pub fn from_file<S: AsRef<Path>>(file: S) -> io::Result<RenameRules> {
+        let mut s = String::new();
+        File::open(file)?.read_to_string(&mut s)?;
+        RenameRules::parse(&s)
+    }
+    /// Parse rules, one per line
+    ///
+    /// ```text
+    /// NET STA LOC CHA  NEWNET NEWSTA NEWLOC NEWCHA [NEWQUALITY]
+    /// ```
+    ///
+    /// The first four fields are patterns with `?` and `*` wildcards.
+    /// A `*` replacement keeps the existing code, and `--` is an empty
+    /// location code.  Blank lines and lines beginning with `#` are ignored.
+    pub fn parse(text: &str) -> io::Result<RenameRules> {
+        let mut rules = RenameRules::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("rename line {}: {}: {}", i + 1, msg, line),
+                )
+            };
+            let v: Vec<&str> = line.split_whitespace().collect();
+            if v.len() != 8 && v.len() != 9 {
+                return Err(bad("expected 8 or 9 fields"));
+            }
+            let code = |s: &str| if s == "--" { "" } else { s }.to_string();
+            let from = [code(v[0]), code(v[1]), code(v[2]), code(v[3])];
+            let mut to = [None, None, None, None];
+            for (k, t) in to.iter_mut().enumerate() {
+                if v[4 + k] != "*" {
+                    *t = Some(code(v[4 + k]));
+                }
+            }
+            let quality = match v.get(8) {
+                None | Some(&"*") => None,
+                Some(q) if q.len() == 1 && "DRQM".contains(*q) => q.chars().next(),
+                Some(_) => return Err(bad("invalid quality code")),
+            };
+            rules.add(RenameRule { from, to, quality });
+        }
+        Ok(rules)
+    }
+    /// Apply the first matching rule to the record at the start of `buf`
+    ///
+    /// Returns true if a rule was applied
+    pub fn apply(&self, buf: &mut [u8]) -> io::Result<bool> {
+        let mut ed = match HeaderEditor::new(buf) {
+            Some(ed) => ed,
+            None => return Ok(false),
+        };
+        let h = ed.header();
+        match self
+            .rules
+            .iter()
+            .find(|r| r.matches(&h.network, &h.station, &h.location, &h.channel))
+        {
+            Some(rule) => rule.apply(&mut ed).map(|_| true),
+            None => Ok(false),
+        }
+    }
+    /// Apply the rules to the records of `input` and write all records
+    /// to `output`
+    ///
+    /// Data which are not miniSEED records are dropped.  Returns the
+    /// number of records renamed.
+    pub fn apply_file<S: AsRef<Path>, P: AsRef<Path>>(
+        &self,
+        input: S,
+        output: P,
+    ) -> io::Result<usize> {
+        let idx = MsIndex::build(&input)?;
+        let mut fp = File::open(&input)?;
+        let mut out = BufWriter::new(File::create(output)?);
+        let mut n = 0;
+        for e in idx.entries() {
+            let mut buf = vec![0u8; e.reclen];
+            fp.seek(SeekFrom::Start(e.offset))?;
+            fp.read_exact(&mut buf)?;
+            if self.apply(&mut buf)? {
+                n += 1;
+            }
+            out.write_all(&buf)?;
+        }
+        out.flush()?;
+        Ok(n)
+    }
+    /// Apply the rules to the records of a file in place
+    ///
+    /// Only the headers of renamed records are rewritten.  Returns the
+    /// number of records renamed.
+    pub fn apply_in_place<S: AsRef<Path>>(&self, file: S) -> io::Result<usize> {
+        let idx = MsIndex::build(&file)?;
+        let mut fp = OpenOptions::new().read(true).write(true).open(&file)?;
+        let mut n = 0;
+        for e in idx.entries() {
+            let mut buf = vec![0u8; e.reclen.min(256)];
+            fp.seek(SeekFrom::Start(e.offset))?;
+            fp.read_exact(&mut buf)?;
+            if self.apply(&mut buf)? {
+                fp.seek(SeekFrom::Start(e.offset))?;
+                fp.write_all(&buf)?;
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,254 @@
+//! Event-based waveform extraction
+//!
+//! Extract windows around a list of events from a set of files or an
+//! archive directory in a single pass over the data.  Each event has an
+//! origin time, a window before and after the origin, and a selection
+//! of channels.
+//!
+//! ```
+//! extern crate chrono;
+//! # extern crate miniseed;
+//! use chrono::Duration;
+//! use miniseed::{str_to_utc, Selection};
+//! use miniseed::extract::{extract, EventRequest};
+//!
+//! # fn main() {
+//! let mut sel = Selection::new();
+//! sel.add("IU_ANMO_00_BH?", None, None);
+//! let origin = str_to_utc("2010-02-27T06:34:14").unwrap();
+//! let req = EventRequest::new("maule", origin, Duration::seconds(60), Duration::seconds(600), sel);
+//!
+//! let events = extract(&[req], &["tests/multiple.seed"]);
+//! assert_eq!(events[0].traces.len(), 1);
+//! assert!(events[0].is_complete());
+//! # }
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use cut::Boundary;
+use {ms_input, ms_output, Selection, Trace};
+
+/// Extraction request for a single event
+#[derive(Debug, Clone)]
+pub struct EventRequest {
+    /// Event name, used when writing output files
+    pub name: String,
+    /// Origin time
+    pub origin: DateTime<Utc>,
+    /// Window length before the origin
+    pub pre: Duration,
+    /// Window length after the origin
+    pub post: Duration,
+    /// Channels to extract, time windows are ignored
+    pub selection: Selection,
+}
+
+impl EventRequest {
+    /// Create a new event request
+    pub fn new(
+        name: &str,
+        origin: DateTime<Utc>,
+        pre: Duration,
+        post: Duration,
+        selection: Selection,
+    ) -> EventRequest {
+        EventRequest {
+            name: name.to_string(),
+            origin,
+            pre,
+            post,
+            selection,
+        }
+    }
+    /// Return the extraction window `[origin - pre, origin + post)`
+    pub fn window(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (self.origin - self.pre, self.origin + self.post)
+    }
+}
+
+/// Data coverage of a channel within an event window
+#[derive(Debug, Clone, PartialEq)]
+pub enum Coverage {
+    /// Data cover the entire window
+    Complete,
+    /// Data cover part of the window, with the gaps listed
+    Partial(Vec<(DateTime<Utc>, DateTime<Utc>)>),
+    /// No data were found
+    Missing,
+}
+
+/// Coverage report for a single channel or selection pattern
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelReport {
+    /// Channel id, or the selection pattern if no data were found
+    pub id: String,
+    pub coverage: Coverage,
+}
+
+/// Data extracted for a single event
+#[derive(Debug, Clone)]
+pub struct EventData {
+    pub request: EventRequest,
+    /// Traces trimmed to the event window
+    pub traces: Vec<Trace>,
+    /// Coverage of each channel and each pattern without data
+    pub report: Vec<ChannelReport>,
+}
+
+impl EventData {
+    /// Return true if every selected channel fully covers the window
+    pub fn is_complete(&self) -> bool {
+        self.report.iter().all(|r| r.coverage == Coverage::Complete)
+    }
+    /// Return reports for channels which are missing or partial
+    pub fn incomplete(&self) -> Vec<&ChannelReport> {
+        self.report
+            .iter()
+            .filter(|r| r.coverage != Coverage::Complete)
+            .collect()
+    }
+    /// Write the event traces to a miniSEED file
+    ///
+    /// Traces are packed into records of length `reclen` with `encoding`
+    /// or the default encoding for the sample type if None
+    pub fn write<S: AsRef<Path>>(
+        &self,
+        file: S,
+        reclen: usize,
+        encoding: Option<u32>,
+    ) -> io::Result<()> {
+        let mut out = ms_output::open(file)?;
+        for tr in &self.traces {
+            let enc = encoding.unwrap_or_else(|| tr.data.default_encoding());
+            for rec in tr.pack(reclen, enc) {
+                out.write(&rec)?;
+            }
+        }
+        out.flush()
+    }
+}
+
+/// Extract event windows from a set of files
+///
+/// Each file is read once, records are decoded only if they match at
+/// least one event
+pub fn extract<S: AsRef<Path>>(requests: &[EventRequest], files: &[S]) -> Vec<EventData> {
+    let mut union = Selection::new();
+    for req in requests {
+        let (t0, t1) = req.window();
+        for e in req.selection.entries() {
+            union.add_with_quality(&e.pattern(), e.quality, Some(t0), Some(t1));
+        }
+    }
+    let mut found: Vec<Vec<Trace>> = requests.iter().map(|_| vec![]).collect();
+    for file in files {
+        let input = ms_input::open(file).with_selection(union.clone());
+        for rec in input {
+            let tr = match Trace::from_record(&rec) {
+                Some(tr) => tr,
+                None => continue,
+            };
+            let q = tr.quality;
+            for (req, traces) in requests.iter().zip(found.iter_mut()) {
+                let (t0, t1) = req.window();
+                if rec.start() < t1
+                    && rec.end1() > t0
+                    && req.selection.matches_id(
+                        &tr.network,
+                        &tr.station,
+                        &tr.location,
+                        &tr.channel,
+                        q,
+                    )
+                {
+                    traces.push(tr.clone());
+                }
+            }
+        }
+    }
+    requests
+        .iter()
+        .zip(found)
+        .map(|(req, mut traces)| {
+            let (t0, t1) = req.window();
+            traces.sort_by(|a, b| a.cmp_id_time(b));
+            let traces: Vec<Trace> = Trace::merge(traces)
+                .iter()
+                .filter_map(|tr| tr.trim(&t0, &t1, Boundary::Inclusive))
+                .collect();
+            let report = coverage(req, &traces);
+            EventData {
+                request: req.clone(),
+                traces,
+                report,
+            }
+        })
+        .collect()
+}
+
+/// Extract event windows from all files below a directory
+pub fn extract_dir<S: AsRef<Path>>(requests: &[EventRequest], dir: S) -> Vec<EventData> {
+    let pattern = dir.as_ref().join("**").join("*");
+    let files: Vec<PathBuf> = match glob::glob(&pattern.to_string_lossy()) {
+        Ok(paths) => paths
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+            .collect(),
+        Err(_) => vec![],
+    };
+    extract(requests, &files)
+}
+
+fn coverage(req: &EventRequest, traces: &[Trace]) -> Vec<ChannelReport> {
+    let (t0, t1) = req.window();
+    let mut report = vec![];
+    let mut ids: Vec<String> = traces.iter().map(|tr| tr.id()).collect();
+    ids.dedup();
+    for id in ids {
+        let mut gaps = vec![];
+        let mut t = t0;
+        for tr in traces.iter().filter(|tr| tr.id() == id) {
+            let tol = Duration::microseconds((tr.delta() * 1e6) as i64);
+            if tr.start - t > tol {
+                gaps.push((t, tr.start));
+            }
+            t = std::cmp::max(t, tr.end1());
+        }
+        if let Some(tr) = traces.iter().find(|tr| tr.id() == id) {
+            if t1 - t > Duration::microseconds((tr.delta() * 1e6) as i64) {
+                gaps.push((t, t1));
+            }
+        }
+        let coverage = if gaps.is_empty() {
+            Coverage::Complete
+        } else {
+            Coverage::Partial(gaps)
+        };
+        report.push(ChannelReport { id, coverage });
+    }
+    for e in req.selection.entries() {
+        let found = traces.iter().any(|tr| {
+            e.matches_id(
+                &tr.network,
+                &tr.station,
+                &tr.location,
+                &tr.channel,
+                tr.quality,
+            )
+        });
+        if !found {
+            report.push(ChannelReport {
+                id: e.pattern(),
+                coverage: Coverage::Missing,
+            });
+        }
+    }
+    report
+}
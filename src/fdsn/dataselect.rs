@@ -0,0 +1,464 @@
+//! FDSN dataselect client
+//!
+//! Requests are made with GET for a single request line or POST with a
+//! bulk body of one `NET STA LOC CHA START END` line per channel and time
+//! window.  Responses are read record by record as they arrive.  Large
+//! requests are split by number of lines and length of time window
+//! before they are sent, and requests rejected as too large (413) are
+//! split in half and retried.  A record spanning the edge between split
+//! windows is returned by servers which do not trim records for both
+//! windows, and only its first copy is returned.
+//!
+//! ```no_run
+//! use miniseed::fdsn::{DataselectClient, RequestLine};
+//! use miniseed::str_to_utc;
+//!
+//! let client = DataselectClient::new("http://service.iris.edu").unwrap();
+//! let t0 = str_to_utc("2010-02-27T06:30:00").unwrap();
+//! let t1 = str_to_utc("2010-02-27T07:30:00").unwrap();
+//! let lines = vec![
+//!     RequestLine::new("IU", "ANMO", "00", "BH?", &t0, &t1),
+//!     RequestLine::new("IU", "COLA", "00", "BH?", &t0, &t1),
+//! ];
+//! for rec in client.fetch(&lines) {
+//!     println!("{}", rec);
+//! }
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io;
+
+use super::format_time;
+use super::http::{encode, request, Body, Response, Url};
+use header::RawHeader;
+use {ms_record, str_to_utc, RecordReader};
+
+/// Path of the dataselect query method below the base URL
+pub const QUERY_PATH: &str = "/fdsnws/dataselect/1/query";
+
+/// Channel and time window requested
+///
+/// Codes may contain the wildcards `*` and `?`, an empty location is
+/// sent as `--`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestLine {
+    pub network: String,
+    pub station: String,
+    pub location: String,
+    pub channel: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl RequestLine {
+    /// Create a request line
+    pub fn new(
+        net: &str,
+        sta: &str,
+        loc: &str,
+        cha: &str,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> RequestLine {
+        RequestLine {
+            network: net.to_string(),
+            station: sta.to_string(),
+            location: if loc == "--" { "" } else { loc }.to_string(),
+            channel: cha.to_string(),
+            start: *start,
+            end: *end,
+        }
+    }
+    /// Parse a bulk request line, `NET STA LOC CHA START END`
+    ///
+    /// ```
+    /// # use miniseed::fdsn::RequestLine;
+    /// let line = RequestLine::parse("IU ANMO -- BHZ 2010-02-27T06:30:00 2010-02-27T07:30:00").unwrap();
+    /// assert_eq!(line.location, "");
+    /// assert_eq!(line.to_string(), "IU ANMO -- BHZ 2010-02-27T06:30:00.000000 2010-02-27T07:30:00.000000");
+    /// ```
+    pub fn parse(line: &str) -> Option<RequestLine> {
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.len() != 6 {
+            return None;
+        }
+        let start = str_to_utc(v[4])?;
+        let end = str_to_utc(v[5])?;
+        Some(RequestLine::new(v[0], v[1], v[2], v[3], &start, &end))
+    }
+    /// Return the location as sent, `--` if empty
+    pub fn location_code(&self) -> &str {
+        if self.location.is_empty() {
+            "--"
+        } else {
+            &self.location
+        }
+    }
+    /// Split the time window into windows no longer than `span`
+    ///
+    /// The windows meet, so a server which does not trim records returns
+    /// a record spanning an edge for both windows
+    ///
+    /// ```
+    /// # extern crate chrono;
+    /// # extern crate miniseed;
+    /// # use miniseed::fdsn::RequestLine;
+    /// # use chrono::Duration;
+    /// # fn main() {
+    /// let line = RequestLine::parse("IU ANMO 00 BHZ 2010-02-27T00:00:00 2010-02-28T12:00:00").unwrap();
+    /// let v = line.split(Duration::days(1));
+    /// assert_eq!(v.len(), 2);
+    /// assert_eq!(v[0].end, v[1].start);
+    /// assert_eq!(v[1].end, line.end);
+    /// # }
+    /// ```
+    pub fn split(&self, span: Duration) -> Vec<RequestLine> {
+        let mut out = vec![];
+        let mut t = self.start;
+        while t < self.end {
+            let mut line = self.clone();
+            line.start = t;
+            line.end = std::cmp::min(t + span, self.end);
+            t = line.end;
+            out.push(line);
+        }
+        if out.is_empty() {
+            out.push(self.clone());
+        }
+        out
+    }
+}
+
+impl fmt::Display for RequestLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.network,
+            self.station,
+            self.location_code(),
+            self.channel,
+            format_time(&self.start),
+            format_time(&self.end)
+        )
+    }
+}
+
+/// FDSN dataselect web service client
+#[derive(Debug, Clone)]
+pub struct DataselectClient {
+    url: Url,
+    timeout: Option<std::time::Duration>,
+    quality: Option<String>,
+    minimum_length: Option<f64>,
+    longest_only: bool,
+    max_lines: usize,
+    max_span: Option<Duration>,
+}
+
+impl DataselectClient {
+    /// Create a client for the service at `base`, e.g.
+    /// `http://service.iris.edu`
+    pub fn new(base: &str) -> io::Result<DataselectClient> {
+        Ok(DataselectClient {
+            url: Url::parse(base)?,
+            timeout: Some(std::time::Duration::from_secs(60)),
+            quality: None,
+            minimum_length: None,
+            longest_only: false,
+            max_lines: 1000,
+            max_span: None,
+        })
+    }
+    /// Set the connection and read timeout, default 60 seconds
+    pub fn with_timeout(mut self, timeout: Option<std::time::Duration>) -> DataselectClient {
+        self.timeout = timeout;
+        self
+    }
+    /// Request data of a quality, e.g. `D`, `M` or `B` for best
+    pub fn with_quality(mut self, quality: &str) -> DataselectClient {
+        self.quality = Some(quality.to_string());
+        self
+    }
+    /// Only return continuous segments at least `seconds` long
+    pub fn with_minimum_length(mut self, seconds: f64) -> DataselectClient {
+        self.minimum_length = Some(seconds);
+        self
+    }
+    /// Only return the longest continuous segment of each channel
+    pub fn with_longest_only(mut self, longest_only: bool) -> DataselectClient {
+        self.longest_only = longest_only;
+        self
+    }
+    /// Send at most `max_lines` lines in each bulk request, default 1000
+    pub fn with_max_lines(mut self, max_lines: usize) -> DataselectClient {
+        self.max_lines = max_lines.max(1);
+        self
+    }
+    /// Split time windows longer than `span` into separate lines
+    pub fn with_max_span(mut self, span: Duration) -> DataselectClient {
+        self.max_span = Some(span);
+        self
+    }
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let mut v = vec![];
+        if let Some(ref q) = self.quality {
+            v.push(("quality", q.clone()));
+        }
+        if let Some(n) = self.minimum_length {
+            v.push(("minimumlength", n.to_string()));
+        }
+        if self.longest_only {
+            v.push(("longestonly", "true".to_string()));
+        }
+        v
+    }
+    /// Return the path and query of a GET request
+    ///
+    /// ```
+    /// # use miniseed::fdsn::{DataselectClient, RequestLine};
+    /// let c = DataselectClient::new("http://localhost:8080").unwrap().with_quality("B");
+    /// let line = RequestLine::parse("IU ANMO 00 BH? 2010-02-27T06:30:00 2010-02-27T07:30:00").unwrap();
+    /// assert_eq!(
+    ///     c.query_target(&line),
+    ///     "/fdsnws/dataselect/1/query?net=IU&sta=ANMO&loc=00&cha=BH?\
+    ///      &start=2010-02-27T06:30:00.000000&end=2010-02-27T07:30:00.000000&quality=B"
+    /// );
+    /// ```
+    pub fn query_target(&self, line: &RequestLine) -> String {
+        let mut q = format!(
+            "{}{}?net={}&sta={}&loc={}&cha={}&start={}&end={}",
+            self.url.path,
+            QUERY_PATH,
+            encode(&line.network),
+            encode(&line.station),
+            encode(line.location_code()),
+            encode(&line.channel),
+            format_time(&line.start),
+            format_time(&line.end)
+        );
+        for (k, v) in self.options() {
+            q += &format!("&{}={}", k, encode(&v));
+        }
+        q
+    }
+    /// Return the body of a bulk POST request
+    ///
+    /// ```
+    /// # use miniseed::fdsn::{DataselectClient, RequestLine};
+    /// let c = DataselectClient::new("http://localhost:8080").unwrap().with_quality("B");
+    /// let line = RequestLine::parse("IU ANMO 00 BHZ 2010-02-27T06:30:00 2010-02-27T07:30:00").unwrap();
+    /// assert_eq!(
+    ///     c.bulk_body(&[line]),
+    ///     "quality=B\nIU ANMO 00 BHZ 2010-02-27T06:30:00.000000 2010-02-27T07:30:00.000000\n"
+    /// );
+    /// ```
+    pub fn bulk_body(&self, lines: &[RequestLine]) -> String {
+        let mut body = String::new();
+        for (k, v) in self.options() {
+            body += &format!("{}={}\n", k, v);
+        }
+        for line in lines {
+            body += &format!("{}\n", line);
+        }
+        body
+    }
+    fn send_get(&self, line: &RequestLine) -> io::Result<Reply> {
+        let target = self.query_target(line);
+        reply(request(&self.url, "GET", &target, &[], None, self.timeout)?)
+    }
+    fn send_post(&self, lines: &[RequestLine]) -> io::Result<Reply> {
+        let target = format!("{}{}", self.url.path, QUERY_PATH);
+        let body = self.bulk_body(lines);
+        reply(request(
+            &self.url,
+            "POST",
+            &target,
+            &[("Content-Type", "text/plain")],
+            Some(body.as_bytes()),
+            self.timeout,
+        )?)
+    }
+    /// Request a single channel and time window with GET
+    ///
+    /// Returns None if there are no data
+    pub fn get(&self, line: &RequestLine) -> io::Result<Option<RecordReader<Body>>> {
+        self.send_get(line).and_then(Reply::into_option)
+    }
+    /// Request several channels and time windows with a single bulk POST
+    ///
+    /// Returns None if there are no data
+    pub fn post(&self, lines: &[RequestLine]) -> io::Result<Option<RecordReader<Body>>> {
+        self.send_post(lines).and_then(Reply::into_option)
+    }
+    /// Return the requests made for `lines`, split by time window and
+    /// number of lines
+    pub fn requests(&self, lines: &[RequestLine]) -> Vec<Vec<RequestLine>> {
+        let lines: Vec<RequestLine> = match self.max_span {
+            Some(span) => lines.iter().flat_map(|l| l.split(span)).collect(),
+            None => lines.to_vec(),
+        };
+        lines.chunks(self.max_lines).map(|c| c.to_vec()).collect()
+    }
+    /// Fetch the records for `lines`, splitting the request as needed
+    pub fn fetch(&self, lines: &[RequestLine]) -> Fetch {
+        let edges = match self.max_span {
+            Some(span) => lines
+                .iter()
+                .flat_map(|l| l.split(span).into_iter().skip(1).map(|w| w.start))
+                .collect(),
+            None => vec![],
+        };
+        Fetch {
+            client: self.clone(),
+            pending: self.requests(lines).into_iter().collect(),
+            current: None,
+            requests: 0,
+            edges,
+            straddling: HashSet::new(),
+        }
+    }
+}
+
+enum Reply {
+    Data(RecordReader<Body>),
+    NoData,
+    TooLarge,
+}
+
+// Classify a response by status
+fn reply(resp: Response) -> io::Result<Reply> {
+    match resp.status {
+        200 => Ok(Reply::Data(RecordReader::new(resp.body))),
+        204 | 404 => Ok(Reply::NoData),
+        413 => Ok(Reply::TooLarge),
+        code => {
+            let reason = resp.reason.clone();
+            let text = resp.text().unwrap_or_default();
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("FDSN dataselect error {} {}: {}", code, reason, text.trim()),
+            ))
+        }
+    }
+}
+
+impl Reply {
+    fn into_option(self) -> io::Result<Option<RecordReader<Body>>> {
+        match self {
+            Reply::Data(r) => Ok(Some(r)),
+            Reply::NoData => Ok(None),
+            Reply::TooLarge => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FDSN dataselect request too large",
+            )),
+        }
+    }
+}
+
+/// Records of a possibly split request, see DataselectClient::fetch()
+pub struct Fetch {
+    client: DataselectClient,
+    pending: VecDeque<Vec<RequestLine>>,
+    current: Option<RecordReader<Body>>,
+    requests: usize,
+    edges: Vec<DateTime<Utc>>,
+    straddling: HashSet<(String, DateTime<Utc>)>,
+}
+
+impl Fetch {
+    /// Return the number of requests sent so far
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+    // Return true if the record spans the edge between split windows and
+    // was already returned for the window before the edge
+    fn is_repeated(&mut self, rec: &ms_record) -> bool {
+        let h = match RawHeader::parse(rec.raw_bytes()) {
+            Some(h) => h,
+            None => return false,
+        };
+        let (start, end) = (h.start(), h.end());
+        if !self.edges.iter().any(|e| start < *e && *e <= end) {
+            return false;
+        }
+        !self.straddling.insert((h.id(), start))
+    }
+    /// Return the next record
+    ///
+    /// A request rejected as too large is split in two, by lines or by
+    /// time window for a single line.  Records spanning the edge between
+    /// split windows are only returned once.
+    pub fn next_record(&mut self) -> io::Result<Option<ms_record>> {
+        loop {
+            if let Some(ref mut r) = self.current {
+                if let Some(rec) = r.next_record()? {
+                    if self.is_repeated(&rec) {
+                        continue;
+                    }
+                    return Ok(Some(rec));
+                }
+            }
+            self.current = None;
+            let lines = match self.pending.pop_front() {
+                Some(lines) => lines,
+                None => return Ok(None),
+            };
+            self.requests += 1;
+            // Requests of a single line are sent with GET
+            let reply = if lines.len() == 1 {
+                self.client.send_get(&lines[0])?
+            } else {
+                self.client.send_post(&lines)?
+            };
+            match reply {
+                Reply::Data(r) => self.current = Some(r),
+                Reply::NoData => {}
+                Reply::TooLarge => {
+                    let (a, b) = halve(&lines).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("FDSN dataselect request too large: {}", lines[0]),
+                        )
+                    })?;
+                    // A single line is split by time window
+                    if lines.len() == 1 {
+                        self.edges.push(b[0].start);
+                    }
+                    self.pending.push_front(b);
+                    self.pending.push_front(a);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Fetch {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        self.next_record().ok().and_then(|r| r)
+    }
+}
+
+// Split a request in two, None if it cannot be split further
+fn halve(lines: &[RequestLine]) -> Option<(Vec<RequestLine>, Vec<RequestLine>)> {
+    if lines.len() > 1 {
+        let (a, b) = lines.split_at(lines.len() / 2);
+        return Some((a.to_vec(), b.to_vec()));
+    }
+    let line = lines.first()?;
+    let half = (line.end - line.start) / 2;
+    if half < Duration::seconds(1) {
+        return None;
+    }
+    let mut a = line.clone();
+    let mut b = line.clone();
+    a.end = line.start + half;
+    b.start = a.end;
+    Some((vec![a], vec![b]))
+}
@@ -0,0 +1,525 @@
+//! Minimal HTTP/1.1 client and server support
+//!
+//! Only plain `http` URLs are supported.  Each request uses a new
+//! connection which is closed after the response, and response bodies
+//! with a `Content-Length`, chunked transfer encoding or delimited by
+//! the end of the connection are read incrementally.  Servers read a
+//! single request per connection and may stream a chunked response.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Location of a web service, `http://host[:port][/path]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    /// Parse a URL
+    ///
+    /// ```
+    /// # use miniseed::fdsn::http::Url;
+    /// let url = Url::parse("http://localhost:8080/fdsnws/").unwrap();
+    /// assert_eq!(url.host, "localhost");
+    /// assert_eq!(url.port, 8080);
+    /// assert_eq!(url.path, "/fdsnws");
+    /// assert_eq!(Url::parse("service.iris.edu").unwrap().port, 80);
+    /// assert!(Url::parse("https://service.iris.edu").is_err());
+    /// ```
+    pub fn parse(url: &str) -> io::Result<Url> {
+        let bad = |msg: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid URL: {}: {}", msg, url),
+            )
+        };
+        let rest = match url.find("://") {
+            Some(i) if &url[..i] == "http" => &url[i + 3..],
+            Some(_) => return Err(bad("only http is supported")),
+            None => url,
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rfind(':') {
+            Some(i) => (
+                &authority[..i],
+                authority[i + 1..]
+                    .parse()
+                    .map_err(|_| bad("invalid port"))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(bad("missing host"));
+        }
+        Ok(Url {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Percent encode a query parameter value
+///
+/// ```
+/// # use miniseed::fdsn::http::encode;
+/// assert_eq!(encode("BH?,HH*"), "BH?,HH*");
+/// assert_eq!(encode("a b&c"), "a%20b%26c");
+/// ```
+pub fn encode(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => out.push(b as char),
+            b'-' | b'_' | b'.' | b'~' | b',' | b'*' | b'?' | b':' => out.push(b as char),
+            _ => out += &format!("%{:02X}", b),
+        }
+    }
+    out
+}
+
+/// Response to a request, the body is read from the connection
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response {
+    /// Return the value of a header, names are not case sensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+    /// Read the body as text
+    pub fn text(mut self) -> io::Result<String> {
+        let mut buf = vec![];
+        self.body.read_to_end(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Length(u64),
+    Chunked(u64),
+    ChunkedDone,
+    Close,
+}
+
+/// Response body
+pub struct Body {
+    reader: BufReader<TcpStream>,
+    framing: Framing,
+}
+
+// Read a line of at most MAX_LINE bytes, longer lines give an
+// `InvalidInput` error
+fn read_line<R: BufRead>(r: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if r.by_ref().take(MAX_LINE as u64 + 1).read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "HTTP connection closed",
+        ));
+    }
+    if line.len() > MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("HTTP line longer than {} bytes", MAX_LINE),
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+// Read at most MAX_HEADERS headers, more give an `InvalidInput` error
+fn read_headers<R: BufRead>(r: &mut R) -> io::Result<Vec<(String, String)>> {
+    let mut headers = vec![];
+    loop {
+        let line = read_line(r)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("more than {} HTTP headers", MAX_HEADERS),
+            ));
+        }
+        if let Some(i) = line.find(':') {
+            headers.push((
+                line[..i].trim().to_string(),
+                line[i + 1..].trim().to_string(),
+            ));
+        }
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.framing {
+                Framing::Length(0) | Framing::ChunkedDone => return Ok(0),
+                Framing::Length(n) => {
+                    let k = (buf.len() as u64).min(n) as usize;
+                    let got = self.reader.read(&mut buf[..k])?;
+                    if got == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "HTTP body shorter than Content-Length",
+                        ));
+                    }
+                    self.framing = Framing::Length(n - got as u64);
+                    return Ok(got);
+                }
+                Framing::Chunked(0) => {
+                    let line = read_line(&mut self.reader)?;
+                    if line.is_empty() {
+                        // End of the previous chunk
+                        continue;
+                    }
+                    let size = line.split(';').next().unwrap_or("").trim();
+                    let size = u64::from_str_radix(size, 16).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid HTTP chunk size: {}", line),
+                        )
+                    })?;
+                    if size == 0 {
+                        // Skip trailers
+                        while !read_line(&mut self.reader)?.is_empty() {}
+                        self.framing = Framing::ChunkedDone;
+                    } else {
+                        self.framing = Framing::Chunked(size);
+                    }
+                }
+                Framing::Chunked(n) => {
+                    let k = (buf.len() as u64).min(n) as usize;
+                    let got = self.reader.read(&mut buf[..k])?;
+                    if got == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "HTTP chunk incomplete",
+                        ));
+                    }
+                    self.framing = Framing::Chunked(n - got as u64);
+                    return Ok(got);
+                }
+                Framing::Close => return self.reader.read(buf),
+            }
+        }
+    }
+}
+
+/// Send a request and read the response status and headers
+///
+/// `target` is the path and query, `headers` are sent in addition to
+/// `Host`, `Connection` and, if there is a body, `Content-Length`
+pub fn request(
+    url: &Url,
+    method: &str,
+    target: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+    timeout: Option<Duration>,
+) -> io::Result<Response> {
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("host not found: {}", url.host),
+            )
+        })?;
+    let mut stream = match timeout {
+        Some(t) => TcpStream::connect_timeout(&addr, t)?,
+        None => TcpStream::connect(addr)?,
+    };
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let mut req = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\n",
+        method, target, url.host, url.port
+    );
+    for &(k, v) in headers {
+        req += &format!("{}: {}\r\n", k, v);
+    }
+    if let Some(body) = body {
+        req += &format!("Content-Length: {}\r\n", body.len());
+    }
+    req += "\r\n";
+    stream.write_all(req.as_bytes())?;
+    if let Some(body) = body {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let status = read_line(&mut reader)?;
+    let bad = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid HTTP status line: {}", status),
+        )
+    };
+    let mut v = status.splitn(3, ' ');
+    if !v.next().unwrap_or("").starts_with("HTTP/") {
+        return Err(bad());
+    }
+    let code = v.next().and_then(|s| s.parse().ok()).ok_or_else(bad)?;
+    let reason = v.next().unwrap_or("").to_string();
+    let headers = read_headers(&mut reader)?;
+    let mut resp = Response {
+        status: code,
+        reason,
+        headers,
+        body: Body {
+            reader,
+            framing: Framing::Close,
+        },
+    };
+    resp.body.framing = if method == "HEAD" || code == 204 || code == 304 {
+        Framing::Length(0)
+    } else if matches!(resp.header("Transfer-Encoding"), Some(te) if te.eq_ignore_ascii_case("chunked"))
+    {
+        Framing::Chunked(0)
+    } else if let Some(n) = resp.header("Content-Length").and_then(|s| s.parse().ok()) {
+        Framing::Length(n)
+    } else {
+        Framing::Close
+    };
+    Ok(resp)
+}
+
+/// Decode a percent encoded query parameter, `+` is a space
+///
+/// ```
+/// # use miniseed::fdsn::http::decode;
+/// assert_eq!(decode("a%20b+c%2a"), "a b c*");
+/// ```
+pub fn decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        match b[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < b.len() => {
+                let hex = std::str::from_utf8(&b[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(c) => {
+                        out.push(c);
+                        i += 2;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Largest request body read by Request::read, 1 MiB
+pub const MAX_BODY: usize = 1 << 20;
+
+/// Longest request, status or header line read, 8 KiB
+pub const MAX_LINE: usize = 8 << 10;
+
+/// Most headers read in a request or response
+pub const MAX_HEADERS: usize = 100;
+
+/// Status of a request refused by Request::read as too large, carried
+/// by its `InvalidInput` errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TooLarge(pub u16);
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HTTP request refused: {} {}", self.0, reason(self.0))
+    }
+}
+
+impl error::Error for TooLarge {}
+
+// Return `InvalidInput` errors as refused with `status`
+fn too_large(e: io::Error, status: u16) -> io::Error {
+    if e.kind() == io::ErrorKind::InvalidInput {
+        io::Error::new(io::ErrorKind::InvalidInput, TooLarge(status))
+    } else {
+        e
+    }
+}
+
+/// Request received by a server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query
+    pub path: String,
+    /// Decoded query parameters in order
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Read a request with a body of `Content-Length` bytes
+    ///
+    /// Request lines longer than MAX_LINE, header lines longer than
+    /// MAX_LINE or more than MAX_HEADERS headers, and bodies longer than
+    /// MAX_BODY give an `InvalidInput` error holding the TooLarge status
+    /// to answer, 414, 431 or 413
+    pub fn read<R: BufRead>(r: &mut R) -> io::Result<Request> {
+        let line = read_line(r).map_err(|e| too_large(e, 414))?;
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.len() != 3 || !v[2].starts_with("HTTP/") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid HTTP request line: {}", line),
+            ));
+        }
+        let (path, query) = match v[1].find('?') {
+            Some(i) => (&v[1][..i], &v[1][i + 1..]),
+            None => (v[1], ""),
+        };
+        let headers = read_headers(r).map_err(|e| too_large(e, 431))?;
+        let n = find_header(&headers, "Content-Length")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        if n > MAX_BODY {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, TooLarge(413)));
+        }
+        let mut body = vec![0u8; n];
+        r.read_exact(&mut body)?;
+        Ok(Request {
+            method: v[0].to_string(),
+            path: decode(path),
+            query: parse_query(query),
+            headers,
+            body,
+        })
+    }
+    /// Return the value of the first query parameter named any of
+    /// `names`
+    pub fn param(&self, names: &[&str]) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| names.contains(&k.as_str()))
+            .map(|(_, v)| v.as_str())
+    }
+    /// Return the value of a header, names are not case sensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Parse a query string, `k1=v1&k2=v2`
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.find('=') {
+            Some(i) => (decode(&kv[..i]), decode(&kv[i + 1..])),
+            None => (decode(kv), String::new()),
+        })
+        .collect()
+}
+
+/// Return the reason phrase of a status code
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Write a complete response
+pub fn write_response<W: Write>(
+    w: &mut W,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        status,
+        reason(status),
+        body.len()
+    );
+    if !body.is_empty() {
+        head += &format!("Content-Type: {}\r\n", content_type);
+    }
+    head += "\r\n";
+    w.write_all(head.as_bytes())?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+/// Response body sent with chunked transfer encoding
+pub struct ChunkedWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Write the status and headers of a successful response
+    pub fn start(mut writer: W, content_type: &str) -> io::Result<ChunkedWriter<W>> {
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
+            content_type
+        );
+        writer.write_all(head.as_bytes())?;
+        Ok(ChunkedWriter { writer })
+    }
+    /// Write the final empty chunk
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(b"0\r\n\r\n")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.writer
+            .write_all(format!("{:x}\r\n", buf.len()).as_bytes())?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,31 @@
+//! FDSN web service support
+//!
+//! The FDSN dataselect service returns miniSEED for requested channels
+//! and time windows and the availability service the time spans for
+//! which data are available, see <https://www.fdsn.org/webservices/>.
+//! A dataselect client and a server for both services over a local
+//! archive are provided.  Requests are made over plain HTTP and a
+//! dataselect response is read as a stream of records with a
+//! RecordReader.
+
+use chrono::DateTime;
+use chrono::Utc;
+
+pub mod dataselect;
+pub mod http;
+pub mod server;
+
+pub use self::dataselect::{DataselectClient, Fetch, RequestLine};
+pub use self::server::FdsnServer;
+
+/// Format a time as used in FDSN requests, `YYYY-MM-DDThh:mm:ss.ffffff`
+///
+/// ```
+/// # use miniseed::fdsn::format_time;
+/// # use miniseed::str_to_utc;
+/// let t = str_to_utc("2010-02-27T06:30:00.0195").unwrap();
+/// assert_eq!(format_time(&t), "2010-02-27T06:30:00.019500");
+/// ```
+pub fn format_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
@@ -0,0 +1,804 @@
+//! FDSN dataselect and availability server over a local archive
+//!
+//! The server implements the `query` method of fdsnws-dataselect and the
+//! `extent` and `query` methods of fdsnws-availability, with GET and POST
+//! requests, for an Archive such as an SDS archive.  Data are streamed as
+//! they are read with records overlapping the edges of the requested
+//! windows trimmed.  Availability is computed by scanning record headers
+//! only.
+//!
+//! ```no_run
+//! use miniseed::fdsn::FdsnServer;
+//!
+//! let server = FdsnServer::from_sds("/data/sds");
+//! let addr = server.spawn("127.0.0.1:8080").unwrap();
+//! println!("http://{}/fdsnws/dataselect/1/query", addr);
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time;
+
+use super::http::{write_response, ChunkedWriter, Request, TooLarge};
+use archive::Archive;
+use cut::Boundary;
+use header::RawHeader;
+use {ms_record, str_to_utc, RecordReader, Selection, Trace};
+
+/// Version of fdsnws-dataselect implemented
+pub const DATASELECT_VERSION: &str = "1.1.0";
+/// Version of fdsnws-availability implemented
+pub const AVAILABILITY_VERSION: &str = "1.0.0";
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+
+/// Channel and optional time window of a request
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    nslc: [String; 4],
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+/// Parsed request parameters
+#[derive(Debug, Clone, PartialEq)]
+struct Query {
+    lines: Vec<Line>,
+    quality: Option<char>,
+    nodata: u16,
+    format: String,
+    merge_gaps: f64,
+    merge_quality: bool,
+    merge_samplerate: bool,
+    limit: Option<usize>,
+}
+
+type Fail = (u16, String);
+
+fn bad(msg: String) -> Fail {
+    (400, msg)
+}
+
+fn parse_time(s: &str) -> Result<Option<DateTime<Utc>>, Fail> {
+    if s == "*" || s.is_empty() {
+        return Ok(None);
+    }
+    str_to_utc(s)
+        .map(Some)
+        .ok_or_else(|| bad(format!("invalid time: {}", s)))
+}
+
+impl Query {
+    fn new() -> Query {
+        Query {
+            lines: vec![],
+            quality: None,
+            nodata: 204,
+            format: "text".to_string(),
+            merge_gaps: 0.0,
+            merge_quality: false,
+            merge_samplerate: false,
+            limit: None,
+        }
+    }
+    // Apply an option other than the channel and time window
+    fn option(&mut self, key: &str, value: &str) -> Result<(), Fail> {
+        match key {
+            "quality" => {
+                self.quality = match value {
+                    "*" | "B" | "" => None,
+                    v if v.len() == 1 => v.chars().next(),
+                    _ => return Err(bad(format!("invalid quality: {}", value))),
+                }
+            }
+            "nodata" => {
+                self.nodata = match value {
+                    "204" => 204,
+                    "404" => 404,
+                    _ => return Err(bad(format!("invalid nodata: {}", value))),
+                }
+            }
+            "format" => self.format = value.to_string(),
+            "mergegaps" => {
+                self.merge_gaps = value
+                    .parse()
+                    .map_err(|_| bad(format!("invalid mergegaps: {}", value)))?
+            }
+            "merge" => {
+                for m in value.split(',') {
+                    match m {
+                        "quality" => self.merge_quality = true,
+                        "samplerate" => self.merge_samplerate = true,
+                        "overlap" => {}
+                        _ => return Err(bad(format!("invalid merge: {}", m))),
+                    }
+                }
+            }
+            "limit" => {
+                self.limit = Some(
+                    value
+                        .parse()
+                        .map_err(|_| bad(format!("invalid limit: {}", value)))?,
+                )
+            }
+            // Accepted and ignored
+            "minimumlength" | "longestonly" | "orderby" | "includerestricted" | "show" => {}
+            _ => return Err(bad(format!("unsupported parameter: {}", key))),
+        }
+        Ok(())
+    }
+    // Parse a GET query of at most `max_lines` lines
+    fn from_get(req: &Request, max_lines: usize) -> Result<Query, Fail> {
+        let mut q = Query::new();
+        let mut codes: [Vec<String>; 4] = Default::default();
+        let mut start = None;
+        let mut end = None;
+        for (k, v) in &req.query {
+            let i = match k.as_str() {
+                "net" | "network" => 0,
+                "sta" | "station" => 1,
+                "loc" | "location" => 2,
+                "cha" | "channel" => 3,
+                "start" | "starttime" => {
+                    start = parse_time(v)?;
+                    continue;
+                }
+                "end" | "endtime" => {
+                    end = parse_time(v)?;
+                    continue;
+                }
+                _ => {
+                    q.option(k, v)?;
+                    continue;
+                }
+            };
+            codes[i].extend(v.split(',').map(|s| s.trim().to_string()));
+        }
+        for c in codes.iter_mut() {
+            if c.is_empty() {
+                c.push("*".to_string());
+            }
+        }
+        // Every combination of the codes is a line
+        let n = codes.iter().fold(1usize, |n, c| n.saturating_mul(c.len()));
+        if n > max_lines {
+            return Err((413, format!("more than {} request lines", max_lines)));
+        }
+        for net in &codes[0] {
+            for sta in &codes[1] {
+                for loc in &codes[2] {
+                    for cha in &codes[3] {
+                        q.lines.push(Line {
+                            nslc: [net.clone(), sta.clone(), loc.clone(), cha.clone()],
+                            start,
+                            end,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(q)
+    }
+    fn from_post(req: &Request) -> Result<Query, Fail> {
+        let mut q = Query::new();
+        let body = String::from_utf8_lossy(&req.body);
+        for line in body.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(i) = line.find('=') {
+                q.option(line[..i].trim(), line[i + 1..].trim())?;
+                continue;
+            }
+            let v: Vec<&str> = line.split_whitespace().collect();
+            if v.len() != 6 {
+                return Err(bad(format!("invalid request line: {}", line)));
+            }
+            q.lines.push(Line {
+                nslc: [
+                    v[0].to_string(),
+                    v[1].to_string(),
+                    v[2].to_string(),
+                    v[3].to_string(),
+                ],
+                start: parse_time(v[4])?,
+                end: parse_time(v[5])?,
+            });
+        }
+        Ok(q)
+    }
+    // Return the selection of a line
+    fn selection(&self, line: &Line) -> Selection {
+        let loc = if line.nslc[2] == "--" {
+            ""
+        } else {
+            &line.nslc[2]
+        };
+        let pattern = format!("{}_{}_{}_{}", line.nslc[0], line.nslc[1], loc, line.nslc[3]);
+        let mut sel = Selection::new();
+        sel.add_with_quality(&pattern, self.quality, line.start, line.end);
+        sel
+    }
+}
+
+/// Continuous span of data of a channel
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+/// Selection and time window of a dataselect request line
+type Window = (Selection, DateTime<Utc>, DateTime<Utc>);
+
+/// Channel, quality and sample rate of a span
+type Key = (String, String, String, String, String, String);
+
+/// Spans of a channel with the modification time of its newest file
+#[derive(Debug, Clone, Default)]
+struct Extent {
+    spans: Vec<Span>,
+    updated: Option<DateTime<Utc>>,
+}
+
+/// FDSN web service server
+///
+/// Clones share the same archive
+#[derive(Clone)]
+pub struct FdsnServer {
+    archive: Arc<Archive>,
+    trim: bool,
+    max_lines: usize,
+    max_span: Option<Duration>,
+    timeout: time::Duration,
+}
+
+impl FdsnServer {
+    /// Create a server for an archive
+    pub fn new(archive: Archive) -> FdsnServer {
+        FdsnServer {
+            archive: Arc::new(archive),
+            trim: true,
+            max_lines: 1000,
+            max_span: None,
+            timeout: time::Duration::from_secs(60),
+        }
+    }
+    /// Create a server for the SDS archive at `root`
+    pub fn from_sds<S: AsRef<Path>>(root: S) -> FdsnServer {
+        FdsnServer::new(Archive::sds(root))
+    }
+    /// Trim records at the edges of requested windows, default true
+    ///
+    /// Records are otherwise returned whole
+    pub fn with_trim(mut self, trim: bool) -> FdsnServer {
+        self.trim = trim;
+        self
+    }
+    /// Reject requests of more than `max_lines` lines as too large,
+    /// default 1000
+    pub fn with_max_lines(mut self, max_lines: usize) -> FdsnServer {
+        self.max_lines = max_lines;
+        self
+    }
+    /// Reject dataselect requests for windows longer than `span` as too
+    /// large
+    pub fn with_max_span(mut self, span: Duration) -> FdsnServer {
+        self.max_span = Some(span);
+        self
+    }
+    /// Close connections which send no data for `timeout`, default 60 s
+    pub fn with_timeout(mut self, timeout: time::Duration) -> FdsnServer {
+        self.timeout = timeout;
+        self
+    }
+    /// Accept connections, each served by a separate thread
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || server.connection(stream));
+        }
+        Ok(())
+    }
+    /// Bind to `addr` and serve connections in a background thread
+    ///
+    /// Returns the address bound, useful with port 0
+    pub fn spawn(&self, addr: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || server.serve(listener));
+        Ok(local)
+    }
+    fn connection(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let req = match Request::read(&mut reader) {
+            Ok(req) => req,
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => {
+                let status = e
+                    .get_ref()
+                    .and_then(|e| e.downcast_ref::<TooLarge>())
+                    .map_or(413, |s| s.0);
+                return write_response(&mut &stream, status, "text/plain", b"");
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(())
+            }
+            Err(_) => return write_response(&mut &stream, 400, "text/plain", b""),
+        };
+        self.handle(&req, stream)
+    }
+    /// Respond to a request
+    pub fn handle<W: Write>(&self, req: &Request, mut w: W) -> io::Result<()> {
+        let path = req.path.trim_end_matches('/');
+        let (service, method) = match path.rfind('/') {
+            Some(i) => (&path[..i], &path[i + 1..]),
+            None => ("", path),
+        };
+        let version = match service {
+            "/fdsnws/dataselect/1" => DATASELECT_VERSION,
+            "/fdsnws/availability/1" => AVAILABILITY_VERSION,
+            _ => return self.error(&mut w, req, (404, "unknown service".to_string()), ""),
+        };
+        if method == "version" {
+            return write_response(&mut w, 200, "text/plain", version.as_bytes());
+        }
+        let query = match req.method.as_str() {
+            "GET" => Query::from_get(req, self.max_lines),
+            "POST" => Query::from_post(req),
+            _ => Err((405, format!("unsupported method: {}", req.method))),
+        };
+        let query = match query {
+            Ok(q) => q,
+            Err(e) => return self.error(&mut w, req, e, version),
+        };
+        let result = match (service, method) {
+            ("/fdsnws/dataselect/1", "query") => match self.windows(&query) {
+                // Errors once the response is started close the connection
+                Ok(windows) => {
+                    if self.dataselect(&windows, &mut w)? {
+                        Ok(())
+                    } else {
+                        Err((query.nodata, "no data".to_string()))
+                    }
+                }
+                Err(e) => Err(e),
+            },
+            ("/fdsnws/availability/1", "extent") => self.availability(&query, true, &mut w),
+            ("/fdsnws/availability/1", "query") => self.availability(&query, false, &mut w),
+            _ => Err((404, format!("unknown method: {}", method))),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err((204, _)) => write_response(&mut w, 204, "text/plain", b""),
+            Err(e) => self.error(&mut w, req, e, version),
+        }
+    }
+    // Write an error response in the FDSN format
+    fn error<W: Write>(&self, w: &mut W, req: &Request, e: Fail, version: &str) -> io::Result<()> {
+        let body = format!(
+            "Error {}: {}\n\n{}\n\nRequest:\n{}\n\nRequest Submitted:\n{}\n\nService version:\n{}\n",
+            e.0,
+            super::http::reason(e.0),
+            e.1,
+            req.path,
+            Utc::now().format(TIME_FORMAT),
+            version
+        );
+        write_response(w, e.0, "text/plain", body.as_bytes())
+    }
+    fn check_size(&self, q: &Query) -> Result<(), Fail> {
+        if q.lines.len() > self.max_lines {
+            return Err((413, format!("more than {} request lines", self.max_lines)));
+        }
+        Ok(())
+    }
+    // Return the selection and window of each line of a dataselect query
+    fn windows(&self, q: &Query) -> Result<Vec<Window>, Fail> {
+        self.check_size(q)?;
+        let mut windows = vec![];
+        for line in &q.lines {
+            let (start, end) = match (line.start, line.end) {
+                (Some(s), Some(e)) if s < e => (s, e),
+                (Some(_), Some(_)) => return Err(bad("start must be before end".to_string())),
+                _ => return Err(bad("start and end are required".to_string())),
+            };
+            if matches!(self.max_span, Some(span) if end - start > span) {
+                return Err((413, "time window too long".to_string()));
+            }
+            windows.push((q.selection(line), start, end));
+        }
+        Ok(windows)
+    }
+    // Write the records of the windows, returning false if there are none
+    fn dataselect<W: Write>(&self, windows: &[Window], w: &mut W) -> io::Result<bool> {
+        let mut out: Option<ChunkedWriter<&mut W>> = None;
+        let mut w = Some(w);
+        for (sel, start, end) in windows {
+            for input in self.archive.inputs(sel, start, end) {
+                for rec in input.filter(|rec| sel.matches(rec)) {
+                    let bufs = self.trimmed(&rec, start, end);
+                    if bufs.is_empty() {
+                        continue;
+                    }
+                    if out.is_none() {
+                        let writer = w.take().unwrap();
+                        out = Some(ChunkedWriter::start(writer, "application/vnd.fdsn.mseed")?);
+                    }
+                    let out = out.as_mut().unwrap();
+                    for buf in bufs {
+                        out.write_all(&buf)?;
+                    }
+                }
+            }
+        }
+        match out {
+            Some(out) => out.finish().map(|_| true),
+            None => Ok(false),
+        }
+    }
+    // Return the bytes of the record trimmed to [start, end)
+    fn trimmed(&self, rec: &ms_record, start: &DateTime<Utc>, end: &DateTime<Utc>) -> Vec<Vec<u8>> {
+        if !self.trim || (rec.start() >= *start && rec.end() < *end) {
+            return vec![rec.raw_bytes().to_vec()];
+        }
+        let tr = match Trace::from_record(rec) {
+            Some(tr) if tr.samprate > 0.0 => tr,
+            _ => return vec![rec.raw_bytes().to_vec()],
+        };
+        match tr.trim(start, end, Boundary::Inclusive) {
+            Some(tr) => {
+                let enc = if tr.data.can_encode(rec.encoding()) {
+                    rec.encoding()
+                } else {
+                    tr.data.default_encoding()
+                };
+                tr.pack_like(rec, rec.reclen(), enc)
+            }
+            None => vec![],
+        }
+    }
+    // Scan the record headers of the files matching the query
+    fn extents(&self, q: &Query) -> BTreeMap<Key, Extent> {
+        let mut files: Vec<(PathBuf, Selection)> = vec![];
+        for line in &q.lines {
+            let sel = q.selection(line);
+            let paths = match (line.start, line.end) {
+                (Some(s), Some(e)) => self.archive.files(&sel, &s, &e),
+                _ => self.archive.all_files(&sel),
+            };
+            files.extend(paths.into_iter().map(|p| (p, sel.clone())));
+        }
+        let mut out: BTreeMap<Key, Extent> = BTreeMap::new();
+        for (path, sel) in files {
+            let updated = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .map(DateTime::<Utc>::from);
+            let fp = match File::open(&path) {
+                Ok(fp) => fp,
+                Err(_) => continue,
+            };
+            let mut reader = RecordReader::new(BufReader::new(fp));
+            while let Ok(Some(raw)) = reader.read_raw() {
+                let h = match RawHeader::parse(&raw) {
+                    Some(h) => h,
+                    None => continue,
+                };
+                if !sel.matches_header(&h) {
+                    continue;
+                }
+                let (start, end) = (h.start(), h.end());
+                let rate = h.samprate();
+                let end = if rate > 0.0 {
+                    end + Duration::microseconds((1e6 / rate).round() as i64)
+                } else {
+                    end
+                };
+                let key = (
+                    h.network.clone(),
+                    h.station.clone(),
+                    h.location.clone(),
+                    h.channel.clone(),
+                    if q.merge_quality {
+                        String::new()
+                    } else {
+                        h.quality.to_string()
+                    },
+                    if q.merge_samplerate {
+                        String::new()
+                    } else {
+                        rate.to_string()
+                    },
+                );
+                let ext = out.entry(key).or_default();
+                ext.spans.push(Span { start, end });
+                if updated > ext.updated {
+                    ext.updated = updated;
+                }
+            }
+        }
+        for (key, ext) in out.iter_mut() {
+            let rate: f64 = key.5.parse().unwrap_or(0.0);
+            let half = if rate > 0.0 { 0.5 / rate } else { 0.0 };
+            let tol = Duration::microseconds(((half + q.merge_gaps) * 1e6) as i64);
+            ext.spans.sort_by_key(|s| s.start);
+            let mut merged: Vec<Span> = vec![];
+            for s in ext.spans.drain(..) {
+                match merged.last_mut() {
+                    Some(last) if s.start <= last.end + tol => {
+                        if s.end > last.end {
+                            last.end = s.end;
+                        }
+                    }
+                    _ => merged.push(s),
+                }
+            }
+            ext.spans = merged;
+        }
+        out
+    }
+    fn availability<W: Write>(&self, q: &Query, extent: bool, w: &mut W) -> Result<(), Fail> {
+        self.check_size(q)?;
+        let content_type = match q.format.as_str() {
+            "text" | "request" => "text/plain",
+            "json" => "application/json",
+            "geocsv" => "text/csv",
+            f => return Err(bad(format!("unsupported format: {}", f))),
+        };
+        // Clip spans to the requested window, when there is a single one
+        let window = match q.lines.first() {
+            Some(l) if q.lines.iter().all(|m| m.start == l.start && m.end == l.end) => {
+                (l.start, l.end)
+            }
+            _ => (None, None),
+        };
+        let mut rows: Vec<Row> = vec![];
+        for (key, ext) in self.extents(q) {
+            let spans: Vec<Span> = ext
+                .spans
+                .iter()
+                .filter_map(|s| {
+                    let start = match window.0 {
+                        Some(t) if t > s.start => t,
+                        _ => s.start,
+                    };
+                    let end = match window.1 {
+                        Some(t) if t < s.end => t,
+                        _ => s.end,
+                    };
+                    if start < end {
+                        Some(Span { start, end })
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            if spans.is_empty() {
+                continue;
+            }
+            if extent {
+                rows.push(Row {
+                    key,
+                    span: Span {
+                        start: spans[0].start,
+                        end: spans[spans.len() - 1].end,
+                    },
+                    updated: ext.updated,
+                    count: spans.len(),
+                });
+            } else {
+                for span in spans {
+                    rows.push(Row {
+                        key: key.clone(),
+                        span,
+                        updated: ext.updated,
+                        count: 1,
+                    });
+                }
+            }
+        }
+        if let Some(limit) = q.limit {
+            rows.truncate(limit);
+        }
+        if rows.is_empty() {
+            return Err((q.nodata, "no data".to_string()));
+        }
+        let body = match q.format.as_str() {
+            "json" => format_json(&rows, q, extent),
+            "geocsv" => format_table(&rows, q, extent, true),
+            "request" => rows
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{} {} {} {} {} {}\n",
+                        r.key.0,
+                        r.key.1,
+                        if r.key.2.is_empty() { "--" } else { &r.key.2 },
+                        r.key.3,
+                        r.span.start.format(TIME_FORMAT),
+                        r.span.end.format(TIME_FORMAT)
+                    )
+                })
+                .collect(),
+            _ => format_table(&rows, q, extent, false),
+        };
+        write_response(w, 200, content_type, body.as_bytes()).map_err(|e| (500, e.to_string()))
+    }
+}
+
+/// Row of an availability response
+struct Row {
+    key: Key,
+    span: Span,
+    updated: Option<DateTime<Utc>>,
+    count: usize,
+}
+
+// Return the column names and values of the rows
+fn columns(rows: &[Row], q: &Query, extent: bool) -> (Vec<&'static str>, Vec<Vec<String>>) {
+    let mut names = vec!["Network", "Station", "Location", "Channel"];
+    if !q.merge_quality {
+        names.push("Quality");
+    }
+    if !q.merge_samplerate {
+        names.push("SampleRate");
+    }
+    names.push("Earliest");
+    names.push("Latest");
+    if extent {
+        names.push("Updated");
+        names.push("TimeSpans");
+        names.push("Restriction");
+    }
+    let values = rows
+        .iter()
+        .map(|r| {
+            let mut v = vec![
+                r.key.0.clone(),
+                r.key.1.clone(),
+                r.key.2.clone(),
+                r.key.3.clone(),
+            ];
+            if !q.merge_quality {
+                v.push(r.key.4.clone());
+            }
+            if !q.merge_samplerate {
+                v.push(r.key.5.clone());
+            }
+            v.push(r.span.start.format(TIME_FORMAT).to_string());
+            v.push(r.span.end.format(TIME_FORMAT).to_string());
+            if extent {
+                v.push(
+                    r.updated
+                        .map(|t| t.format(TIME_FORMAT).to_string())
+                        .unwrap_or_default(),
+                );
+                v.push(r.count.to_string());
+                v.push("OPEN".to_string());
+            }
+            v
+        })
+        .collect();
+    (names, values)
+}
+
+fn format_table(rows: &[Row], q: &Query, extent: bool, geocsv: bool) -> String {
+    let (names, values) = columns(rows, q, extent);
+    let mut out = String::new();
+    if geocsv {
+        let types: Vec<&str> = names
+            .iter()
+            .map(|n| match *n {
+                "SampleRate" => "float",
+                "TimeSpans" => "integer",
+                "Earliest" | "Latest" | "Updated" => "datetime",
+                _ => "string",
+            })
+            .collect();
+        let units: Vec<&str> = names
+            .iter()
+            .map(|n| match *n {
+                "SampleRate" => "hertz",
+                "Earliest" | "Latest" | "Updated" => "ISO_8601",
+                _ => "unitless",
+            })
+            .collect();
+        out += "#dataset: GeoCSV 2.0\n#delimiter: |\n";
+        out += &format!("#field_unit: {}\n", units.join("|"));
+        out += &format!("#field_type: {}\n", types.join("|"));
+        out += &names.join("|");
+        out += "\n";
+        for v in values {
+            out += &v.join("|");
+            out += "\n";
+        }
+    } else {
+        out += &format!("#{}\n", names.join(" "));
+        for mut v in values {
+            if v[2].is_empty() {
+                v[2] = "--".to_string();
+            }
+            out += &v.join(" ");
+            out += "\n";
+        }
+    }
+    out
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn format_json(rows: &[Row], q: &Query, extent: bool) -> String {
+    let time = |t: &DateTime<Utc>| json_str(&t.format(TIME_FORMAT).to_string());
+    let mut sources: Vec<String> = vec![];
+    let mut i = 0;
+    while i < rows.len() {
+        // Rows of the same channel are grouped for query responses
+        let mut j = i + 1;
+        while !extent && j < rows.len() && rows[j].key == rows[i].key {
+            j += 1;
+        }
+        let r = &rows[i];
+        let mut fields = vec![
+            format!("\"network\":{}", json_str(&r.key.0)),
+            format!("\"station\":{}", json_str(&r.key.1)),
+            format!("\"location\":{}", json_str(&r.key.2)),
+            format!("\"channel\":{}", json_str(&r.key.3)),
+        ];
+        if !q.merge_quality {
+            fields.push(format!("\"quality\":{}", json_str(&r.key.4)));
+        }
+        if !q.merge_samplerate {
+            fields.push(format!("\"samplerate\":{}", r.key.5));
+        }
+        if extent {
+            fields.push(format!("\"earliest\":{}", time(&r.span.start)));
+            fields.push(format!("\"latest\":{}", time(&r.span.end)));
+            if let Some(ref t) = r.updated {
+                fields.push(format!("\"updated\":{}", time(t)));
+            }
+            fields.push(format!("\"timespanCount\":{}", r.count));
+            fields.push("\"restriction\":\"OPEN\"".to_string());
+        } else {
+            let spans: Vec<String> = rows[i..j]
+                .iter()
+                .map(|r| format!("[{},{}]", time(&r.span.start), time(&r.span.end)))
+                .collect();
+            fields.push(format!("\"timespans\":[{}]", spans.join(",")));
+        }
+        sources.push(format!("{{{}}}", fields.join(",")));
+        i = j;
+    }
+    format!(
+        "{{\"created\":{},\"version\":1.0,\"datasources\":[{}]}}\n",
+        time(&Utc::now()),
+        sources.join(",")
+    )
+}
@@ -0,0 +1,248 @@
+//! Following a growing file
+//!
+//! `Follow` reads the records of a file and, at the end of the file,
+//! polls for more data like `tail -f`.  Only complete records are
+//! returned, so a record being written is returned once all its bytes
+//! are in the file.  If the file is truncated it is read again from
+//! the start, and if it is replaced, e.g. by log rotation, the rest of
+//! the old file is read before the new file is read from the start.
+//!
+//! Records are framed with reader::Framer.  A record without a
+//! blockette 1000 is only known to be complete once the next record
+//! starts, so the last such record is held back until then.
+//!
+//! ```no_run
+//! use miniseed::ms_input;
+//!
+//! for rec in ms_input::open("live.mseed").follow().from_end() {
+//!     println!("{}", rec);
+//! }
+//! ```
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reader::Framer;
+use {ms_record, Selection};
+
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Records read from a file as it grows
+pub struct Follow {
+    path: PathBuf,
+    file: Option<File>,
+    id: Option<(u64, u64)>,
+    framer: Framer,
+    selection: Option<Selection>,
+    start: u64,
+    base: u64,
+    pos: u64,
+    from_end: bool,
+    poll: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl Follow {
+    /// Follow the file at `path` from its start
+    ///
+    /// The file need not exist yet
+    pub fn new<S: AsRef<Path>>(path: S) -> Follow {
+        Follow {
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            id: None,
+            framer: Framer::new(),
+            selection: None,
+            start: 0,
+            base: 0,
+            pos: 0,
+            from_end: false,
+            poll: Duration::from_millis(500),
+            idle_timeout: None,
+        }
+    }
+    /// Only return records matching the selection
+    ///
+    /// Record headers are checked before the records are parsed
+    pub fn with_selection(mut self, selection: Selection) -> Follow {
+        self.framer = Framer::new().with_selection(selection.clone());
+        self.selection = Some(selection);
+        self
+    }
+    /// Start reading at byte `offset`, which must be the start of a
+    /// record
+    pub fn with_offset(mut self, offset: u64) -> Follow {
+        self.start = offset;
+        self.from_end = false;
+        self
+    }
+    /// Start reading at the current end of the file, returning only
+    /// records added from now on
+    ///
+    /// The file should end on a record boundary.  A file which does not
+    /// exist yet is read from its start once created.
+    pub fn from_end(mut self) -> Follow {
+        self.from_end = true;
+        self
+    }
+    /// Check the file for new data every `interval`, 500 ms by default
+    pub fn with_poll_interval(mut self, interval: Duration) -> Follow {
+        self.poll = interval;
+        self
+    }
+    /// Stop after `timeout` without new data instead of waiting forever
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Follow {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    /// Return the path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Return the byte offset in the current file of the end of the last
+    /// record returned
+    pub fn offset(&self) -> u64 {
+        self.base + self.framer.offset()
+    }
+    // Open the file, at `offset` or the end, returning false if it does
+    // not exist yet
+    fn open(&mut self, offset: Option<u64>) -> io::Result<bool> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let meta = file.metadata()?;
+        let pos = match offset {
+            Some(n) => n.min(meta.len()),
+            None => meta.len(),
+        };
+        file.seek(SeekFrom::Start(pos))?;
+        self.id = file_id(&meta);
+        self.file = Some(file);
+        self.restart(pos);
+        Ok(true)
+    }
+    // Discard buffered bytes and continue from `pos` in the current file
+    fn restart(&mut self, pos: u64) {
+        self.framer = match self.selection {
+            Some(ref sel) => Framer::new().with_selection(sel.clone()),
+            None => Framer::new(),
+        };
+        self.base = pos;
+        self.pos = pos;
+    }
+    // Check for a truncated or replaced file at the end of the current
+    // file, returning true if reading should continue without waiting
+    fn check(&mut self) -> io::Result<bool> {
+        let meta = match fs::metadata(&self.path) {
+            Ok(meta) => meta,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let id = file_id(&meta);
+        if id.is_some() && id != self.id {
+            if !self.framer.is_finished() {
+                // Return the records held back at the end of the old file
+                // before switching
+                self.framer.finish();
+                return Ok(true);
+            }
+            return self.open(Some(0));
+        }
+        if meta.len() < self.pos {
+            if let Some(ref mut file) = self.file {
+                file.seek(SeekFrom::Start(0))?;
+            }
+            self.restart(0);
+            return Ok(true);
+        }
+        Ok(meta.len() > self.pos)
+    }
+    /// Return the bytes of the next record, waiting for it to be written
+    ///
+    /// Returns None if an idle timeout is set and no record is written
+    /// before it expires
+    pub fn read_raw(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut last = Instant::now();
+        loop {
+            match self.framer.next_raw() {
+                Ok(Some(raw)) => return Ok(Some(raw)),
+                Ok(None) => {}
+                Err(e) => {
+                    let pos = self.pos;
+                    self.restart(pos);
+                    return Err(e);
+                }
+            }
+            let n = match self.file {
+                Some(ref mut file) => {
+                    let mut tmp = [0u8; 8192];
+                    match file.read(&mut tmp) {
+                        Ok(n) => {
+                            self.framer.extend(&tmp[..n]);
+                            n
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e),
+                    }
+                }
+                None => {
+                    let offset = if self.from_end {
+                        None
+                    } else {
+                        Some(self.start)
+                    };
+                    if self.open(offset)? {
+                        continue;
+                    }
+                    // A file created later is read from its start
+                    self.from_end = false;
+                    self.start = 0;
+                    0
+                }
+            };
+            if n > 0 {
+                self.pos += n as u64;
+                last = Instant::now();
+                continue;
+            }
+            if self.file.is_some() && self.check()? {
+                continue;
+            }
+            if let Some(timeout) = self.idle_timeout {
+                if last.elapsed() >= timeout {
+                    return Ok(None);
+                }
+            }
+            thread::sleep(self.poll);
+        }
+    }
+    /// Return the next record, waiting for it to be written
+    pub fn next_record(&mut self) -> io::Result<Option<ms_record>> {
+        self.read_raw()?
+            .map(|raw| ms_record::try_parse(&raw))
+            .transpose()
+    }
+}
+
+impl Iterator for Follow {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        self.next_record().ok().and_then(|r| r)
+    }
+}
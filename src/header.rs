@@ -0,0 +1,327 @@
+//! Raw record header parsing
+//!
+//! Parse the fixed section of data header and blockettes 100, 1000 and
+//! 1001 directly from record bytes without decoding the data samples.
+//! This is used for fast scanning and for framing records in a byte
+//! stream.
+//!
+//! ```
+//! use miniseed::header::RawHeader;
+//! use std::fs::File;
+//! use std::io::Read;
+//!
+//! let mut buf = vec![];
+//! File::open("tests/sample.miniseed").unwrap().read_to_end(&mut buf).unwrap();
+//! let hdr = RawHeader::parse(&buf).unwrap();
+//! assert_eq!(hdr.id(), "PN_PPNAF_00_HHZ");
+//! assert_eq!(hdr.reclen, Some(512));
+//! assert_eq!(hdr.samprate(), 100.0);
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::TimeZone;
+use chrono::Utc;
+
+/// Length of the fixed section of data header
+pub const FSDH_LEN: usize = 48;
+/// Smallest supported record length
+pub const MIN_RECLEN: usize = 128;
+/// Largest supported record length
+pub const MAX_RECLEN: usize = 1048576;
+
+/// Fixed section of data header and selected blockette values
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawHeader {
+    pub sequence_number: i32,
+    pub quality: char,
+    pub network: String,
+    pub station: String,
+    pub location: String,
+    pub channel: String,
+    pub year: u16,
+    pub day: u16,
+    pub hour: u8,
+    pub min: u8,
+    pub sec: u8,
+    /// Fractional seconds in 0.0001 seconds
+    pub fract: u16,
+    pub numsamples: u16,
+    pub samprate_fact: i16,
+    pub samprate_mult: i16,
+    pub act_flags: u8,
+    pub io_flags: u8,
+    pub dq_flags: u8,
+    pub numblockettes: u8,
+    /// Time correction in 0.0001 seconds
+    pub time_correct: i32,
+    pub data_offset: u16,
+    pub blockette_offset: u16,
+    /// True if the header is little endian
+    pub swapped: bool,
+    /// Actual sample rate from blockette 100
+    pub b100_samprate: Option<f32>,
+    /// Data encoding from blockette 1000
+    pub encoding: Option<u8>,
+    /// Record length from blockette 1000
+    pub reclen: Option<usize>,
+    /// Microseconds from blockette 1001
+    pub b1001_usec: Option<i8>,
+    /// Timing quality from blockette 1001
+    pub timing_quality: Option<u8>,
+}
+
+fn u16_at(buf: &[u8], i: usize, swap: bool) -> u16 {
+    let v = [buf[i], buf[i + 1]];
+    if swap {
+        u16::from_le_bytes(v)
+    } else {
+        u16::from_be_bytes(v)
+    }
+}
+
+fn u32_at(buf: &[u8], i: usize, swap: bool) -> u32 {
+    let v = [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+    if swap {
+        u32::from_le_bytes(v)
+    } else {
+        u32::from_be_bytes(v)
+    }
+}
+
+fn code(buf: &[u8]) -> String {
+    buf.iter()
+        .filter(|&&c| c != 0)
+        .map(|&c| c as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn valid_btime(year: u16, day: u16, hour: u8, min: u8, sec: u8) -> bool {
+    (1900..=2100).contains(&year)
+        && (1..=366).contains(&day)
+        && hour <= 23
+        && min <= 59
+        && sec <= 60
+}
+
+/// Return true if the buffer begins with a plausible fixed header
+///
+/// The sequence number must be digits, spaces or nulls, the quality one
+/// of `D`, `R`, `Q` or `M`, and the start time valid in either byte order
+pub fn is_header(buf: &[u8]) -> bool {
+    if buf.len() < FSDH_LEN {
+        return false;
+    }
+    if !buf[..6]
+        .iter()
+        .all(|&c| (c as char).is_ascii_digit() || c == b' ' || c == 0)
+    {
+        return false;
+    }
+    if !b"DRQM".contains(&buf[6]) || !(buf[7] == b' ' || buf[7] == 0) {
+        return false;
+    }
+    detect_swap(buf).is_some()
+}
+
+fn detect_swap(buf: &[u8]) -> Option<bool> {
+    for &swap in &[false, true] {
+        let year = u16_at(buf, 20, swap);
+        let day = u16_at(buf, 22, swap);
+        if valid_btime(year, day, buf[24], buf[25], buf[26]) {
+            return Some(swap);
+        }
+    }
+    None
+}
+
+impl RawHeader {
+    /// Parse a record header from the start of a buffer
+    ///
+    /// Returns None if the buffer does not begin with a valid fixed header.
+    /// Blockettes beyond the end of the buffer are ignored.
+    pub fn parse(buf: &[u8]) -> Option<RawHeader> {
+        if !is_header(buf) {
+            return None;
+        }
+        let swap = detect_swap(buf)?;
+        let seq = code(&buf[0..6]);
+        let mut h = RawHeader {
+            sequence_number: seq.parse().unwrap_or(0),
+            quality: buf[6] as char,
+            station: code(&buf[8..13]),
+            location: code(&buf[13..15]),
+            channel: code(&buf[15..18]),
+            network: code(&buf[18..20]),
+            year: u16_at(buf, 20, swap),
+            day: u16_at(buf, 22, swap),
+            hour: buf[24],
+            min: buf[25],
+            sec: buf[26],
+            fract: u16_at(buf, 28, swap),
+            numsamples: u16_at(buf, 30, swap),
+            samprate_fact: u16_at(buf, 32, swap) as i16,
+            samprate_mult: u16_at(buf, 34, swap) as i16,
+            act_flags: buf[36],
+            io_flags: buf[37],
+            dq_flags: buf[38],
+            numblockettes: buf[39],
+            time_correct: u32_at(buf, 40, swap) as i32,
+            data_offset: u16_at(buf, 44, swap),
+            blockette_offset: u16_at(buf, 46, swap),
+            swapped: swap,
+            b100_samprate: None,
+            encoding: None,
+            reclen: None,
+            b1001_usec: None,
+            timing_quality: None,
+        };
+        for (btype, off) in h.blockettes(buf) {
+            match btype {
+                100 if off + 8 <= buf.len() => {
+                    let v = u32_at(buf, off + 4, swap);
+                    h.b100_samprate = Some(f32::from_bits(v));
+                }
+                1000 if off + 8 <= buf.len() => {
+                    h.encoding = Some(buf[off + 4]);
+                    let exp = buf[off + 6] as u32;
+                    if exp < 32 {
+                        h.reclen = Some(1usize << exp);
+                    }
+                }
+                1001 if off + 8 <= buf.len() => {
+                    h.timing_quality = Some(buf[off + 4]);
+                    h.b1001_usec = Some(buf[off + 5] as i8);
+                }
+                _ => {}
+            }
+        }
+        Some(h)
+    }
+    /// Return the type and offset of each blockette within the buffer
+    pub fn blockettes(&self, buf: &[u8]) -> Vec<(u16, usize)> {
+        let mut out = vec![];
+        let mut off = self.blockette_offset as usize;
+        let mut n = 0;
+        while off >= FSDH_LEN && off + 4 <= buf.len() && n < self.numblockettes.max(1) as usize * 2
+        {
+            let btype = u16_at(buf, off, self.swapped);
+            let next = u16_at(buf, off + 2, self.swapped) as usize;
+            out.push((btype, off));
+            if next <= off {
+                break;
+            }
+            off = next;
+            n += 1;
+        }
+        out
+    }
+    /// Return the identifier `NET_STA_LOC_CHA`
+    pub fn id(&self) -> String {
+        format!(
+            "{}_{}_{}_{}",
+            self.network, self.station, self.location, self.channel
+        )
+    }
+    /// Return the nominal sample rate, or the blockette 100 rate if present
+    pub fn samprate(&self) -> f64 {
+        if let Some(r) = self.b100_samprate {
+            return r as f64;
+        }
+        let f = self.samprate_fact as f64;
+        let m = self.samprate_mult as f64;
+        match (self.samprate_fact, self.samprate_mult) {
+            (0, _) | (_, 0) => 0.0,
+            (a, b) if a > 0 && b > 0 => f * m,
+            (a, _) if a > 0 => -f / m,
+            (_, b) if b > 0 => -m / f,
+            _ => 1.0 / (f * m),
+        }
+    }
+    /// Return the start time from the header BTime, see ms_record::start()
+    pub fn start(&self) -> DateTime<Utc> {
+        let d = NaiveDate::from_yo_opt(self.year as i32, self.day as u32)
+            .and_then(|d| {
+                d.and_hms_micro_opt(
+                    self.hour as u32,
+                    self.min as u32,
+                    self.sec.min(59) as u32,
+                    self.fract as u32 * 100,
+                )
+            })
+            .unwrap_or_else(|| {
+                NaiveDate::from_ymd_opt(1970, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            });
+        let t = Utc.from_utc_datetime(&d);
+        if self.sec == 60 {
+            t + Duration::seconds(1)
+        } else {
+            t
+        }
+    }
+    /// Return the start time including blockette 1001 microseconds and
+    /// the time correction, if it has not been applied
+    pub fn corrected_start(&self) -> DateTime<Utc> {
+        let mut t = self.start();
+        if let Some(us) = self.b1001_usec {
+            t += Duration::microseconds(us as i64);
+        }
+        if self.act_flags & 0x02 == 0 && self.time_correct != 0 {
+            t += Duration::microseconds(self.time_correct as i64 * 100);
+        }
+        t
+    }
+    /// Return the time of the last sample
+    pub fn end(&self) -> DateTime<Utc> {
+        let rate = self.samprate();
+        let n = self.numsamples.max(1) as f64;
+        if rate <= 0.0 {
+            return self.start();
+        }
+        let dt = 1.0 / rate;
+        self.start() + Duration::microseconds(((n - 1.0) * dt * 1e6) as i64)
+    }
+}
+
+/// Return the length of the record at the start of the buffer
+///
+/// The length is taken from blockette 1000 if present.  Otherwise the
+/// buffer is searched for the next record header at power of two
+/// offsets, and the remaining length of the buffer is returned if
+/// `complete` is true and no following header is found.  Returns None if
+/// the buffer does not begin with a record header or more data are needed.
+///
+/// ```
+/// # use miniseed::header::record_length;
+/// # use std::io::Read;
+/// let mut buf = vec![];
+/// std::fs::File::open("tests/multiple.seed").unwrap().read_to_end(&mut buf).unwrap();
+/// assert_eq!(record_length(&buf, false), Some(512));
+/// assert_eq!(record_length(&buf[..100], false), Some(512));
+/// assert_eq!(record_length(&buf[1..], true), None);
+/// ```
+pub fn record_length(buf: &[u8], complete: bool) -> Option<usize> {
+    let h = RawHeader::parse(buf)?;
+    if let Some(n) = h.reclen {
+        if (MIN_RECLEN..=MAX_RECLEN).contains(&n) {
+            return Some(n);
+        }
+    }
+    let mut n = MIN_RECLEN;
+    while n <= MAX_RECLEN && n < buf.len() {
+        if is_header(&buf[n..]) {
+            return Some(n);
+        }
+        n *= 2;
+    }
+    if complete && (MIN_RECLEN..=MAX_RECLEN).contains(&buf.len()) {
+        return Some(buf.len());
+    }
+    None
+}
@@ -0,0 +1,300 @@
+//! Record index for random access into large files
+//!
+//! An index is built by scanning the record headers of a file once,
+//! without decoding data samples.  It may be saved to a sidecar file,
+//! `FILE.idx`, and records overlapping a query are then read directly
+//! from their byte offsets.
+//!
+//! ```
+//! use miniseed::index::MsIndex;
+//! use miniseed::{str_to_utc, Selection};
+//!
+//! let idx = MsIndex::build("tests/multiple.seed").unwrap();
+//! assert_eq!(idx.len(), 1243);
+//!
+//! let mut sel = Selection::new();
+//! sel.add("IU_ANMO_00_BHZ", str_to_utc("2010-02-27T06:30:00"), str_to_utc("2010-02-27T06:31:00"));
+//! let recs = idx.records(&sel).unwrap();
+//! assert_eq!(recs.len(), 4);
+//! ```
+//!
+//! Index entries may also be used to position an `ms_input`:
+//!
+//! ```
+//! # use miniseed::index::MsIndex;
+//! use miniseed::ms_input;
+//!
+//! let idx = MsIndex::build("tests/multiple.seed").unwrap();
+//! let mut input = ms_input::open("tests/multiple.seed");
+//! input.seek_to_offset(idx.entries()[100].offset);
+//! assert_eq!(input.next().unwrap().start(), idx.entries()[100].start);
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use header::{record_length, RawHeader, MAX_RECLEN, MIN_RECLEN};
+use {ms_record, str_to_utc, Selection};
+
+const INDEX_VERSION: &str = "# miniseed index 1";
+
+/// Index entry for a single record
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    /// Byte offset of the record within the file
+    pub offset: u64,
+    /// Record length in bytes
+    pub reclen: usize,
+    /// Identifier `NET_STA_LOC_CHA`
+    pub id: String,
+    pub quality: char,
+    /// Time of the first sample
+    pub start: DateTime<Utc>,
+    /// Time of the last sample
+    pub end: DateTime<Utc>,
+    /// Number of samples
+    pub nsamples: usize,
+}
+
+impl IndexEntry {
+    /// Return true if the entry matches the selection
+    pub fn matches(&self, sel: &Selection) -> bool {
+        let v: Vec<&str> = self.id.split('_').collect();
+        if v.len() != 4 {
+            return false;
+        }
+        sel.matches_span(
+            [v[0], v[1], v[2], v[3]],
+            self.quality,
+            &self.start,
+            &self.end,
+        )
+    }
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} {} {} {}",
+            self.offset,
+            self.reclen,
+            self.id,
+            self.quality,
+            self.start.format("%Y-%m-%dT%H:%M:%S%.6f"),
+            self.end.format("%Y-%m-%dT%H:%M:%S%.6f"),
+            self.nsamples
+        )
+    }
+    fn from_line(line: &str) -> Option<IndexEntry> {
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.len() != 7 {
+            return None;
+        }
+        Some(IndexEntry {
+            offset: v[0].parse().ok()?,
+            reclen: v[1]
+                .parse()
+                .ok()
+                .filter(|n| (MIN_RECLEN..=MAX_RECLEN).contains(n))?,
+            id: v[2].to_string(),
+            quality: v[3].chars().next()?,
+            start: str_to_utc(v[4])?,
+            end: str_to_utc(v[5])?,
+            nsamples: v[6].parse().ok()?,
+        })
+    }
+}
+
+// Return the size and modification time of a file, in nanoseconds
+// since 1970 or 0 if unknown
+fn stamp(md: &fs::Metadata) -> (u64, u64) {
+    let mtime = md
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64);
+    (md.len(), mtime)
+}
+
+/// Record index of a single file
+#[derive(Debug, Clone)]
+pub struct MsIndex {
+    file: PathBuf,
+    size: u64,
+    mtime: u64,
+    entries: Vec<IndexEntry>,
+}
+
+impl MsIndex {
+    /// Build an index by scanning the record headers of a file
+    ///
+    /// Data which are not miniSEED records are skipped
+    pub fn build<S: AsRef<Path>>(file: S) -> io::Result<MsIndex> {
+        let path = file.as_ref().to_path_buf();
+        let mut fp = File::open(&path)?;
+        let (size, mtime) = stamp(&fp.metadata()?);
+        let mut entries = vec![];
+        let mut offset = 0u64;
+        let mut buf = vec![];
+        while offset + (MIN_RECLEN as u64) <= size {
+            fp.seek(SeekFrom::Start(offset))?;
+            buf.clear();
+            (&mut fp).take(256).read_to_end(&mut buf)?;
+            let hdr = match RawHeader::parse(&buf) {
+                Some(h) => h,
+                None => {
+                    offset += MIN_RECLEN as u64;
+                    continue;
+                }
+            };
+            let reclen = match hdr.reclen {
+                Some(n) if (MIN_RECLEN..=MAX_RECLEN).contains(&n) => n,
+                Some(_) => {
+                    offset += MIN_RECLEN as u64;
+                    continue;
+                }
+                None => {
+                    // No blockette 1000, search for the next header
+                    fp.seek(SeekFrom::Start(offset))?;
+                    buf.clear();
+                    (&mut fp).take(8192 + 48).read_to_end(&mut buf)?;
+                    let complete = offset + buf.len() as u64 >= size;
+                    match record_length(&buf, complete) {
+                        Some(n) => n,
+                        None => {
+                            offset += MIN_RECLEN as u64;
+                            continue;
+                        }
+                    }
+                }
+            };
+            entries.push(IndexEntry {
+                offset,
+                reclen,
+                id: hdr.id(),
+                quality: hdr.quality,
+                start: hdr.start(),
+                end: hdr.end(),
+                nsamples: hdr.numsamples as usize,
+            });
+            offset += reclen as u64;
+        }
+        Ok(MsIndex {
+            file: path,
+            size,
+            mtime,
+            entries,
+        })
+    }
+    /// Return the sidecar index file for a data file, `FILE.idx`
+    pub fn sidecar<S: AsRef<Path>>(file: S) -> PathBuf {
+        let mut s = file.as_ref().as_os_str().to_os_string();
+        s.push(".idx");
+        PathBuf::from(s)
+    }
+    /// Write the index to the sidecar file
+    pub fn save(&self) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(MsIndex::sidecar(&self.file))?);
+        writeln!(out, "{} {} {}", INDEX_VERSION, self.size, self.mtime)?;
+        for e in &self.entries {
+            writeln!(out, "{}", e.to_line())?;
+        }
+        out.flush()
+    }
+    /// Read the index of a data file from its sidecar file
+    pub fn load<S: AsRef<Path>>(file: S) -> io::Result<MsIndex> {
+        let path = file.as_ref().to_path_buf();
+        let fp = BufReader::new(File::open(MsIndex::sidecar(&path))?);
+        let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut lines = fp.lines();
+        let first = lines.next().unwrap_or(Ok(String::new()))?;
+        if !first.starts_with(INDEX_VERSION) {
+            return Err(bad("unknown index format"));
+        }
+        let v: Vec<u64> = first[INDEX_VERSION.len()..]
+            .split_whitespace()
+            .map(|s| s.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| bad("invalid index file size or time"))?;
+        let (size, mtime) = match v[..] {
+            [size, mtime] => (size, mtime),
+            _ => return Err(bad("invalid index file size or time")),
+        };
+        let mut entries = vec![];
+        for line in lines {
+            let line = line?;
+            entries.push(IndexEntry::from_line(&line).ok_or_else(|| bad("invalid index entry"))?);
+        }
+        Ok(MsIndex {
+            file: path,
+            size,
+            mtime,
+            entries,
+        })
+    }
+    /// Load the sidecar index if it is current, otherwise build and save
+    /// a new index
+    ///
+    /// An index is current if it records the same file size and
+    /// modification time as the data file
+    pub fn open<S: AsRef<Path>>(file: S) -> io::Result<MsIndex> {
+        let (size, mtime) = stamp(&fs::metadata(&file)?);
+        if let Ok(idx) = MsIndex::load(&file) {
+            if idx.size == size && idx.mtime == mtime {
+                return Ok(idx);
+            }
+        }
+        let idx = MsIndex::build(&file)?;
+        idx.save()?;
+        Ok(idx)
+    }
+    /// Return the indexed data file
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+    /// Return the index entries in file order
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+    /// Return the number of indexed records
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Return true if no records were indexed
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Return the entries matching the selection
+    pub fn query(&self, sel: &Selection) -> Vec<&IndexEntry> {
+        self.entries.iter().filter(|e| e.matches(sel)).collect()
+    }
+    /// Read the raw bytes of an indexed record
+    pub fn read_bytes(&self, entry: &IndexEntry) -> io::Result<Vec<u8>> {
+        let mut fp = File::open(&self.file)?;
+        fp.seek(SeekFrom::Start(entry.offset))?;
+        let mut buf = vec![0u8; entry.reclen];
+        fp.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+    /// Read and decode an indexed record
+    pub fn read(&self, entry: &IndexEntry) -> io::Result<ms_record> {
+        Ok(ms_record::parse(&self.read_bytes(entry)?))
+    }
+    /// Read and decode all records matching the selection
+    pub fn records(&self, sel: &Selection) -> io::Result<Vec<ms_record>> {
+        let mut fp = File::open(&self.file)?;
+        let mut out = vec![];
+        for e in self.query(sel) {
+            fp.seek(SeekFrom::Start(e.offset))?;
+            let mut buf = vec![0u8; e.reclen];
+            fp.read_exact(&mut buf)?;
+            out.push(ms_record::parse(&buf));
+        }
+        Ok(out)
+    }
+}
@@ -39,6 +39,7 @@ use chrono::DateTime;
 use chrono::Duration;
 use chrono::NaiveDate;
 use chrono::NaiveDateTime;
+use chrono::TimeZone;
 use chrono::Utc;
 //use chrono::Timelike;
 
@@ -47,10 +48,50 @@ use std::ffi::CString;
 use std::path::Path;
 
 use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
 
 extern crate glob;
 
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate tokio;
+
+pub mod archive;
+pub mod clock;
+pub mod cut;
+pub mod datalink;
+pub mod dedup;
+pub mod earthworm;
+pub mod edit;
+pub mod extract;
+pub mod fdsn;
+pub mod follow;
+pub mod header;
+pub mod index;
+pub mod merge;
+pub mod reader;
+pub mod repack;
+pub mod sds;
+pub mod seedlink;
+pub mod selection;
+pub mod split;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod trace;
+pub mod watch;
+
+pub use reader::RecordReader;
+pub use selection::Selection;
+#[cfg(feature = "async")]
+pub use stream::{RecordSink, RecordStream};
+pub use trace::{Samples, Trace};
+
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
 unsafe impl Send for ms_record {}
@@ -63,8 +104,10 @@ unsafe impl Sync for ms_record {}
 // unsafe impl Sync for ms_trace {}
 
 /// MiniSEED Record
+///
+/// The decoded record and a copy of the encoded record bytes
 #[derive(Debug)]
-pub struct ms_record(*mut MSRecord);
+pub struct ms_record(*mut MSRecord, Vec<u8>);
 // #[derive(Debug)]
 // pub struct ms_group(*mut MSTraceGroup);
 // #[derive(Debug)]
@@ -135,6 +178,93 @@ pub fn f64_to_utc(t: f64) -> DateTime<Utc> {
     let t = NaiveDateTime::from_timestamp(i, f);
     DateTime::<Utc>::from_utc(t, Utc)
 }
+/// Convert DateTime<Utc> to microseconds from epoch, the libmseed high
+///   precision time
+pub fn utc_to_hptime(t: &DateTime<Utc>) -> i64 {
+    t.timestamp() * 1_000_000 + t.timestamp_subsec_micros() as i64
+}
+/// Convert microseconds from epoch, the libmseed high precision time,
+///   to DateTime<Utc>
+///
+/// ```
+/// # use miniseed::{hptime_to_utc, str_to_utc, utc_to_hptime};
+/// let t = str_to_utc("1969-12-31T23:59:59.5").unwrap();
+/// assert_eq!(utc_to_hptime(&t), -500_000);
+/// assert_eq!(hptime_to_utc(-500_000), t);
+/// ```
+pub fn hptime_to_utc(t: i64) -> DateTime<Utc> {
+    let secs = t.div_euclid(1_000_000);
+    let micros = t.rem_euclid(1_000_000) as u32;
+    Utc.timestamp_opt(secs, micros * 1000).unwrap()
+}
+/// Parse a time string into DateTime<Utc>
+///
+/// Accepted forms are `YYYY-MM-DD[THH:MM:SS.FFFFFF]`,
+/// `YYYY-DDD[THH:MM:SS.FFFFFF]` and the SEED style
+/// `YYYY,DDD[,HH:MM:SS.FFFFFF]`, where trailing time components may be
+/// omitted.  A trailing `Z` is ignored.
+///
+/// ```
+/// # use miniseed::str_to_utc;
+/// let t = str_to_utc("2016-10-30T18:02:58.23").unwrap();
+/// assert_eq!(t.to_string(), "2016-10-30 18:02:58.230 UTC");
+/// assert_eq!(str_to_utc("2016,304,18:02:58.23"), Some(t));
+/// assert_eq!(str_to_utc("2016-304T18:02:58.230000Z"), Some(t));
+/// assert_eq!(str_to_utc("2016-13-01"), None);
+/// ```
+pub fn str_to_utc(s: &str) -> Option<DateTime<Utc>> {
+    let s = s.trim().trim_end_matches('Z');
+    let (date, time) = if s.contains(',') {
+        let mut it = s.splitn(3, ',');
+        let y = it.next()?;
+        let d = it.next()?;
+        let rest = it.next().unwrap_or("");
+        (format!("{}-{}", y, d), rest.replace(',', ":"))
+    } else {
+        let mut it = s.splitn(2, ['T', ' ']);
+        let d = it.next()?.to_string();
+        (d, it.next().unwrap_or("").to_string())
+    };
+    let dv: Vec<&str> = date.split('-').collect();
+    let year: i32 = dv[0].parse().ok()?;
+    let day = match dv.len() {
+        2 => NaiveDate::from_yo_opt(year, dv[1].parse().ok()?)?,
+        3 => NaiveDate::from_ymd_opt(year, dv[1].parse().ok()?, dv[2].parse().ok()?)?,
+        _ => return None,
+    };
+    let mut hms = [0u32; 3];
+    let mut usec = 0u32;
+    if !time.is_empty() {
+        let tv: Vec<&str> = time.split(':').collect();
+        if tv.len() > 4 {
+            return None;
+        }
+        for (i, v) in tv.iter().enumerate() {
+            let mut v = *v;
+            if i == 2 {
+                if let Some(k) = v.find('.') {
+                    let frac = &v[k + 1..];
+                    if frac.is_empty()
+                        || frac.len() > 6
+                        || !frac.chars().all(|c| c.is_ascii_digit())
+                    {
+                        return None;
+                    }
+                    usec = format!("{:0<6}", frac).parse().ok()?;
+                    v = &v[..k];
+                }
+            }
+            if i == 3 {
+                // SEED style YYYY,DDD,HH,MM,SS,FFFFFF
+                usec = format!("{:0<6}", v).parse().ok()?;
+                continue;
+            }
+            hms[i] = v.parse().ok()?;
+        }
+    }
+    let t = day.and_hms_micro_opt(hms[0], hms[1], hms[2], usec)?;
+    Some(Utc.from_utc_datetime(&t))
+}
 
 // fn tmax_to_f64(v: &[DateTime<Utc>]) -> f64 {
 //     utc_to_f64(&tmax(v))
@@ -425,6 +555,9 @@ impl<'a> Data<'a> {
 pub struct ms_input {
     _filename: CString,
     pmsfp: *mut MSFileParam,
+    selection: Option<Selection>,
+    offset: Option<u64>,
+    seek: Option<u64>,
 }
 
 impl ms_input {
@@ -434,31 +567,267 @@ impl ms_input {
         return ms_input {
             _filename: cfile,
             pmsfp: std::ptr::null_mut() as *mut MSFileParam,
+            selection: None,
+            offset: None,
+            seek: None,
         };
     }
 
+    /// Only return records matching the selection
+    ///
+    /// Record headers are checked against the selection before the
+    /// data samples are decoded, non-matching records are skipped
+    ///
+    /// ```
+    /// # use miniseed::{ms_input, Selection};
+    /// let mut sel = Selection::new();
+    /// sel.add("IU_ANMO_00_BHN", None, None);
+    /// let input = ms_input::open("tests/multiple.seed").with_selection(sel);
+    /// assert_eq!(input.count(), 0);
+    /// ```
+    pub fn with_selection(mut self, selection: Selection) -> ms_input {
+        self.selection = Some(selection);
+        self
+    }
+
     pub fn filename(&self) -> &str {
         return self._filename.to_str().unwrap();
     }
+
+    /// Follow the file as it grows, like `tail -f`
+    ///
+    /// Reading starts at the offset set with seek_to_offset or
+    /// seek_to_time, otherwise at the start of the file, and the
+    /// selection of the input is kept.  See follow::Follow.
+    ///
+    /// ```
+    /// # use miniseed::ms_input;
+    /// # use std::time::Duration;
+    /// let mut input = ms_input::open("tests/multiple.seed");
+    /// input.seek_to_offset(512 * 1240);
+    /// let mut follow = input
+    ///     .follow()
+    ///     .with_poll_interval(Duration::from_millis(10))
+    ///     .with_idle_timeout(Duration::from_millis(50));
+    /// let mut n = 0;
+    /// while let Some(_) = follow.read_raw().unwrap() {
+    ///     n += 1;
+    /// }
+    /// assert_eq!(n, 3);
+    /// ```
+    pub fn follow(mut self) -> follow::Follow {
+        let mut f = follow::Follow::new(self.filename());
+        if let Some(sel) = self.selection.take() {
+            f = f.with_selection(sel);
+        }
+        if let Some(offset) = self.seek.take() {
+            f = f.with_offset(offset);
+        }
+        f
+    }
+
+    /// Return the byte offset of the last record returned
+    ///
+    /// ```
+    /// # use miniseed::ms_input;
+    /// let mut input = ms_input::open("tests/multiple.seed");
+    /// assert_eq!(input.current_offset(), None);
+    /// input.next();
+    /// input.next();
+    /// assert_eq!(input.current_offset(), Some(512));
+    /// ```
+    pub fn current_offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Read the next record from the byte offset `offset`
+    ///
+    /// The offset must be the start of a record, e.g. from
+    /// current_offset() or an index::IndexEntry
+    ///
+    /// ```
+    /// # use miniseed::ms_input;
+    /// let mut input = ms_input::open("tests/multiple.seed");
+    /// input.seek_to_offset(1024);
+    /// let rec = input.next().unwrap();
+    /// assert_eq!(rec.start().to_string(), "2010-02-27 06:30:39.369500 UTC");
+    /// assert_eq!(input.current_offset(), Some(1024));
+    /// ```
+    pub fn seek_to_offset(&mut self, offset: u64) {
+        self.seek = Some(offset);
+    }
+
+    /// Return the record length if all records in the file have the
+    /// same length
+    ///
+    /// The first and last records are checked, and the file size must
+    /// be a multiple of the record length
+    pub fn fixed_reclen(&self) -> Option<usize> {
+        let mut fp = File::open(self.filename()).ok()?;
+        let size = fp.metadata().ok()?.len();
+        let mut buf = vec![0u8; 256.min(size as usize)];
+        fp.read_exact(&mut buf).ok()?;
+        let reclen = header::RawHeader::parse(&buf)?.reclen?;
+        if size % reclen as u64 != 0 {
+            return None;
+        }
+        fp.seek(SeekFrom::Start(size - reclen as u64)).ok()?;
+        fp.read_exact(&mut buf).ok()?;
+        match header::RawHeader::parse(&buf)?.reclen {
+            Some(n) if n == reclen => Some(reclen),
+            _ => None,
+        }
+    }
+
+    /// Return the `n`-th record in the file, counting from 0
+    ///
+    /// If the record length is fixed, the record is read directly,
+    /// otherwise the headers of the preceding records are scanned.  If a
+    /// selection is set, the first matching record at or after the
+    /// `n`-th record is returned.  Iteration continues from the record
+    /// returned.
+    ///
+    /// ```
+    /// # use miniseed::ms_input;
+    /// let mut input = ms_input::open("tests/multiple.seed");
+    /// let rec = input.nth_record(2).unwrap();
+    /// assert_eq!(rec.start().to_string(), "2010-02-27 06:30:39.369500 UTC");
+    /// assert!(input.nth_record(1243).is_none());
+    /// ```
+    pub fn nth_record(&mut self, n: usize) -> Option<ms_record> {
+        if let Some(reclen) = self.fixed_reclen() {
+            self.seek_to_offset((n * reclen) as u64);
+            return self.next();
+        }
+        self.seek_to_offset(0);
+        for _ in 0..n {
+            self.read_raw(0)?;
+        }
+        self.next()
+    }
+
+    /// Position the input at the first record ending at or after `t`
+    ///
+    /// The file must contain a single channel sorted by time.  If the
+    /// record length is fixed a binary search of the record headers is
+    /// used, otherwise all headers are scanned.  Returns the record number
+    /// or None if all records end before `t`.
+    ///
+    /// ```
+    /// # use miniseed::{ms_input, str_to_utc};
+    /// let mut input = ms_input::open("tests/multiple.seed");
+    /// let t = str_to_utc("2010-02-27T06:30:30").unwrap();
+    /// assert_eq!(input.seek_to_time(&t), Some(1));
+    /// let rec = input.next().unwrap();
+    /// assert!(rec.start() <= t && rec.end() >= t);
+    /// ```
+    pub fn seek_to_time(&mut self, t: &DateTime<Utc>) -> Option<usize> {
+        if let Some(reclen) = self.fixed_reclen() {
+            let mut fp = File::open(self.filename()).ok()?;
+            let count = (fp.metadata().ok()?.len() / reclen as u64) as usize;
+            let mut buf = vec![0u8; 256.min(reclen)];
+            let (mut lo, mut hi) = (0, count);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                fp.seek(SeekFrom::Start((mid * reclen) as u64)).ok()?;
+                fp.read_exact(&mut buf).ok()?;
+                match header::RawHeader::parse(&buf) {
+                    Some(ref h) if h.end() < *t => lo = mid + 1,
+                    _ => hi = mid,
+                }
+            }
+            if lo == count {
+                return None;
+            }
+            self.seek_to_offset((lo * reclen) as u64);
+            return Some(lo);
+        }
+        let idx = index::MsIndex::build(self.filename()).ok()?;
+        let k = idx.entries().iter().position(|e| e.end >= *t)?;
+        self.seek_to_offset(idx.entries()[k].offset);
+        Some(k)
+    }
+
+    fn read_raw(&mut self, dataflag: flag) -> Option<ms_record> {
+        let mut fpos: off_t = 0;
+        if let Some(offset) = self.seek.take() {
+            if offset == 0 {
+                self.close();
+            } else {
+                fpos = -(offset as off_t);
+            }
+        }
+        let rec =
+            ms_record::read_next_flags(&self._filename, &mut self.pmsfp, dataflag, &mut fpos)?;
+        self.offset = Some(fpos as u64);
+        Some(rec)
+    }
+
+    fn close(&mut self) {
+        if self.pmsfp.is_null() {
+            return;
+        }
+        let mut pmsr = std::ptr::null_mut() as *mut MSRecord;
+        unsafe {
+            ms_readmsr_r(
+                &mut self.pmsfp,
+                &mut pmsr,
+                std::ptr::null(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+                0,
+                0,
+            );
+        }
+        self.pmsfp = std::ptr::null_mut();
+    }
+}
+
+impl Drop for ms_input {
+    fn drop(&mut self) {
+        self.close();
+    }
 }
 
 impl Iterator for ms_input {
     type Item = ms_record;
     fn next(&mut self) -> Option<ms_record> {
-        return ms_record::read_next(&self._filename, &mut self.pmsfp);
+        // The selection is put back once a record is found
+        let sel = match self.selection.take() {
+            Some(sel) => sel,
+            None => return self.read_raw(1),
+        };
+        let rec = loop {
+            match self.read_raw(0) {
+                Some(ref rec) if !sel.matches(rec) => {}
+                other => break other,
+            }
+        };
+        self.selection = Some(sel);
+        rec.map(ms_record::unpack)
     }
 }
 
 pub struct ms_output {
-    file: File,
+    file: BufWriter<File>,
+    passthrough: bool,
+    // First error of the pack handler, which cannot return it
+    error: Option<std::io::Error>,
 }
 
 unsafe extern "C" fn pack_handler_wrapper(buffer: *mut c_char, buflen: c_int, ptr: *mut c_void) {
     let optr: *mut ms_output = ptr as *mut ms_output;
     if let Some(o) = optr.as_mut() {
+        if o.error.is_some() {
+            return;
+        }
         let chars: &[c_char] = std::slice::from_raw_parts(buffer, buflen as usize);
         let bytes = &*(chars as *const [i8] as *const [u8]);
-        o.file.write(bytes as &[u8]).unwrap();
+        if let Err(e) = o.file.write_all(bytes as &[u8]) {
+            o.error = Some(e);
+        }
     } else {
         println!("optr was null");
     }
@@ -466,10 +835,71 @@ unsafe extern "C" fn pack_handler_wrapper(buffer: *mut c_char, buflen: c_int, pt
 
 impl ms_output {
     pub fn open<S: AsRef<Path>>(filename: S) -> std::io::Result<ms_output> {
-        return File::create(filename).map(|fh| ms_output { file: fh });
+        return File::create(filename).map(|fh| ms_output {
+            file: BufWriter::new(fh),
+            passthrough: false,
+            error: None,
+        });
+    }
+
+    /// Open a file for appending, creating it if it does not exist
+    pub fn append<S: AsRef<Path>>(filename: S) -> std::io::Result<ms_output> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(filename)
+            .map(|fh| ms_output {
+                file: BufWriter::new(fh),
+                passthrough: false,
+                error: None,
+            })
+    }
+
+    /// Write records with their original bytes rather than repacking
+    ///
+    /// Records without encoded bytes are repacked
+    ///
+    /// ```
+    /// # use miniseed::{ms_input, ms_output};
+    /// let file = std::env::temp_dir().join("miniseed-passthrough.mseed");
+    /// let mut out = ms_output::open(&file).unwrap().with_passthrough(true);
+    /// for rec in ms_input::open("tests/multiple.seed") {
+    ///     out.write(&rec).unwrap();
+    /// }
+    /// out.flush().unwrap();
+    /// let a = std::fs::read("tests/multiple.seed").unwrap();
+    /// assert_eq!(std::fs::read(&file).unwrap(), a);
+    /// ```
+    pub fn with_passthrough(mut self, passthrough: bool) -> ms_output {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Flush buffered records to the file
+    ///
+    /// Records are buffered, so flush before the output is dropped to
+    /// see errors writing the last records
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.take_error()?;
+        self.file.flush()
+    }
+
+    // Return and clear the error stored by the pack handler
+    fn take_error(&mut self) -> std::io::Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Write encoded record bytes unchanged
+    pub fn write_raw(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(buf)
     }
 
-    pub fn write(&mut self, record: &ms_record) {
+    /// Write a record, packed from its header and samples unless written
+    /// with passthrough
+    pub fn write(&mut self, record: &ms_record) -> std::io::Result<()> {
+        if self.passthrough && !record.raw_bytes().is_empty() {
+            return self.write_raw(record.raw_bytes());
+        }
         let ptr = (self as *mut ms_output) as *mut c_void;
         let rec_ptr: *const MSRecord = &(record.ptr());
         let rec_mut_ptr: *mut MSRecord = rec_ptr as *mut MSRecord_s;
@@ -483,6 +913,7 @@ impl ms_output {
                 0,
             );
         }
+        self.take_error()
     }
 }
 
@@ -516,8 +947,19 @@ impl ms_record {
     }
 
     pub fn read_next(file: &CString, pmsfp: &mut *mut MSFileParam) -> Option<ms_record> {
+        let mut fpos: off_t = 0;
+        ms_record::read_next_flags(file, pmsfp, 1, &mut fpos)
+    }
+
+    /// Read the next record, a negative `fpos` is the offset to read
+    ///   from, and on return `fpos` is the offset of the record read
+    fn read_next_flags(
+        file: &CString,
+        pmsfp: &mut *mut MSFileParam,
+        dataflag: flag,
+        fpos: &mut off_t,
+    ) -> Option<ms_record> {
         let verbose: flag = 1;
-        let dataflag: flag = 1;
         let skipnotdata: flag = 1;
         let mut pmsr = ms_record::null();
 
@@ -528,7 +970,7 @@ impl ms_record {
                 ((&mut pmsr) as *mut _) as *mut *mut MSRecord,
                 file.as_ptr(),
                 0,
-                std::ptr::null_mut(), // fpos
+                fpos,
                 std::ptr::null_mut(), // last
                 skipnotdata,
                 dataflag,
@@ -537,13 +979,44 @@ impl ms_record {
         };
 
         if retcode == MS_NOERROR as i32 {
-            return Some(ms_record(pmsr));
+            // The record buffer is reused by the next read, keep a copy
+            let raw = unsafe {
+                let m = *pmsr;
+                if m.record.is_null() || m.reclen <= 0 {
+                    vec![]
+                } else {
+                    let mut raw =
+                        std::slice::from_raw_parts(m.record as *const u8, m.reclen as usize)
+                            .to_vec();
+                    (*pmsr).record = raw.as_mut_ptr() as *mut c_char;
+                    raw
+                }
+            };
+            return Some(ms_record(pmsr, raw));
         } else if retcode == MS_ENDOFFILE as i32 {
             return None;
         } else {
             panic!("readmsr_r retcode: {}", retcode)
         }
     }
+
+    /// Decode the data samples of a record read header-only
+    fn unpack(mut self) -> ms_record {
+        let mut pmsr = self.0;
+        let retcode = unsafe {
+            msr_unpack(
+                self.1.as_mut_ptr() as *mut c_char,
+                (*pmsr).reclen,
+                ((&mut pmsr) as *mut _) as *mut *mut MSRecord,
+                1,
+                0,
+            )
+        };
+        if retcode != MS_NOERROR as i32 {
+            panic!("msr_unpack retcode: {}", retcode)
+        }
+        self
+    }
     /// Return the MiniSEED Record FSDH Header,
     ///   this is typically used internally
     ///
@@ -610,6 +1083,22 @@ impl ms_record {
         let dt = self.delta();
         b + Duration::microseconds(((n - 1) as f64 * dt * 1e6) as i64)
     }
+    /// Return the end time from the header sample count
+    ///
+    /// Unlike end(), this is valid for records read without data samples
+    ///
+    /// ```
+    /// # use miniseed::ms_record;
+    /// let file = "tests/sample.miniseed";
+    /// let rec = ms_record::read(file);
+    /// assert_eq!(rec.header_end(), rec.end());
+    /// ```
+    pub fn header_end(&self) -> DateTime<Utc> {
+        let n = self.ptr().samplecnt.max(1);
+        let b = self.start();
+        let dt = self.delta();
+        b + Duration::microseconds(((n - 1) as f64 * dt * 1e6) as i64)
+    }
     /// Return the time of the next sample beyond the record
     ///   assuming a constant sample rate
     ///
@@ -661,6 +1150,28 @@ impl ms_record {
         let m = self.ptr();
         m.sampletype as u8 as char
     }
+    /// Return the record length in bytes
+    ///
+    /// ```
+    /// # use miniseed::ms_record;
+    /// let file = "tests/sample.miniseed";
+    /// let rec = ms_record::read(file);
+    /// assert_eq!(rec.reclen(), 512);
+    /// ```
+    pub fn reclen(&self) -> usize {
+        self.ptr().reclen as usize
+    }
+    /// Return the libmseed data encoding, e.g. DE_STEIM2
+    ///
+    /// ```
+    /// # use miniseed::{ms_record, DE_STEIM2};
+    /// let file = "tests/sample.miniseed";
+    /// let rec = ms_record::read(file);
+    /// assert_eq!(rec.encoding(), DE_STEIM2);
+    /// ```
+    pub fn encoding(&self) -> u32 {
+        self.ptr().encoding as u32
+    }
     /// Return the number of points or samples
     ///
     /// ```
@@ -858,7 +1369,64 @@ impl ms_record {
         if ret != MS_NOERROR as i32 {
             println!("retcode: {}", ret);
         }
-        ms_record(pmsr)
+        // The record points into rec, which is kept with the record
+        ms_record(pmsr, rec)
+    }
+
+    /// Parse a buffer holding a record, returning an `InvalidData` error
+    /// if it does not begin with a complete record
+    ///
+    /// ```
+    /// # use miniseed::ms_record;
+    /// let buf = std::fs::read("tests/sample.miniseed").unwrap();
+    /// let rec = ms_record::try_parse(&buf).unwrap();
+    /// assert_eq!(rec.id(), "PN_PPNAF_00_HHZ");
+    /// assert!(ms_record::try_parse(&buf[..100]).is_err());
+    /// assert!(ms_record::try_parse(b"not a record").is_err());
+    /// ```
+    pub fn try_parse(record: &[u8]) -> std::io::Result<ms_record> {
+        let mut rec = record.to_vec();
+        let c_rec = rec.as_mut_ptr() as *mut c_char;
+        let mut pmsr = ms_record::null();
+        let ppmsr = cast!(ptr, pmsr, MSRecord);
+        let ret = unsafe { msr_parse(c_rec, record.len() as i32, ppmsr, 0, 1, 0) };
+        if pmsr.is_null() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "invalid MiniSEED record",
+            ));
+        }
+        // The record points into rec, which is kept with the record
+        let rec = ms_record(pmsr, rec);
+        if ret != MS_NOERROR as i32 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid MiniSEED record, libmseed error {}", ret),
+            ));
+        }
+        Ok(rec)
+    }
+
+    /// Return the encoded record bytes, exactly as read or parsed
+    ///
+    /// ```
+    /// # use miniseed::ms_record;
+    /// use std::fs::File;
+    /// use std::io::Read;
+    ///
+    /// let mut buf = vec![];
+    /// File::open("tests/sample.miniseed").unwrap().read_to_end(&mut buf).unwrap();
+    /// let rec = ms_record::read("tests/sample.miniseed");
+    /// assert_eq!(rec.raw_bytes(), &buf[..]);
+    /// assert_eq!(ms_record::parse(&buf).raw_bytes(), &buf[..]);
+    /// ```
+    pub fn raw_bytes(&self) -> &[u8] {
+        let n = self.ptr().reclen;
+        if n > 0 && (n as usize) < self.1.len() {
+            &self.1[..n as usize]
+        } else {
+            &self.1
+        }
     }
 
     // Get Character data, if available
@@ -0,0 +1,131 @@
+//! Time-ordered merging of many inputs
+//!
+//! Records from a set of inputs are interleaved in order of start time,
+//! with ties broken by id and then by input order.  Only the next record
+//! of each input is held in memory, so a day of data from hundreds of
+//! per-channel files may be replayed as a single stream.  Each input must
+//! itself be sorted by start time.
+//!
+//! ```
+//! use miniseed::merge::MergeInputs;
+//!
+//! let files = ["tests/xff5ed77dda384bb087b21f93f4dd5415",
+//!              "tests/xffba7d5cfef54987b022bc6e313d0d6e",
+//!              "tests/xff00b5d8b3124f1aa2de549070709634"];
+//! let ids: Vec<String> = MergeInputs::from_files(&files).map(|r| r.id()).collect();
+//! assert_eq!(ids, ["YW_507_01_HH2", "PN_PPNAF_00_HHZ", "PO_CHGQ__HHE"]);
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::path::Path;
+
+use {ms_input, ms_record};
+
+/// Next record of an input waiting to be merged
+struct Pending {
+    start: DateTime<Utc>,
+    id: String,
+    input: usize,
+    rec: ms_record,
+}
+
+impl Pending {
+    fn new(input: usize, rec: ms_record) -> Pending {
+        Pending {
+            start: rec.start(),
+            id: rec.id(),
+            input,
+            rec,
+        }
+    }
+    fn key(&self) -> (&DateTime<Utc>, &str, usize) {
+        (&self.start, &self.id, self.input)
+    }
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Pending) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Pending) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    // Reversed, BinaryHeap is a max-heap
+    fn cmp(&self, other: &Pending) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+/// Iterator over the records of many inputs in start time order
+pub struct MergeInputs {
+    inputs: Vec<ms_input>,
+    heap: BinaryHeap<Pending>,
+    primed: bool,
+}
+
+impl MergeInputs {
+    /// Merge records from a set of inputs
+    ///
+    /// Inputs are not read until the first record is requested
+    pub fn new(inputs: Vec<ms_input>) -> MergeInputs {
+        let n = inputs.len();
+        MergeInputs {
+            inputs,
+            heap: BinaryHeap::with_capacity(n),
+            primed: false,
+        }
+    }
+    /// Merge records from a set of files
+    pub fn from_files<S: AsRef<Path>>(files: &[S]) -> MergeInputs {
+        MergeInputs::new(files.iter().map(ms_input::open).collect())
+    }
+    /// Return the number of inputs
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+    /// Return true if there are no inputs
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+    /// Return the start time of the next record without reading it
+    pub fn peek_time(&mut self) -> Option<DateTime<Utc>> {
+        self.prime();
+        self.heap.peek().map(|p| p.start)
+    }
+    fn prime(&mut self) {
+        if self.primed {
+            return;
+        }
+        self.primed = true;
+        for i in 0..self.inputs.len() {
+            self.advance(i);
+        }
+    }
+    fn advance(&mut self, i: usize) {
+        if let Some(rec) = self.inputs[i].next() {
+            self.heap.push(Pending::new(i, rec));
+        }
+    }
+}
+
+impl Iterator for MergeInputs {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        self.prime();
+        let p = self.heap.pop()?;
+        self.advance(p.input);
+        Some(p.rec)
+    }
+}
@@ -0,0 +1,186 @@
+//! Reading records from a byte stream
+//!
+//! `RecordReader` is the counterpart of ms_input for any `Read`, e.g. a
+//! network connection or a decompressed file.  Records are framed with
+//! header::record_length, so records of mixed lengths are supported, and
+//! are parsed only when returned.
+//!
+//! ```
+//! use miniseed::RecordReader;
+//! use std::fs::File;
+//!
+//! let file = File::open("tests/multiple.seed").unwrap();
+//! let reader = RecordReader::new(file);
+//! assert_eq!(reader.count(), 1243);
+//! ```
+
+use std::io;
+use std::io::Read;
+
+use header::{is_header, record_length, RawHeader, FSDH_LEN, MAX_RECLEN};
+use {ms_record, Selection};
+
+/// Splits a byte stream into records
+///
+/// Bytes are added as they are read and complete records are taken
+/// out, so the same framing is used for blocking and async streams.
+///
+/// ```
+/// use miniseed::reader::Framer;
+/// use std::fs;
+///
+/// let buf = fs::read("tests/multiple.seed").unwrap();
+/// let mut framer = Framer::new();
+/// framer.extend(&buf[..700]);
+/// assert_eq!(framer.next_raw().unwrap().map(|r| r.len()), Some(512));
+/// assert_eq!(framer.next_raw().unwrap(), None);
+/// framer.extend(&buf[700..1024]);
+/// framer.finish();
+/// assert_eq!(framer.next_raw().unwrap().map(|r| r.len()), Some(512));
+/// assert_eq!(framer.next_raw().unwrap(), None);
+/// assert_eq!(framer.offset(), 1024);
+/// ```
+#[derive(Debug, Default)]
+pub struct Framer {
+    buf: Vec<u8>,
+    eof: bool,
+    selection: Option<Selection>,
+    offset: u64,
+}
+
+impl Framer {
+    /// Create a framer for an empty stream
+    pub fn new() -> Framer {
+        Framer::default()
+    }
+    /// Only return records matching the selection
+    ///
+    /// Record headers are checked before the records are parsed
+    pub fn with_selection(mut self, selection: Selection) -> Framer {
+        self.selection = Some(selection);
+        self
+    }
+    /// Add bytes read from the stream
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+    /// Mark the end of the stream, so the last record may be shorter
+    /// than its next power of two
+    pub fn finish(&mut self) {
+        self.eof = true;
+    }
+    /// Return true at the end of the stream
+    pub fn is_finished(&self) -> bool {
+        self.eof
+    }
+    /// Return the number of bytes up to the end of the last record
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    /// Return the bytes of the next record
+    ///
+    /// Returns None if more bytes are needed or, after finish, at the
+    /// end of the stream.  Returns an error if the stream does not
+    /// contain records, after which the rest of the stream is dropped.
+    pub fn next_raw(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while let Some(raw) = self.frame()? {
+            let wanted = match self.selection {
+                Some(ref sel) => match RawHeader::parse(&raw) {
+                    Some(h) => sel.matches_header(&h),
+                    None => false,
+                },
+                None => true,
+            };
+            if wanted {
+                return Ok(Some(raw));
+            }
+        }
+        Ok(None)
+    }
+    // Split the next record from the buffer
+    fn frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match record_length(&self.buf, self.eof) {
+            Some(n) if n <= self.buf.len() => {
+                self.offset += n as u64;
+                Ok(Some(self.buf.drain(..n).collect()))
+            }
+            Some(_) if !self.eof => Ok(None),
+            None if !self.eof && self.buf.len() < FSDH_LEN => Ok(None),
+            None if !self.eof && is_header(&self.buf) && self.buf.len() <= MAX_RECLEN => Ok(None),
+            _ if self.buf.is_empty() => Ok(None),
+            _ => {
+                self.buf.clear();
+                self.eof = true;
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid record at byte {}", self.offset),
+                ))
+            }
+        }
+    }
+}
+
+/// Records read from a byte stream
+pub struct RecordReader<R: Read> {
+    reader: R,
+    framer: Framer,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Read records from `reader`
+    pub fn new(reader: R) -> RecordReader<R> {
+        RecordReader {
+            reader,
+            framer: Framer::new(),
+        }
+    }
+    /// Only return records matching the selection
+    ///
+    /// Record headers are checked before the records are parsed
+    pub fn with_selection(mut self, selection: Selection) -> RecordReader<R> {
+        self.framer = self.framer.with_selection(selection);
+        self
+    }
+    /// Return the number of bytes read up to the end of the last record
+    pub fn offset(&self) -> u64 {
+        self.framer.offset()
+    }
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+    /// Return the bytes of the next record
+    ///
+    /// Returns None at the end of the stream and an error if the stream
+    /// does not contain records
+    pub fn read_raw(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(raw) = self.framer.next_raw()? {
+                return Ok(Some(raw));
+            }
+            if self.framer.is_finished() {
+                return Ok(None);
+            }
+            let mut tmp = [0u8; 8192];
+            match self.reader.read(&mut tmp) {
+                Ok(0) => self.framer.finish(),
+                Ok(n) => self.framer.extend(&tmp[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    /// Return the next record
+    pub fn next_record(&mut self) -> io::Result<Option<ms_record>> {
+        self.read_raw()?
+            .map(|raw| ms_record::try_parse(&raw))
+            .transpose()
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        self.next_record().ok().and_then(|r| r)
+    }
+}
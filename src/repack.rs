@@ -0,0 +1,178 @@
+//! Repacking records to a different record length or encoding
+//!
+//! Contiguous records of each channel are reassembled into continuous
+//! samples and re-encoded into records of a target length and encoding.
+//! Records are only joined if their quality code, header flags, time
+//! correction and timing quality match, and these are preserved in the
+//! repacked records.
+//!
+//! ```
+//! use miniseed::{ms_input, DE_STEIM2};
+//! use miniseed::repack::Repacker;
+//!
+//! let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+//! let (recs, stats) = Repacker::new(4096, DE_STEIM2).repack(recs);
+//! assert_eq!(stats.records_in, 1243);
+//! assert_eq!(stats.records_out, recs.len());
+//! assert!(stats.ratio() > 1.0);
+//! ```
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use {ms_input, ms_record, Trace};
+
+/// Counts of records, bytes and samples handled by Repacker
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RepackStats {
+    /// Records read
+    pub records_in: usize,
+    /// Records written
+    pub records_out: usize,
+    /// Bytes read
+    pub bytes_in: usize,
+    /// Bytes written
+    pub bytes_out: usize,
+    /// Data samples repacked
+    pub samples: usize,
+}
+
+impl RepackStats {
+    /// Return the ratio of bytes read to bytes written
+    ///
+    /// Values above 1 indicate the repacked data are smaller
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_out == 0 {
+            return 0.0;
+        }
+        self.bytes_in as f64 / self.bytes_out as f64
+    }
+}
+
+/// Header values which must match for records to be joined
+#[derive(Debug, Clone, PartialEq)]
+struct Flags {
+    act: u8,
+    io: u8,
+    dq: u8,
+    time_correct: i32,
+    timing_quality: Option<u8>,
+}
+
+impl Flags {
+    fn new(rec: &ms_record) -> Flags {
+        let h = rec.header();
+        let b1001 = rec.ptr().Blkt1001;
+        Flags {
+            act: h.act_flags,
+            io: h.io_flags,
+            dq: h.dq_flags,
+            time_correct: h.time_correct,
+            timing_quality: if b1001.is_null() {
+                None
+            } else {
+                Some(unsafe { (*b1001).timing_qual })
+            },
+        }
+    }
+}
+
+/// Contiguous samples and the record used as the header template
+struct Segment {
+    template: ms_record,
+    flags: Flags,
+    trace: Trace,
+}
+
+/// Record repacking to a target record length and encoding
+#[derive(Debug, Clone)]
+pub struct Repacker {
+    reclen: usize,
+    encoding: u32,
+}
+
+impl Repacker {
+    /// Create a repacker for records of `reclen` bytes with `encoding`
+    ///
+    /// `encoding` is a libmseed data encoding, e.g. `DE_STEIM2`.  Data
+    /// whose sample type cannot be stored with `encoding` are packed with
+    /// the default encoding for the sample type.
+    pub fn new(reclen: usize, encoding: u32) -> Repacker {
+        Repacker { reclen, encoding }
+    }
+    /// Repack records and return the encoded records
+    ///
+    /// Output records are ordered by id and start time
+    pub fn repack_bytes(&self, records: Vec<ms_record>) -> (Vec<Vec<u8>>, RepackStats) {
+        let mut stats = RepackStats {
+            records_in: records.len(),
+            bytes_in: records.iter().map(|r| r.reclen()).sum(),
+            ..RepackStats::default()
+        };
+        let mut recs: Vec<(String, ms_record)> = records.into_iter().map(|r| (r.id(), r)).collect();
+        recs.sort_by(|a, b| (&a.0, a.1.start()).cmp(&(&b.0, b.1.start())));
+
+        let mut out = vec![];
+        let mut seg: Option<Segment> = None;
+        for (_, rec) in recs {
+            let tr = match Trace::from_record(&rec) {
+                Some(tr) => tr,
+                None => continue,
+            };
+            let flags = Flags::new(&rec);
+            if let Some(ref mut s) = seg {
+                if s.flags == flags && s.trace.is_contiguous(&tr) && s.trace.data.extend(&tr.data) {
+                    continue;
+                }
+            }
+            if let Some(s) = seg.take() {
+                out.extend(self.pack(&s, &mut stats));
+            }
+            seg = Some(Segment {
+                template: rec,
+                flags,
+                trace: tr,
+            });
+        }
+        if let Some(s) = seg.take() {
+            out.extend(self.pack(&s, &mut stats));
+        }
+        stats.records_out = out.len();
+        stats.bytes_out = out.iter().map(|b| b.len()).sum();
+        (out, stats)
+    }
+    /// Repack records
+    ///
+    /// see repack_bytes()
+    pub fn repack(&self, records: Vec<ms_record>) -> (Vec<ms_record>, RepackStats) {
+        let (bufs, stats) = self.repack_bytes(records);
+        (bufs.iter().map(|b| ms_record::parse(b)).collect(), stats)
+    }
+    /// Repack the records of a set of files and write the result to
+    /// `output`
+    pub fn files<S: AsRef<Path>, P: AsRef<Path>>(
+        &self,
+        inputs: &[S],
+        output: P,
+    ) -> io::Result<RepackStats> {
+        let recs: Vec<ms_record> = inputs.iter().flat_map(ms_input::open).collect();
+        let (bufs, stats) = self.repack_bytes(recs);
+        let mut out = BufWriter::new(File::create(output)?);
+        for buf in &bufs {
+            out.write_all(buf)?;
+        }
+        out.flush()?;
+        Ok(stats)
+    }
+    fn pack(&self, seg: &Segment, stats: &mut RepackStats) -> Vec<Vec<u8>> {
+        let enc = if seg.trace.data.can_encode(self.encoding) {
+            self.encoding
+        } else {
+            seg.trace.data.default_encoding()
+        };
+        stats.samples += seg.trace.npts();
+        seg.trace.pack_like(&seg.template, self.reclen, enc)
+    }
+}
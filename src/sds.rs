@@ -0,0 +1,179 @@
+//! SDS (SeisComP Data Structure) archives
+//!
+//! An SDS archive stores one file per channel per day with the layout
+//!
+//! ```text
+//! ROOT/YEAR/NET/STA/CHA.D/NET.STA.LOC.CHA.D.YEAR.DOY
+//! ```
+//!
+//! where `DOY` is the three digit day of year.  Records are stored in
+//! the file of the day on which they start.  `SdsArchive` and `SdsWriter`
+//! are an `Archive` and `ArchiveWriter` with the SDS path template.
+//!
+//! Reading from an archive:
+//!
+//! ```no_run
+//! use miniseed::{str_to_utc, Selection};
+//! use miniseed::sds::SdsArchive;
+//!
+//! let sds = SdsArchive::new("/data/sds");
+//! let mut sel = Selection::new();
+//! sel.add("IU_ANMO_00_BH?", None, None);
+//! let t0 = str_to_utc("2010-02-27T06:30:00").unwrap();
+//! let t1 = str_to_utc("2010-02-28T06:30:00").unwrap();
+//! for tr in sds.traces(&sel, &t0, &t1) {
+//!     println!("{} {} {}", tr.id(), tr.start, tr.npts());
+//! }
+//! ```
+//!
+//! Writing to an archive:
+//!
+//! ```no_run
+//! use miniseed::ms_input;
+//! use miniseed::sds::SdsWriter;
+//!
+//! let mut sds = SdsWriter::new("/data/sds").with_split_midnight(true);
+//! for rec in ms_input::open("tests/multiple.seed") {
+//!     sds.write(&rec).unwrap();
+//! }
+//! sds.close().unwrap();
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use archive::{Archive, ArchiveWriter};
+use {ms_record, Selection, Trace};
+
+/// SDS archive reader
+#[derive(Debug, Clone)]
+pub struct SdsArchive {
+    archive: Archive,
+}
+
+impl SdsArchive {
+    /// Create a reader for the archive at `root`
+    pub fn new<S: AsRef<Path>>(root: S) -> SdsArchive {
+        SdsArchive {
+            archive: Archive::sds(root),
+        }
+    }
+    /// Return the underlying archive
+    pub fn archive(&self) -> &Archive {
+        &self.archive
+    }
+    /// Return the archive root directory
+    pub fn root(&self) -> &Path {
+        self.archive.root()
+    }
+    /// Return the path of the day file for a channel and time
+    ///
+    /// ```
+    /// # use miniseed::sds::SdsArchive;
+    /// # use miniseed::str_to_utc;
+    /// let sds = SdsArchive::new("sds");
+    /// let t = str_to_utc("2010-02-27T06:30:00").unwrap();
+    /// let path = sds.path("IU", "ANMO", "00", "BHZ", &t);
+    /// assert_eq!(path.to_str(), Some("sds/2010/IU/ANMO/BHZ.D/IU.ANMO.00.BHZ.D.2010.058"));
+    /// ```
+    pub fn path(&self, net: &str, sta: &str, loc: &str, cha: &str, t: &DateTime<Utc>) -> PathBuf {
+        self.archive.path(net, sta, loc, cha, 'D', t)
+    }
+    /// Return the existing day files which may contain data matching
+    /// the selection between `start` and `end`
+    ///
+    /// The day before `start` is included, as records starting before
+    /// midnight are stored in the previous day file
+    pub fn day_files(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<PathBuf> {
+        self.archive.files(sel, start, end)
+    }
+    /// Read records matching the selection between `start` and `end`
+    ///
+    /// Time windows in the selection are also applied
+    pub fn records(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<ms_record> {
+        self.archive.records(sel, start, end)
+    }
+    /// Read traces matching the selection between `start` and `end`
+    ///
+    /// Records are merged into contiguous traces across day files and
+    /// trimmed to the window `[start, end)`
+    pub fn traces(
+        &self,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Vec<Trace> {
+        self.archive.traces(sel, start, end)
+    }
+}
+
+/// SDS archive writer
+///
+/// Records are appended to the day file of their start time.  A bounded
+/// number of files are kept open, with the least recently used file
+/// closed when the limit is reached.
+pub struct SdsWriter {
+    writer: ArchiveWriter,
+}
+
+impl SdsWriter {
+    /// Create a writer for the archive at `root`
+    ///
+    /// By default up to 64 files are kept open and records are not split
+    pub fn new<S: AsRef<Path>>(root: S) -> SdsWriter {
+        SdsWriter {
+            writer: ArchiveWriter::new(Archive::sds(root)),
+        }
+    }
+    /// Set the maximum number of open files
+    pub fn with_max_open(self, max_open: usize) -> SdsWriter {
+        SdsWriter {
+            writer: self.writer.with_max_open(max_open),
+        }
+    }
+    /// Split records which straddle midnight into records for each day
+    ///
+    /// Split records are repacked with the original record length,
+    /// encoding and header flags
+    pub fn with_split_midnight(self, split: bool) -> SdsWriter {
+        SdsWriter {
+            writer: self.writer.with_split(split),
+        }
+    }
+    /// Return the number of open files
+    pub fn num_open(&self) -> usize {
+        self.writer.num_open()
+    }
+    /// Write a record to its day file
+    pub fn write(&mut self, rec: &ms_record) -> io::Result<()> {
+        self.writer.write(rec)
+    }
+    /// Flush all open files
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+    /// Close files which have not been written to within `idle`
+    ///
+    /// Returns the number of files closed
+    pub fn close_idle(&mut self, idle: std::time::Duration) -> io::Result<usize> {
+        self.writer.close_idle(idle)
+    }
+    /// Flush and close all open files
+    pub fn close(&mut self) -> io::Result<()> {
+        self.writer.close()
+    }
+}
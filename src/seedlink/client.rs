@@ -0,0 +1,577 @@
+//! SeedLink client
+//!
+//! The client negotiates each requested station in multi-station mode
+//! and yields the records received.  The sequence number and time of the
+//! last record of each station are kept so that, after a disconnection
+//! or a restart with a state file, data resume where they stopped.
+//!
+//! ```no_run
+//! use miniseed::seedlink::SeedLinkClient;
+//!
+//! let mut client = SeedLinkClient::new("rtserve.iris.washington.edu:18000")
+//!     .with_station("IU", "ANMO", &["00BH?.D"])
+//!     .with_state_file("seedlink.state");
+//! for rec in client.by_ref().take(10) {
+//!     println!("{}", rec);
+//! }
+//! client.close().unwrap();
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{format_time, parse_time, PacketReader, SeedLinkPacket, MAX_SEQUENCE};
+use ms_record;
+
+/// Interval between checks for keepalives and timeouts while idle
+const TICK: Duration = Duration::from_millis(100);
+
+/// Data transfer mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Buffered and real-time data, the connection stays open (`DATA`)
+    Data,
+    /// Buffered data only, the server ends the transfer when all buffered
+    /// data are sent (`FETCH`)
+    Fetch,
+    /// Data within a time window, open ended if no end is given (`TIME`)
+    Time(DateTime<Utc>, Option<DateTime<Utc>>),
+}
+
+/// Station requested from a server and its resume state
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub network: String,
+    pub station: String,
+    /// Stream selectors `LLCCC.T`, e.g. `00BHZ.D` or `BH?`, all streams
+    /// if empty
+    pub selectors: Vec<String>,
+    /// Sequence number of the last packet received
+    pub sequence: Option<u32>,
+    /// End time of the last record received
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl Station {
+    /// Create a station request without resume state
+    pub fn new(network: &str, station: &str, selectors: &[&str]) -> Station {
+        Station {
+            network: network.to_string(),
+            station: station.to_string(),
+            selectors: selectors.iter().map(|s| s.to_string()).collect(),
+            sequence: None,
+            time: None,
+        }
+    }
+    /// Return the data transfer command for this station
+    ///
+    /// ```
+    /// # use miniseed::seedlink::{Mode, Station};
+    /// # use miniseed::str_to_utc;
+    /// let mut st = Station::new("IU", "ANMO", &[]);
+    /// assert_eq!(st.command(&Mode::Data), "DATA");
+    /// st.sequence = Some(0x1A);
+    /// st.time = str_to_utc("2010-02-27T06:30:00");
+    /// assert_eq!(st.command(&Mode::Fetch), "FETCH 00001B 2010,02,27,06,30,00");
+    /// ```
+    pub fn command(&self, mode: &Mode) -> String {
+        match *mode {
+            Mode::Data | Mode::Fetch => {
+                let mut cmd = if *mode == Mode::Data { "DATA" } else { "FETCH" }.to_string();
+                if let Some(seq) = self.sequence {
+                    cmd += &format!(" {:06X}", (seq + 1) & MAX_SEQUENCE);
+                    if let Some(t) = self.time {
+                        cmd += &format!(" {}", format_time(&t));
+                    }
+                }
+                cmd
+            }
+            Mode::Time(start, end) => {
+                let start = match self.time {
+                    Some(t) if t > start => t,
+                    _ => start,
+                };
+                let mut cmd = format!("TIME {}", format_time(&start));
+                if let Some(end) = end {
+                    cmd += &format!(" {}", format_time(&end));
+                }
+                cmd
+            }
+        }
+    }
+    fn matches(&self, rec: &ms_record) -> bool {
+        self.network == rec.network() && self.station == rec.station()
+    }
+}
+
+/// Packet received from a server
+enum Packet {
+    Data(u32, ms_record),
+    /// INFO response, true if more packets of the response follow
+    Info(bool, ms_record),
+    End,
+}
+
+/// Connection to a server with the bytes received but not yet framed
+struct Connection {
+    stream: TcpStream,
+    reader: PacketReader,
+    last_recv: Instant,
+    last_keepalive: Instant,
+}
+
+impl Connection {
+    fn open(addr: &str) -> io::Result<Connection> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(TICK))?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            reader: PacketReader::new(),
+            last_recv: Instant::now(),
+            last_keepalive: Instant::now(),
+        })
+    }
+    fn send(&mut self, cmd: &str) -> io::Result<()> {
+        self.stream.write_all(format!("{}\r\n", cmd).as_bytes())
+    }
+    // Read available bytes, returns false if nothing arrived within a tick
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut tmp = [0u8; 4096];
+        match self.stream.read(&mut tmp) {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by server",
+            )),
+            Ok(n) => {
+                self.reader.feed(&tmp[..n]);
+                self.last_recv = Instant::now();
+                Ok(true)
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+    fn read_line(&mut self, timeout: Duration) -> io::Result<String> {
+        loop {
+            if let Some(line) = self.reader.take_line() {
+                return Ok(line);
+            }
+            if !self.fill()? && self.last_recv.elapsed() > timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no response from server",
+                ));
+            }
+        }
+    }
+    // Send a command and check for an OK response
+    fn command(&mut self, cmd: &str, timeout: Duration) -> io::Result<()> {
+        self.send(cmd)?;
+        let resp = self.read_line(timeout)?;
+        if resp != "OK" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", cmd, resp),
+            ));
+        }
+        Ok(())
+    }
+    // Send HELLO and return the two response lines
+    fn hello(&mut self, timeout: Duration) -> io::Result<(String, String)> {
+        self.send("HELLO")?;
+        let id = self.read_line(timeout)?;
+        let org = self.read_line(timeout)?;
+        Ok((id, org))
+    }
+    // Frame the next packet from the received bytes
+    fn packet(&mut self) -> io::Result<Option<Packet>> {
+        if self.reader.take_end() {
+            return Ok(Some(Packet::End));
+        }
+        if self.reader.buffered().starts_with(b"ERROR") {
+            return match self.reader.take_line() {
+                Some(line) => Err(io::Error::new(io::ErrorKind::InvalidData, line)),
+                None => Ok(None),
+            };
+        }
+        Ok(self.reader.next_packet()?.map(|(pkt, rec)| match pkt {
+            SeedLinkPacket::Data(seq) => Packet::Data(seq, rec),
+            SeedLinkPacket::Info(more) => Packet::Info(more, rec),
+        }))
+    }
+}
+
+/// SeedLink v3 client
+///
+/// Connection failures and timeouts are retried after a delay which
+/// doubles from the minimum to the maximum backoff, by default without
+/// limit in the number of retries.
+pub struct SeedLinkClient {
+    addr: String,
+    stations: Vec<Station>,
+    mode: Mode,
+    keepalive: Option<Duration>,
+    timeout: Duration,
+    backoff: (Duration, Duration),
+    retries: Option<usize>,
+    state_file: Option<PathBuf>,
+    state_loaded: bool,
+    state_other: Vec<String>,
+    conn: Option<Connection>,
+    server: Option<String>,
+    failures: usize,
+    done: bool,
+}
+
+impl SeedLinkClient {
+    /// Create a client for the server at `addr`, `host:port`
+    pub fn new(addr: &str) -> SeedLinkClient {
+        SeedLinkClient {
+            addr: addr.to_string(),
+            stations: vec![],
+            mode: Mode::Data,
+            keepalive: None,
+            timeout: Duration::from_secs(60),
+            backoff: (Duration::from_secs(1), Duration::from_secs(60)),
+            retries: None,
+            state_file: None,
+            state_loaded: false,
+            state_other: vec![],
+            conn: None,
+            server: None,
+            failures: 0,
+            done: false,
+        }
+    }
+    /// Request a station with stream selectors, all streams if empty
+    pub fn with_station(
+        mut self,
+        network: &str,
+        station: &str,
+        selectors: &[&str],
+    ) -> SeedLinkClient {
+        self.stations
+            .push(Station::new(network, station, selectors));
+        self
+    }
+    /// Set the data transfer mode, `Mode::Data` by default
+    pub fn with_mode(mut self, mode: Mode) -> SeedLinkClient {
+        self.mode = mode;
+        self
+    }
+    /// Send a keepalive after `interval` without data from the server
+    pub fn with_keepalive(mut self, interval: Duration) -> SeedLinkClient {
+        self.keepalive = Some(interval);
+        self
+    }
+    /// Reconnect after `timeout` without data from the server, 60 s by
+    /// default
+    pub fn with_timeout(mut self, timeout: Duration) -> SeedLinkClient {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the minimum and maximum delay before reconnecting
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> SeedLinkClient {
+        self.backoff = (min, max);
+        self
+    }
+    /// Give up after `retries` successive failed connections
+    pub fn with_retries(mut self, retries: usize) -> SeedLinkClient {
+        self.retries = Some(retries);
+        self
+    }
+    /// Load and save the sequence numbers of the stations in `file`
+    ///
+    /// The state is loaded, if the file exists, before the first
+    /// connection and saved at the end of the transfer, on close() and
+    /// when the client is dropped.  See save_state() for the format.
+    pub fn with_state_file<S: AsRef<Path>>(mut self, file: S) -> SeedLinkClient {
+        self.state_file = Some(file.as_ref().to_path_buf());
+        self
+    }
+    /// Return the requested stations and their resume state
+    pub fn stations(&self) -> &[Station] {
+        &self.stations
+    }
+    /// Return the server identification from the last connection
+    pub fn server_id(&self) -> Option<&str> {
+        self.server.as_deref()
+    }
+    /// Return the server identification and organization lines
+    ///
+    /// A separate connection is used
+    pub fn hello(&self) -> io::Result<(String, String)> {
+        let mut conn = Connection::open(&self.addr)?;
+        let resp = conn.hello(self.timeout)?;
+        let _ = conn.send("BYE");
+        Ok(resp)
+    }
+    /// Request information at `level` and return the XML response
+    ///
+    /// Levels are `ID`, `CAPABILITIES`, `STATIONS`, `STREAMS`, `GAPS`,
+    /// `CONNECTIONS` and `ALL`.  A separate connection is used.
+    pub fn info(&self, level: &str) -> io::Result<String> {
+        let mut conn = Connection::open(&self.addr)?;
+        conn.hello(self.timeout)?;
+        conn.send(&format!("INFO {}", level))?;
+        let mut text = String::new();
+        loop {
+            match conn.packet()? {
+                Some(Packet::Info(more, rec)) => {
+                    if let Some(s) = rec.as_string() {
+                        text.push_str(s.trim_end_matches('\0'));
+                    }
+                    if !more {
+                        break;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if !conn.fill()? && conn.last_recv.elapsed() > self.timeout {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "no response from server",
+                        ));
+                    }
+                }
+            }
+        }
+        let _ = conn.send("BYE");
+        Ok(text)
+    }
+    /// Return the next record and its sequence number
+    ///
+    /// Returns None when a `FETCH` or time window transfer is complete,
+    /// and an error when the retries are exhausted
+    pub fn next_record(&mut self) -> io::Result<Option<(u32, ms_record)>> {
+        if !self.state_loaded {
+            self.load_state()?;
+        }
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            match self.step() {
+                Ok(Some(Packet::Data(seq, rec))) => {
+                    self.failures = 0;
+                    if let Some(st) = self.stations.iter_mut().find(|st| st.matches(&rec)) {
+                        st.sequence = Some(seq);
+                        st.time = Some(rec.end1());
+                    }
+                    return Ok(Some((seq, rec)));
+                }
+                Ok(Some(Packet::End)) => {
+                    self.done = true;
+                    self.conn = None;
+                    self.save_state()?;
+                }
+                Ok(Some(Packet::Info(..))) | Ok(None) => {}
+                Err(e) => {
+                    self.conn = None;
+                    self.failures += 1;
+                    if let Some(n) = self.retries {
+                        if self.failures > n {
+                            return Err(e);
+                        }
+                    }
+                    thread::sleep(self.delay());
+                }
+            }
+        }
+    }
+    /// Close the connection and save the state
+    ///
+    /// The state is only saved if it was loaded by a transfer
+    pub fn close(&mut self) -> io::Result<()> {
+        if let Some(mut conn) = self.conn.take() {
+            let _ = conn.send("BYE");
+        }
+        self.done = true;
+        if self.state_loaded {
+            self.save_state()?;
+        }
+        Ok(())
+    }
+    /// Load the sequence numbers of the stations from the state file
+    ///
+    /// Nothing is loaded if there is no state file or it does not exist.
+    /// Lines of stations not requested are kept for save_state(), and
+    /// nothing is loaded if any line is invalid.
+    pub fn load_state(&mut self) -> io::Result<()> {
+        let file = match self.state_file {
+            Some(ref f) if f.exists() => f.clone(),
+            _ => {
+                self.state_loaded = true;
+                return Ok(());
+            }
+        };
+        let mut s = String::new();
+        File::open(&file)?.read_to_string(&mut s)?;
+        let mut entries = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("state line {}: {}: {}", i + 1, msg, line),
+                )
+            };
+            let v: Vec<&str> = line.split_whitespace().collect();
+            if v.len() < 3 || v.len() > 4 {
+                return Err(bad("expected 3 or 4 fields"));
+            }
+            let seq: u32 = v[2].parse().map_err(|_| bad("invalid sequence number"))?;
+            let time = match v.get(3) {
+                Some(t) => Some(parse_time(t).ok_or_else(|| bad("invalid time"))?),
+                None => None,
+            };
+            entries.push((v[0], v[1], seq, time, line));
+        }
+        self.state_other.clear();
+        for (net, sta, seq, time, line) in entries {
+            let mut requested = false;
+            for st in self.stations.iter_mut() {
+                if st.network == net && st.station == sta {
+                    st.sequence = Some(seq);
+                    st.time = time;
+                    requested = true;
+                }
+            }
+            if !requested {
+                self.state_other.push(line.to_string());
+            }
+        }
+        self.state_loaded = true;
+        Ok(())
+    }
+    /// Save the sequence numbers of the stations to the state file
+    ///
+    /// One line per station which has received data, following the
+    /// lines of other stations read by load_state()
+    ///
+    /// ```text
+    /// NET STA SEQUENCE YYYY,MM,DD,hh,mm,ss
+    /// ```
+    ///
+    /// An existing state file is only replaced once it has been loaded
+    pub fn save_state(&self) -> io::Result<()> {
+        let file = match self.state_file {
+            Some(ref f) => f,
+            None => return Ok(()),
+        };
+        if !self.state_loaded && file.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("state file not loaded: {}", file.display()),
+            ));
+        }
+        let mut fp = File::create(file)?;
+        for line in &self.state_other {
+            writeln!(fp, "{}", line)?;
+        }
+        for st in &self.stations {
+            if let Some(seq) = st.sequence {
+                write!(fp, "{} {} {}", st.network, st.station, seq)?;
+                if let Some(t) = st.time {
+                    write!(fp, " {}", format_time(&t))?;
+                }
+                writeln!(fp)?;
+            }
+        }
+        fp.flush()
+    }
+    fn connect(&mut self) -> io::Result<()> {
+        if self.stations.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no stations requested",
+            ));
+        }
+        let mut conn = Connection::open(&self.addr)?;
+        let (id, _) = conn.hello(self.timeout)?;
+        self.server = Some(id);
+        for st in &self.stations {
+            conn.command(
+                &format!("STATION {} {}", st.station, st.network),
+                self.timeout,
+            )?;
+            for sel in &st.selectors {
+                conn.command(&format!("SELECT {}", sel), self.timeout)?;
+            }
+            conn.command(&st.command(&self.mode), self.timeout)?;
+        }
+        conn.send("END")?;
+        self.conn = Some(conn);
+        Ok(())
+    }
+    // Connect if needed and return the next packet, if any has arrived
+    fn step(&mut self) -> io::Result<Option<Packet>> {
+        if self.conn.is_none() {
+            self.connect()?;
+        }
+        let conn = self.conn.as_mut().unwrap();
+        if let Some(p) = conn.packet()? {
+            return Ok(Some(p));
+        }
+        if !conn.fill()? {
+            let idle = conn.last_recv.elapsed();
+            if idle > self.timeout {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no data from server",
+                ));
+            }
+            if let Some(k) = self.keepalive {
+                if idle >= k && conn.last_keepalive.elapsed() >= k {
+                    conn.send("INFO ID")?;
+                    conn.last_keepalive = Instant::now();
+                }
+            }
+        }
+        Ok(None)
+    }
+    // Delay before the next connection attempt
+    fn delay(&self) -> Duration {
+        let (min, max) = self.backoff;
+        let mut d = min;
+        for _ in 1..self.failures {
+            if d >= max {
+                break;
+            }
+            d *= 2;
+        }
+        std::cmp::min(d, max)
+    }
+}
+
+impl Iterator for SeedLinkClient {
+    type Item = ms_record;
+    fn next(&mut self) -> Option<ms_record> {
+        match self.next_record() {
+            Ok(Some((_, rec))) => Some(rec),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for SeedLinkClient {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -0,0 +1,76 @@
+//! SeedLink protocol support
+//!
+//! SeedLink v3 transfers 512 byte miniSEED records over TCP, each
+//! preceded by an 8 byte header of `SL` and a six hex digit sequence
+//! number.  A client negotiates the stations and streams it wants and
+//! resumes after a disconnection by requesting the packets following the
+//! last sequence number received from each station.
+//!
+//! ```
+//! use miniseed::seedlink::{Mode, SeedLinkClient, SeedLinkServer};
+//!
+//! let server = SeedLinkServer::from_files(&["tests/multiple.seed"]);
+//! let addr = server.spawn("127.0.0.1:0").unwrap();
+//!
+//! let client = SeedLinkClient::new(&addr.to_string())
+//!     .with_station("IU", "ANMO", &["00BHZ"])
+//!     .with_mode(Mode::Fetch)
+//!     .with_retries(0);
+//! assert_eq!(client.count(), 1243);
+//! ```
+
+use chrono::DateTime;
+use chrono::NaiveDate;
+use chrono::TimeZone;
+use chrono::Utc;
+
+pub mod client;
+pub mod packet;
+pub mod server;
+
+pub use self::client::{Mode, SeedLinkClient, Station};
+pub use self::packet::{PacketReader, SeedLinkPacket};
+pub use self::server::SeedLinkServer;
+
+/// Default SeedLink port
+pub const DEFAULT_PORT: u16 = 18000;
+
+/// Largest sequence number, sequence numbers wrap to 0 after this
+pub const MAX_SEQUENCE: u32 = 0xFF_FFFF;
+
+/// Format a time as used in SeedLink commands, `YYYY,MM,DD,hh,mm,ss`
+///
+/// ```
+/// # use miniseed::seedlink::format_time;
+/// # use miniseed::str_to_utc;
+/// let t = str_to_utc("2010-02-27T06:30:00.0195").unwrap();
+/// assert_eq!(format_time(&t), "2010,02,27,06,30,00");
+/// ```
+pub fn format_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y,%m,%d,%H,%M,%S").to_string()
+}
+
+/// Parse a time as used in SeedLink commands, `YYYY,MM,DD,hh,mm,ss`
+///
+/// Trailing fields may be omitted and default to zero
+///
+/// ```
+/// # use miniseed::seedlink::parse_time;
+/// # use miniseed::str_to_utc;
+/// let t = parse_time("2010,02,27,06,30,00").unwrap();
+/// assert_eq!(t, str_to_utc("2010-02-27T06:30:00").unwrap());
+/// assert_eq!(parse_time("2010,02,27"), str_to_utc("2010-02-27"));
+/// assert_eq!(parse_time("2010-02-27"), None);
+/// ```
+pub fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    let v: Vec<u32> = s
+        .split(',')
+        .map(|x| x.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    if v.len() < 3 || v.len() > 6 {
+        return None;
+    }
+    let f = |i: usize| v.get(i).cloned().unwrap_or(0);
+    let t = NaiveDate::from_ymd_opt(f(0) as i32, f(1), f(2))?.and_hms_opt(f(3), f(4), f(5))?;
+    Some(Utc.from_utc_datetime(&t))
+}
@@ -0,0 +1,196 @@
+//! SeedLink packet framing
+//!
+//! Each packet is an 8 byte header followed by a 512 byte record.  Data
+//! packets begin with `SL` and a six hex digit sequence number, INFO
+//! responses with `SLINFO` and `*` if more packets of the response
+//! follow or a space for the last one.  A `PacketReader` frames packets
+//! from bytes fed as they arrive.
+//!
+//! ```
+//! use miniseed::seedlink::{PacketReader, SeedLinkPacket};
+//! use std::io::Read;
+//!
+//! let mut buf = vec![];
+//! std::fs::File::open("tests/ff00b5d8b3124f1aa2de549070709634")
+//!     .unwrap()
+//!     .read_to_end(&mut buf)
+//!     .unwrap();
+//! assert_eq!(SeedLinkPacket::parse(&buf).unwrap(), Some(SeedLinkPacket::Data(0x95FCE3)));
+//!
+//! let mut reader = PacketReader::new();
+//! reader.feed(&buf[..100]);
+//! assert!(reader.next_record().unwrap().is_none());
+//! reader.feed(&buf[100..]);
+//! let (seq, rec) = reader.next_record().unwrap().unwrap();
+//! assert_eq!(seq, 0x95FCE3);
+//! assert_eq!(rec.id(), "PN_PPNAF_00_HHZ");
+//! ```
+
+use std::io;
+
+use super::MAX_SEQUENCE;
+use header::record_length;
+use ms_record;
+
+/// Length of the SeedLink packet header
+pub const HEADER_LEN: usize = 8;
+
+/// Length of the record in a SeedLink packet
+pub const RECORD_LEN: usize = 512;
+
+/// SeedLink packet header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeedLinkPacket {
+    /// Data packet with its sequence number
+    Data(u32),
+    /// INFO response packet, true if more packets of the response follow
+    Info(bool),
+}
+
+impl SeedLinkPacket {
+    /// Parse the packet header at the start of `buf`
+    ///
+    /// Returns None if fewer than 8 bytes are available and an error if
+    /// the signature or sequence number is invalid
+    pub fn parse(buf: &[u8]) -> io::Result<Option<SeedLinkPacket>> {
+        if buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        if &buf[..2] != b"SL" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid SeedLink packet signature",
+            ));
+        }
+        if &buf[2..6] == b"INFO" {
+            return Ok(Some(SeedLinkPacket::Info(buf[7] == b'*')));
+        }
+        std::str::from_utf8(&buf[2..HEADER_LEN])
+            .ok()
+            .and_then(|s| u32::from_str_radix(s, 16).ok())
+            .map(|seq| Some(SeedLinkPacket::Data(seq)))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid sequence number"))
+    }
+    /// Return the sequence number of a data packet
+    pub fn sequence(&self) -> Option<u32> {
+        match *self {
+            SeedLinkPacket::Data(seq) => Some(seq),
+            SeedLinkPacket::Info(_) => None,
+        }
+    }
+    /// Return true for INFO packets
+    pub fn is_info(&self) -> bool {
+        self.sequence().is_none()
+    }
+    /// Return the encoded packet header
+    ///
+    /// ```
+    /// # use miniseed::seedlink::SeedLinkPacket;
+    /// assert_eq!(&SeedLinkPacket::Data(0x1A).header(), b"SL00001A");
+    /// assert_eq!(&SeedLinkPacket::Info(true).header(), b"SLINFO *");
+    /// ```
+    pub fn header(&self) -> [u8; HEADER_LEN] {
+        let s = match *self {
+            SeedLinkPacket::Data(seq) => format!("SL{:06X}", seq & MAX_SEQUENCE),
+            SeedLinkPacket::Info(more) => format!("SLINFO {}", if more { '*' } else { ' ' }),
+        };
+        let mut h = [0u8; HEADER_LEN];
+        h.copy_from_slice(s.as_bytes());
+        h
+    }
+    /// Return the packet header followed by `record`
+    pub fn encode(&self, record: &[u8]) -> Vec<u8> {
+        let mut buf = self.header().to_vec();
+        buf.extend_from_slice(record);
+        buf
+    }
+    /// Return the packet header and total length of the packet at the
+    /// start of `buf`
+    ///
+    /// Returns None if the packet is incomplete, and an error if the
+    /// header is invalid or the record is not a 512 byte miniSEED record
+    pub fn frame(buf: &[u8]) -> io::Result<Option<(SeedLinkPacket, usize)>> {
+        let pkt = match SeedLinkPacket::parse(buf)? {
+            Some(pkt) => pkt,
+            None => return Ok(None),
+        };
+        if buf.len() < HEADER_LEN + RECORD_LEN {
+            return Ok(None);
+        }
+        match record_length(&buf[HEADER_LEN..HEADER_LEN + RECORD_LEN], true) {
+            Some(RECORD_LEN) => Ok(Some((pkt, HEADER_LEN + RECORD_LEN))),
+            Some(n) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid SeedLink record length: {}", n),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "SeedLink packet does not contain a miniSEED record",
+            )),
+        }
+    }
+}
+
+/// Incremental framing of SeedLink packets from a byte stream
+///
+/// Besides packets a server sends text responses to commands, lines
+/// ending in `\r\n`, and `END` at the end of a transfer.  These are read
+/// with take_line() and take_end().
+#[derive(Debug, Clone, Default)]
+pub struct PacketReader {
+    buf: Vec<u8>,
+}
+
+impl PacketReader {
+    /// Create an empty reader
+    pub fn new() -> PacketReader {
+        PacketReader { buf: vec![] }
+    }
+    /// Add bytes received
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+    /// Return the bytes received but not yet read
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+    /// Remove all buffered bytes
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+    /// Return the next complete packet and its record
+    pub fn next_packet(&mut self) -> io::Result<Option<(SeedLinkPacket, ms_record)>> {
+        let (pkt, n) = match SeedLinkPacket::frame(&self.buf)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        // An invalid record is removed with its packet
+        let rec = ms_record::try_parse(&self.buf[HEADER_LEN..n]);
+        self.buf.drain(..n);
+        Ok(Some((pkt, rec?)))
+    }
+    /// Return the sequence number and record of the next complete data
+    /// packet, INFO packets are skipped
+    pub fn next_record(&mut self) -> io::Result<Option<(u32, ms_record)>> {
+        while let Some((pkt, rec)) = self.next_packet()? {
+            if let SeedLinkPacket::Data(seq) = pkt {
+                return Ok(Some((seq, rec)));
+            }
+        }
+        Ok(None)
+    }
+    /// Remove and return the next line of text, without the `\r\n`
+    pub fn take_line(&mut self) -> Option<String> {
+        let i = self.buf.windows(2).position(|w| w == b"\r\n")?;
+        let line: Vec<u8> = self.buf.drain(..i + 2).take(i).collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+    /// Remove `END` and return true if the buffer begins with it
+    pub fn take_end(&mut self) -> bool {
+        if self.buf.starts_with(b"END") {
+            self.buf.drain(..3);
+            return true;
+        }
+        false
+    }
+}
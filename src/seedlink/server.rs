@@ -0,0 +1,605 @@
+//! SeedLink server
+//!
+//! Records from files or an SDS archive are numbered in order of start
+//! time and served to clients in multi-station or uni-station mode.
+//! Clients select stations with `STATION`, streams with `SELECT` and
+//! where to start with `DATA`, `FETCH` or `TIME`.  `INFO ID`, `STATIONS`
+//! and `STREAMS` are answered with XML in INFO packets.  With replay,
+//! records are released as if acquired in real time, starting from the
+//! first record when the server starts.
+//!
+//! ```no_run
+//! use miniseed::seedlink::SeedLinkServer;
+//!
+//! let server = SeedLinkServer::from_files(&["tests/multiple.seed"])
+//!     .with_organization("Example")
+//!     .with_replay(1.0);
+//! let listener = std::net::TcpListener::bind("127.0.0.1:18000").unwrap();
+//! server.serve(listener).unwrap();
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use super::packet::RECORD_LEN;
+use super::{parse_time, Mode, SeedLinkPacket};
+use repack::Repacker;
+use sds::SdsArchive;
+use selection::wildcard_match;
+use {ms_input, ms_record, Samples, Selection, Trace, DE_ASCII, DE_STEIM2};
+
+/// Server identification returned by HELLO
+const SOFTWARE: &str = "SeedLink v3.1 (miniseed-rs)";
+
+/// Interval between checks for commands and released records
+const TICK: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Longest command line accepted, longer lines close the session
+const MAX_LINE: usize = 1024;
+
+/// Record served with its sequence number
+struct Entry {
+    seq: u32,
+    network: String,
+    station: String,
+    location: String,
+    channel: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bytes: Vec<u8>,
+}
+
+/// SeedLink v3 server
+#[derive(Clone)]
+pub struct SeedLinkServer {
+    entries: Arc<Vec<Entry>>,
+    organization: String,
+    replay: Option<f64>,
+}
+
+impl SeedLinkServer {
+    /// Create a server for a set of records
+    ///
+    /// Records are numbered in order of start time.  Records which are
+    /// not 512 bytes long are repacked into 512 byte Steim-2 records,
+    /// or the default encoding for the sample type.
+    pub fn new(records: Vec<ms_record>) -> SeedLinkServer {
+        let mut bufs = vec![];
+        let mut other = vec![];
+        for rec in records {
+            if rec.reclen() == RECORD_LEN {
+                bufs.push(rec);
+            } else {
+                other.push(rec);
+            }
+        }
+        let (packed, _) = Repacker::new(RECORD_LEN, DE_STEIM2).repack(other);
+        bufs.extend(packed);
+        bufs.sort_by_key(|r| r.start());
+        let entries = bufs
+            .iter()
+            .enumerate()
+            .map(|(i, rec)| Entry {
+                seq: i as u32,
+                network: rec.network(),
+                station: rec.station(),
+                location: rec.location(),
+                channel: rec.channel(),
+                start: rec.start(),
+                end: rec.end1(),
+                bytes: rec.raw_bytes().to_vec(),
+            })
+            .collect();
+        SeedLinkServer {
+            entries: Arc::new(entries),
+            organization: "miniseed-rs".to_string(),
+            replay: None,
+        }
+    }
+    /// Create a server for the records of a set of files
+    pub fn from_files<S: AsRef<Path>>(files: &[S]) -> SeedLinkServer {
+        SeedLinkServer::new(files.iter().flat_map(ms_input::open).collect())
+    }
+    /// Create a server for the records of the files in a directory
+    ///
+    /// Subdirectories are not searched
+    pub fn from_dir<S: AsRef<Path>>(dir: S) -> io::Result<SeedLinkServer> {
+        let mut files = vec![];
+        for e in fs::read_dir(dir)? {
+            let path = e?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(SeedLinkServer::from_files(&files))
+    }
+    /// Create a server for the records of an SDS archive matching the
+    /// selection between `start` and `end`
+    pub fn from_sds(
+        sds: &SdsArchive,
+        sel: &Selection,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> SeedLinkServer {
+        SeedLinkServer::new(sds.records(sel, start, end))
+    }
+    /// Set the organization returned by HELLO and INFO
+    pub fn with_organization(mut self, organization: &str) -> SeedLinkServer {
+        self.organization = organization.to_string();
+        self
+    }
+    /// Release records at `speed` times real time
+    ///
+    /// Records are released when the time since the server started,
+    /// scaled by `speed`, reaches their start time relative to the first
+    /// record
+    pub fn with_replay(mut self, speed: f64) -> SeedLinkServer {
+        self.replay = Some(speed);
+        self
+    }
+    /// Return the number of records served
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Return true if there are no records to serve
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Accept connections, each served by a separate thread
+    ///
+    /// Replay starts when this is called
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        let started = Instant::now();
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                let _ = Session::new(&server, started, stream).and_then(|mut s| s.run());
+            });
+        }
+        Ok(())
+    }
+    /// Bind to `addr` and serve connections in a background thread
+    ///
+    /// Returns the address bound, useful with port 0
+    pub fn spawn(self, addr: &str) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        thread::spawn(move || self.serve(listener));
+        Ok(local)
+    }
+    // Number of records released after `started`
+    fn released(&self, started: Instant) -> usize {
+        let speed = match self.replay {
+            Some(speed) => speed,
+            None => return self.entries.len(),
+        };
+        let t0 = match self.entries.first() {
+            Some(e) => e.start,
+            None => return 0,
+        };
+        let elapsed = started.elapsed().as_secs_f64() * speed;
+        let t = t0 + Duration::microseconds((elapsed * 1e6) as i64);
+        self.entries
+            .iter()
+            .position(|e| e.start > t)
+            .unwrap_or(self.entries.len())
+    }
+    fn info(&self, level: &str) -> Option<String> {
+        let mut xml = format!(
+            "<?xml version=\"1.0\"?>\n<seedlink software=\"{}\" organization=\"{}\">\n",
+            SOFTWARE,
+            xml_escape(&self.organization)
+        );
+        match level {
+            "ID" => {}
+            "STATIONS" | "STREAMS" => {
+                let mut stations: Vec<(&str, &str)> = self
+                    .entries
+                    .iter()
+                    .map(|e| (e.network.as_str(), e.station.as_str()))
+                    .collect();
+                stations.sort();
+                stations.dedup();
+                for (net, sta) in stations {
+                    let entries: Vec<&Entry> = self
+                        .entries
+                        .iter()
+                        .filter(|e| e.network == net && e.station == sta)
+                        .collect();
+                    xml += &format!(
+                        "<station name=\"{}\" network=\"{}\" description=\"\" begin_seq=\"{:06X}\" end_seq=\"{:06X}\"",
+                        sta,
+                        net,
+                        entries[0].seq,
+                        entries[entries.len() - 1].seq
+                    );
+                    if level == "STATIONS" {
+                        xml += "/>\n";
+                        continue;
+                    }
+                    xml += ">\n";
+                    let mut streams: Vec<(&str, &str)> = entries
+                        .iter()
+                        .map(|e| (e.location.as_str(), e.channel.as_str()))
+                        .collect();
+                    streams.sort();
+                    streams.dedup();
+                    for (loc, cha) in streams {
+                        let s: Vec<&&Entry> = entries
+                            .iter()
+                            .filter(|e| e.location == loc && e.channel == cha)
+                            .collect();
+                        let begin = s.iter().map(|e| e.start).min().unwrap();
+                        let end = s.iter().map(|e| e.end).max().unwrap();
+                        xml += &format!(
+                            "<stream location=\"{}\" seedname=\"{}\" type=\"D\" begin_time=\"{}\" end_time=\"{}\"/>\n",
+                            loc,
+                            cha,
+                            info_time(&begin),
+                            info_time(&end)
+                        );
+                    }
+                    xml += "</station>\n";
+                }
+            }
+            _ => return None,
+        }
+        xml += "</seedlink>\n";
+        Some(xml)
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn info_time(t: &DateTime<Utc>) -> String {
+    format!(
+        "{}.{:04}",
+        t.format("%Y/%m/%d %H:%M:%S"),
+        t.timestamp_subsec_micros() / 100
+    )
+}
+
+/// Return true if the location and channel match a stream selector
+/// `LLCCC.T`
+///
+/// The location may be omitted and `--` matches an empty location
+fn selector_match(sel: &str, loc: &str, cha: &str) -> bool {
+    let (lc, typ) = match sel.find('.') {
+        Some(i) => (&sel[..i], &sel[i + 1..]),
+        None => (sel, ""),
+    };
+    if !typ.is_empty() && !wildcard_match(typ, "D") {
+        return false;
+    }
+    let (lp, cp) = if lc.len() > 3 {
+        lc.split_at(lc.len() - 3)
+    } else {
+        ("*", lc)
+    };
+    let loc = format!("{:2}", loc);
+    let lp = if lp == "--" { "  " } else { lp };
+    wildcard_match(lp, &loc) && wildcard_match(cp, cha)
+}
+
+/// Station requested by a client
+struct Request {
+    network: String,
+    station: String,
+    selectors: Vec<String>,
+    mode: Mode,
+    seq: Option<u32>,
+    time: Option<DateTime<Utc>>,
+}
+
+impl Request {
+    fn new(network: &str, station: &str) -> Request {
+        Request {
+            network: network.to_string(),
+            station: station.to_string(),
+            selectors: vec![],
+            mode: Mode::Data,
+            seq: None,
+            time: None,
+        }
+    }
+    fn wants(&self, e: &Entry) -> bool {
+        if !wildcard_match(&self.network, &e.network) || !wildcard_match(&self.station, &e.station)
+        {
+            return false;
+        }
+        let (neg, pos): (Vec<&String>, Vec<&String>) =
+            self.selectors.iter().partition(|s| s.starts_with('!'));
+        if !pos.is_empty()
+            && !pos
+                .iter()
+                .any(|s| selector_match(s, &e.location, &e.channel))
+        {
+            return false;
+        }
+        if neg
+            .iter()
+            .any(|s| selector_match(&s[1..], &e.location, &e.channel))
+        {
+            return false;
+        }
+        match self.mode {
+            Mode::Data | Mode::Fetch => match (self.seq, self.time) {
+                (Some(seq), _) => e.seq >= seq,
+                (None, Some(t)) => e.start >= t,
+                (None, None) => true,
+            },
+            Mode::Time(start, end) => e.end > start && !matches!(end, Some(end) if e.start >= end),
+        }
+    }
+    // Return true if the request is complete once records up to `cursor`
+    // are sent
+    fn done(&self, entries: &[Entry], cursor: usize, released: usize) -> bool {
+        match self.mode {
+            Mode::Data | Mode::Time(_, None) => false,
+            Mode::Fetch => cursor >= released,
+            Mode::Time(_, Some(end)) => !matches!(entries.get(cursor), Some(e) if e.start < end),
+        }
+    }
+}
+
+/// Connection to a client
+struct Session<'a> {
+    server: &'a SeedLinkServer,
+    started: Instant,
+    stream: TcpStream,
+    buf: Vec<u8>,
+    requests: Vec<Request>,
+    multi: bool,
+    /// False after a STATION command is rejected
+    station_ok: bool,
+    streaming: bool,
+    ended: bool,
+    cursor: usize,
+}
+
+impl<'a> Session<'a> {
+    fn new(
+        server: &'a SeedLinkServer,
+        started: Instant,
+        stream: TcpStream,
+    ) -> io::Result<Session<'a>> {
+        stream.set_read_timeout(Some(TICK))?;
+        stream.set_nodelay(true)?;
+        Ok(Session {
+            server,
+            started,
+            stream,
+            buf: vec![],
+            requests: vec![],
+            multi: false,
+            station_ok: false,
+            streaming: false,
+            ended: false,
+            cursor: 0,
+        })
+    }
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            if let Some(line) = self.line()? {
+                if !self.command(&line)? {
+                    return Ok(());
+                }
+            }
+            if self.streaming && !self.ended {
+                self.pump()?;
+            }
+        }
+    }
+    // Return the next command line, None if none arrived within a tick
+    fn line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(i) = self.buf.iter().take(MAX_LINE + 1).position(|&c| c == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..i + 1).collect();
+                return Ok(Some(String::from_utf8_lossy(&line).trim().to_string()));
+            }
+            if self.buf.len() > MAX_LINE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("command line longer than {} bytes", MAX_LINE),
+                ));
+            }
+            let mut tmp = [0u8; 1024];
+            match self.stream.read(&mut tmp) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed by client",
+                    ))
+                }
+                Ok(n) => self.buf.extend_from_slice(&tmp[..n]),
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    fn reply(&mut self, ok: bool) -> io::Result<()> {
+        self.stream
+            .write_all(if ok { b"OK\r\n" } else { b"ERROR\r\n" })
+    }
+    // Handle a command, returns false when the client says BYE
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let v: Vec<&str> = line.split_whitespace().collect();
+        if v.is_empty() {
+            return Ok(true);
+        }
+        match v[0].to_uppercase().as_str() {
+            "HELLO" => {
+                let msg = format!("{}\r\n{}\r\n", SOFTWARE, self.server.organization);
+                self.stream.write_all(msg.as_bytes())?;
+            }
+            "BYE" => return Ok(false),
+            "INFO" => {
+                let level = v.get(1).map_or(String::new(), |s| s.to_uppercase());
+                match self.server.info(&level) {
+                    Some(xml) => self.send_info(&xml)?,
+                    None => self.reply(false)?,
+                }
+            }
+            _ if self.streaming => self.reply(false)?,
+            "STATION" if v.len() >= 2 => {
+                let net = v.get(2).cloned().unwrap_or("*");
+                let req = Request::new(net, v[1]);
+                self.multi = true;
+                let ok = self.server.entries.iter().any(|e| {
+                    wildcard_match(&req.network, &e.network)
+                        && wildcard_match(&req.station, &e.station)
+                });
+                if ok {
+                    self.requests.push(req);
+                }
+                self.station_ok = ok;
+                self.reply(ok)?;
+            }
+            "SELECT" => {
+                let multi = self.multi;
+                match self.current() {
+                    Some(req) => {
+                        match v.get(1) {
+                            Some(sel) => req.selectors.push(sel.to_string()),
+                            None => req.selectors.clear(),
+                        }
+                        if multi {
+                            self.reply(true)?;
+                        }
+                    }
+                    None => self.reply(false)?,
+                }
+            }
+            "DATA" | "FETCH" | "TIME" => {
+                let ok = self.transfer(&v);
+                if self.multi {
+                    self.reply(ok)?;
+                } else if ok {
+                    self.streaming = true;
+                } else {
+                    self.reply(false)?;
+                }
+            }
+            "END" if self.multi && !self.requests.is_empty() => self.streaming = true,
+            _ => self.reply(false)?,
+        }
+        Ok(true)
+    }
+    // Request for the current station, the catch-all request in
+    // uni-station mode
+    fn current(&mut self) -> Option<&mut Request> {
+        if !self.multi && self.requests.is_empty() {
+            self.requests.push(Request::new("*", "*"));
+        }
+        if self.multi && !self.station_ok {
+            return None;
+        }
+        self.requests.last_mut()
+    }
+    // Set the transfer mode of the current station
+    fn transfer(&mut self, v: &[&str]) -> bool {
+        let n = self.server.entries.len() as u32;
+        let cmd = v[0].to_uppercase();
+        let req = match self.current() {
+            Some(req) => req,
+            None => return false,
+        };
+        if cmd == "TIME" {
+            let start = match v.get(1).and_then(|s| parse_time(s)) {
+                Some(t) => t,
+                None => return false,
+            };
+            let end = match v.get(2) {
+                Some(s) => match parse_time(s) {
+                    Some(t) => Some(t),
+                    None => return false,
+                },
+                None => None,
+            };
+            req.mode = Mode::Time(start, end);
+            return true;
+        }
+        req.mode = if cmd == "DATA" {
+            Mode::Data
+        } else {
+            Mode::Fetch
+        };
+        if let Some(s) = v.get(1) {
+            match u32::from_str_radix(s, 16) {
+                // Unknown sequence numbers fall back to the time, if any
+                Ok(seq) => req.seq = if seq <= n { Some(seq) } else { None },
+                Err(_) => return false,
+            }
+        }
+        if let Some(s) = v.get(2) {
+            match parse_time(s) {
+                Some(t) => req.time = Some(t),
+                None => return false,
+            }
+        }
+        true
+    }
+    // Send the released records wanted by the requests
+    fn pump(&mut self) -> io::Result<()> {
+        let entries = &self.server.entries;
+        let released = self.server.released(self.started);
+        while self.cursor < released {
+            let e = &entries[self.cursor];
+            if self.requests.iter().any(|r| r.wants(e)) {
+                self.stream
+                    .write_all(&SeedLinkPacket::Data(e.seq).encode(&e.bytes))?;
+            }
+            self.cursor += 1;
+        }
+        if self
+            .requests
+            .iter()
+            .all(|r| r.done(entries, self.cursor, released))
+        {
+            self.stream.write_all(b"END")?;
+            self.ended = true;
+        }
+        Ok(())
+    }
+    // Send an INFO response as ASCII records
+    fn send_info(&mut self, xml: &str) -> io::Result<()> {
+        let tr = Trace {
+            network: "SL".to_string(),
+            station: "INFO".to_string(),
+            location: "".to_string(),
+            channel: "INF".to_string(),
+            quality: 'D',
+            start: Utc::now(),
+            samprate: 0.0,
+            data: Samples::Ascii(xml.as_bytes().to_vec()),
+        };
+        let recs = tr.pack_bytes(RECORD_LEN, DE_ASCII);
+        for (i, rec) in recs.iter().enumerate() {
+            let pkt = SeedLinkPacket::Info(i + 1 < recs.len());
+            self.stream.write_all(&pkt.encode(rec))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+//! Record selection by NSLC patterns and time windows
+//!
+//! A `Selection` is a list of network, station, location and channel
+//! patterns, each with an optional quality code and any number of time
+//! windows.  Patterns may contain `?` (any single character) and `*`
+//! (any sequence of characters) wildcards.
+//!
+//! ```
+//! use miniseed::{ms_input, Selection};
+//!
+//! let mut sel = Selection::new();
+//! sel.add("IU_ANMO_*_BH?", None, None);
+//!
+//! let input = ms_input::open("tests/multiple.seed").with_selection(sel);
+//! assert_eq!(input.count(), 1243);
+//! ```
+//!
+//! Selections may also be read from libmseed / dataselect style
+//! selection files:
+//!
+//! ```text
+//! # Network Station Location Channel [Quality [Start [End]]]
+//! IU ANMO 00 BHZ
+//! IU *    -- BH? D 2010-02-27T06:30:00 2010-02-27T07:00:00
+//! ```
+
+use chrono::DateTime;
+use chrono::Utc;
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use header::RawHeader;
+use ms_record;
+use str_to_utc;
+
+/// Time window, a missing start or end is unbounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    /// Create a new time window
+    pub fn new(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> TimeWindow {
+        TimeWindow { start, end }
+    }
+    /// Return true if the span `[start, end]` overlaps the window
+    pub fn overlaps(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+        if let Some(ref s) = self.start {
+            if end < s {
+                return false;
+            }
+        }
+        if let Some(ref e) = self.end {
+            if start > e {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A single selection pattern with its quality and time windows
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectEntry {
+    /// Network, Station, Location and Channel patterns
+    pub nslc: [String; 4],
+    /// Quality code, `None` matches any quality
+    pub quality: Option<char>,
+    /// Time windows, empty matches all times
+    pub windows: Vec<TimeWindow>,
+}
+
+impl SelectEntry {
+    /// Return the pattern in the form `NET_STA_LOC_CHA`
+    pub fn pattern(&self) -> String {
+        self.nslc.join("_")
+    }
+    /// Return true if the id and quality match this entry
+    pub fn matches_id(&self, net: &str, sta: &str, loc: &str, cha: &str, quality: char) -> bool {
+        if let Some(q) = self.quality {
+            if q != quality {
+                return false;
+            }
+        }
+        wildcard_match(&self.nslc[0], net)
+            && wildcard_match(&self.nslc[1], sta)
+            && wildcard_match(&self.nslc[2], loc)
+            && wildcard_match(&self.nslc[3], cha)
+    }
+    /// Return true if the span `[start, end]` overlaps any time window
+    pub fn matches_time(&self, start: &DateTime<Utc>, end: &DateTime<Utc>) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.overlaps(start, end))
+    }
+}
+
+/// Record selection
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    entries: Vec<SelectEntry>,
+}
+
+impl Selection {
+    /// Create an empty selection, which matches nothing
+    pub fn new() -> Selection {
+        Selection { entries: vec![] }
+    }
+    /// Add a pattern of the form `NET_STA_LOC_CHA` with a time window
+    ///
+    /// Adding the same pattern more than once adds time windows to
+    /// the existing pattern.
+    ///
+    /// ```
+    /// # use miniseed::Selection;
+    /// let mut sel = Selection::new();
+    /// sel.add("PN_PPNAF_00_HH?", None, None);
+    /// assert!(sel.matches_id("PN", "PPNAF", "00", "HHZ", 'D'));
+    /// assert!(!sel.matches_id("PN", "PPNAF", "00", "BHZ", 'D'));
+    /// ```
+    pub fn add(
+        &mut self,
+        pattern: &str,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> &mut Selection {
+        self.add_with_quality(pattern, None, start, end)
+    }
+    /// Add a pattern of the form `NET_STA_LOC_CHA` with a quality
+    /// code and a time window
+    pub fn add_with_quality(
+        &mut self,
+        pattern: &str,
+        quality: Option<char>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> &mut Selection {
+        let mut parts = pattern.splitn(4, '_').map(|s| s.to_string());
+        let mut nslc: [String; 4] = Default::default();
+        for v in nslc.iter_mut() {
+            *v = parts.next().unwrap_or_else(|| "*".to_string());
+        }
+        let window = if start.is_some() || end.is_some() {
+            Some(TimeWindow::new(start, end))
+        } else {
+            None
+        };
+        if let Some(e) = self
+            .entries
+            .iter_mut()
+            .find(|e| e.nslc == nslc && e.quality == quality)
+        {
+            match window {
+                Some(w) => {
+                    if !e.windows.is_empty() {
+                        e.windows.push(w)
+                    }
+                }
+                None => e.windows.clear(),
+            }
+            return self;
+        }
+        self.entries.push(SelectEntry {
+            nslc,
+            quality,
+            windows: window.into_iter().collect(),
+        });
+        self
+    }
+    /// Return the selection entries
+    pub fn entries(&self) -> &[SelectEntry] {
+        &self.entries
+    }
+    /// Return true if the selection has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Return true if the id and quality match any entry, ignoring time
+    pub fn matches_id(&self, net: &str, sta: &str, loc: &str, cha: &str, quality: char) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.matches_id(net, sta, loc, cha, quality))
+    }
+    /// Return true if the id, quality and time span match any entry
+    ///
+    /// `nslc` holds the network, station, location and channel codes
+    pub fn matches_span(
+        &self,
+        nslc: [&str; 4],
+        quality: char,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> bool {
+        let [net, sta, loc, cha] = nslc;
+        self.entries
+            .iter()
+            .any(|e| e.matches_id(net, sta, loc, cha, quality) && e.matches_time(start, end))
+    }
+    /// Return true if a raw record header matches the selection
+    pub fn matches_header(&self, h: &RawHeader) -> bool {
+        self.matches_span(
+            [&h.network, &h.station, &h.location, &h.channel],
+            h.quality,
+            &h.start(),
+            &h.end(),
+        )
+    }
+    /// Return true if the record matches the selection
+    ///
+    /// Only the record header is used, so this may be called on records
+    /// read without data samples
+    ///
+    /// ```
+    /// # use miniseed::{ms_record, Selection, str_to_utc};
+    /// let rec = ms_record::read("tests/sample.miniseed");
+    /// let mut sel = Selection::new();
+    /// sel.add("PN_*", str_to_utc("2016-10-30T18:00:00"), str_to_utc("2016-10-30T18:03:00"));
+    /// assert!(sel.matches(&rec));
+    /// ```
+    pub fn matches(&self, rec: &ms_record) -> bool {
+        let start = rec.start();
+        let end = rec.header_end();
+        self.matches_span(
+            [
+                &rec.network(),
+                &rec.station(),
+                &rec.location(),
+                &rec.channel(),
+            ],
+            rec.dataquality().chars().next().unwrap_or(' '),
+            &start,
+            &end,
+        )
+    }
+    /// Parse a selection from a libmseed / dataselect selection file
+    pub fn from_file<S: AsRef<Path>>(file: S) -> io::Result<Selection> {
+        let mut s = String::new();
+        File::open(file)?.read_to_string(&mut s)?;
+        Selection::parse(&s)
+    }
+    /// Parse a selection from the text of a selection file
+    ///
+    /// Each line contains `Network Station Location Channel` and
+    /// optionally `Quality Start End`.  A location of `--` is an empty
+    /// location code, and `*` may be used for any optional field.
+    /// Lines beginning with `#` are comments.
+    ///
+    /// ```
+    /// # use miniseed::Selection;
+    /// let sel = Selection::parse("# Comment\nIU ANMO -- BH? D 2010-02-27 2010-02-28").unwrap();
+    /// assert!(sel.matches_id("IU", "ANMO", "", "BHZ", 'D'));
+    /// assert!(!sel.matches_id("IU", "ANMO", "00", "BHZ", 'D'));
+    /// assert!(!sel.matches_id("IU", "ANMO", "", "BHZ", 'M'));
+    /// ```
+    pub fn parse(text: &str) -> io::Result<Selection> {
+        let mut sel = Selection::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let v: Vec<&str> = line.split_whitespace().collect();
+            if v.len() < 4 || v.len() > 7 {
+                return Err(invalid(i, line, "expected 4 to 7 fields"));
+            }
+            let loc = if v[2] == "--" { "" } else { v[2] };
+            let pattern = format!("{}_{}_{}_{}", v[0], v[1], loc, v[3]);
+            let quality = match v.get(4) {
+                None | Some(&"*") => None,
+                Some(q) if q.len() == 1 => q.chars().next(),
+                Some(_) => return Err(invalid(i, line, "invalid quality code")),
+            };
+            let mut times = [None, None];
+            for (k, t) in times.iter_mut().enumerate() {
+                *t = match v.get(5 + k) {
+                    None | Some(&"*") => None,
+                    Some(s) => match str_to_utc(s) {
+                        Some(t) => Some(t),
+                        None => return Err(invalid(i, line, "invalid time")),
+                    },
+                };
+            }
+            sel.add_with_quality(&pattern, quality, times[0], times[1]);
+        }
+        Ok(sel)
+    }
+}
+
+fn invalid(i: usize, line: &str, msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("selection line {}: {}: {}", i + 1, msg, line),
+    )
+}
+
+/// Match text against a pattern with `?` and `*` wildcards
+///
+/// ```
+/// # use miniseed::selection::wildcard_match;
+/// assert!(wildcard_match("BH?", "BHZ"));
+/// assert!(wildcard_match("*", ""));
+/// assert!(wildcard_match("A*O", "ANMO"));
+/// assert!(!wildcard_match("BH?", "HHZ"));
+/// ```
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
@@ -0,0 +1,108 @@
+//! Splitting files by channel and time
+//!
+//! Records are written to one file per channel and time period, named by
+//! a `PathTemplate` relative to an output directory.  The period defaults
+//! to that of the template, e.g. a template containing `%j` gives one
+//! file per day and one without time tokens a file per channel.
+//! Optionally records are cut at period boundaries so that each file
+//! covers exactly its period.
+//!
+//! ```
+//! use miniseed::ms_input;
+//! use miniseed::archive::Period;
+//! use miniseed::split::Splitter;
+//!
+//! let dir = std::env::temp_dir().join("miniseed-split-doc");
+//! let mut split = Splitter::new(&dir, "%n.%s.%l.%c.%Y%m%d%H")
+//!     .with_cut(true);
+//! split.split(ms_input::open("tests/multiple.seed")).unwrap();
+//! split.close().unwrap();
+//! assert_eq!(split.period(), Period::Hour);
+//! assert_eq!(split.paths()[0], dir.join("IU.ANMO.00.BHZ.2010022706"));
+//! ```
+
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use archive::{Archive, ArchiveWriter, PathTemplate, Period};
+use {ms_input, ms_record};
+
+/// Writer of records to files by channel and time period
+///
+/// Existing files are truncated when first written to.  As with an
+/// `ArchiveWriter` a bounded number of files are kept open.
+pub struct Splitter {
+    writer: ArchiveWriter,
+    period: Period,
+}
+
+impl Splitter {
+    /// Create a splitter writing to `dir` with file names from `template`
+    ///
+    /// See archive::PathTemplate for the template tokens
+    pub fn new<S: AsRef<Path>>(dir: S, template: &str) -> Splitter {
+        let template = PathTemplate::new(template);
+        let period = template.period();
+        let writer = ArchiveWriter::new(Archive::new(dir, template)).with_truncate(true);
+        Splitter { writer, period }
+    }
+    /// Set the time period covered by each file
+    ///
+    /// The template should contain the time tokens needed to distinguish
+    /// periods, otherwise successive periods are written to the same file
+    pub fn with_period(self, period: Period) -> Splitter {
+        Splitter {
+            writer: self.writer.with_period(period),
+            period,
+        }
+    }
+    /// Cut records which straddle a period boundary
+    ///
+    /// Cut records are repacked with the original record length and
+    /// encoding
+    pub fn with_cut(self, cut: bool) -> Splitter {
+        Splitter {
+            writer: self.writer.with_split(cut),
+            period: self.period,
+        }
+    }
+    /// Set the maximum number of open files
+    pub fn with_max_open(self, max_open: usize) -> Splitter {
+        Splitter {
+            writer: self.writer.with_max_open(max_open),
+            period: self.period,
+        }
+    }
+    /// Return the time period covered by each file
+    pub fn period(&self) -> Period {
+        self.period
+    }
+    /// Return the files written to, in the order first written
+    pub fn paths(&self) -> &[PathBuf] {
+        self.writer.paths()
+    }
+    /// Write a record to its file
+    pub fn write(&mut self, rec: &ms_record) -> io::Result<()> {
+        self.writer.write(rec)
+    }
+    /// Write all records of an input
+    ///
+    /// Returns the number of records read
+    pub fn split(&mut self, input: ms_input) -> io::Result<usize> {
+        let mut n = 0;
+        for rec in input {
+            self.writer.write(&rec)?;
+            n += 1;
+        }
+        Ok(n)
+    }
+    /// Flush all open files
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+    /// Flush and close all open files
+    pub fn close(&mut self) -> io::Result<()> {
+        self.writer.close()
+    }
+}
@@ -0,0 +1,192 @@
+//! Async record streams
+//!
+//! `RecordStream` reads records from any tokio `AsyncRead` as a
+//! `futures::Stream` and `RecordSink` writes records to any
+//! `AsyncWrite` as a `futures::Sink`.  Records are framed with the
+//! reader::Framer used by RecordReader, so both accept the same
+//! streams.  Requires the `async` feature.
+//!
+//! ```
+//! # extern crate futures;
+//! # extern crate miniseed;
+//! # fn main() {
+//! use futures::executor::block_on;
+//! use futures::StreamExt;
+//! use miniseed::{RecordSink, RecordStream};
+//! use std::fs;
+//!
+//! let buf = fs::read("tests/multiple.seed").unwrap();
+//! let stream = RecordStream::new(&buf[..]);
+//! let recs: Vec<_> = block_on(stream.collect::<Vec<_>>());
+//! assert_eq!(recs.len(), 1243);
+//!
+//! let mut sink = RecordSink::new(Vec::new());
+//! block_on(futures::stream::iter(recs).forward(&mut sink)).unwrap();
+//! assert_eq!(sink.into_inner(), buf);
+//! # }
+//! ```
+
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use reader::Framer;
+use {ms_record, Selection};
+
+/// Records read from an async byte stream
+pub struct RecordStream<R: AsyncRead + Unpin> {
+    reader: R,
+    framer: Framer,
+}
+
+impl<R: AsyncRead + Unpin> RecordStream<R> {
+    /// Read records from `reader`
+    pub fn new(reader: R) -> RecordStream<R> {
+        RecordStream {
+            reader,
+            framer: Framer::new(),
+        }
+    }
+    /// Only return records matching the selection
+    ///
+    /// Record headers are checked before the records are parsed
+    pub fn with_selection(mut self, selection: Selection) -> RecordStream<R> {
+        self.framer = self.framer.with_selection(selection);
+        self
+    }
+    /// Return the number of bytes read up to the end of the last record
+    pub fn offset(&self) -> u64 {
+        self.framer.offset()
+    }
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+    /// Poll for the bytes of the next record
+    ///
+    /// Returns None at the end of the stream and an error if the stream
+    /// does not contain records
+    pub fn poll_raw(&mut self, cx: &mut Context) -> Poll<Option<io::Result<Vec<u8>>>> {
+        loop {
+            match self.framer.next_raw() {
+                Ok(Some(raw)) => return Poll::Ready(Some(Ok(raw))),
+                Ok(None) if self.framer.is_finished() => return Poll::Ready(None),
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+            let mut tmp = [0u8; 8192];
+            let mut buf = ReadBuf::new(&mut tmp);
+            match Pin::new(&mut self.reader).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => self.framer.finish(),
+                Poll::Ready(Ok(())) => self.framer.extend(buf.filled()),
+                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for RecordStream<R> {
+    type Item = io::Result<ms_record>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_raw(cx)
+            .map(|r| r.map(|r| r.and_then(|raw| ms_record::try_parse(&raw))))
+    }
+}
+
+/// Records written to an async byte stream
+///
+/// The raw bytes of each record are written unchanged, as with
+/// ms_output::with_passthrough, and records are buffered until the sink
+/// is flushed or the buffer holds at least `capacity` bytes.
+pub struct RecordSink<W: AsyncWrite + Unpin> {
+    writer: W,
+    buf: Vec<u8>,
+    capacity: usize,
+}
+
+impl<W: AsyncWrite + Unpin> RecordSink<W> {
+    /// Write records to `writer`
+    pub fn new(writer: W) -> RecordSink<W> {
+        RecordSink {
+            writer,
+            buf: vec![],
+            capacity: 8192,
+        }
+    }
+    /// Buffer up to `capacity` bytes before writing, 8192 by default
+    pub fn with_capacity(mut self, capacity: usize) -> RecordSink<W> {
+        self.capacity = capacity;
+        self
+    }
+    /// Return the underlying writer
+    ///
+    /// Records not yet flushed are lost
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+    /// Queue the bytes of a record for writing
+    pub fn start_send_raw(&mut self, raw: &[u8]) {
+        self.buf.extend_from_slice(raw);
+    }
+    // Write out the buffer
+    fn poll_write_buf(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while !self.buf.is_empty() {
+            match Pin::new(&mut self.writer).poll_write(cx, &self.buf) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write records",
+                    )))
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.buf.drain(..n);
+                }
+                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Sink<ms_record> for RecordSink<W> {
+    type Error = io::Error;
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.buf.len() < this.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        this.poll_write_buf(cx)
+    }
+    fn start_send(self: Pin<&mut Self>, rec: ms_record) -> io::Result<()> {
+        if rec.raw_bytes().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("record without raw bytes: {}", rec.id()),
+            ));
+        }
+        self.get_mut().start_send_raw(rec.raw_bytes());
+        Ok(())
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_write_buf(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.writer).poll_flush(cx),
+            other => other,
+        }
+    }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_write_buf(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.writer).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Contiguous time series with owned data samples
+//!
+//! A `Trace` holds the samples of one or more contiguous records for a
+//! single channel.  Unlike a `ms_record`, the samples are owned and may
+//! be modified, trimmed, or packed back into miniSEED records.
+//!
+//! ```
+//! use miniseed::{ms_input, Trace};
+//!
+//! let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+//! let traces = Trace::from_records(&recs);
+//! assert_eq!(traces.len(), 1);
+//! assert_eq!(traces[0].id(), "IU_ANMO_00_BHZ");
+//! ```
+
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+
+use libc::{c_char, c_int, c_void};
+
+use std::cmp::Ordering;
+
+use {blkt_1001_s, fsdh_s, hptime_t, msr_addblockette, msr_free, msr_init, msr_pack};
+use {
+    hptime_to_utc, utc_to_hptime, DE_ASCII, DE_FLOAT32, DE_FLOAT64, DE_INT16, DE_INT32, DE_STEIM1,
+    DE_STEIM2,
+};
+use {ms_record, Data};
+
+/// Owned data samples
+#[derive(Debug, Clone, PartialEq)]
+pub enum Samples {
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Ascii(Vec<u8>),
+}
+
+impl Samples {
+    /// Return the number of samples
+    pub fn len(&self) -> usize {
+        match *self {
+            Samples::Int(ref y) => y.len(),
+            Samples::Float(ref y) => y.len(),
+            Samples::Double(ref y) => y.len(),
+            Samples::Ascii(ref y) => y.len(),
+        }
+    }
+    /// Return true if there are no samples
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return the sample type code, see ms_record::data_type()
+    pub fn dtype(&self) -> char {
+        match *self {
+            Samples::Int(_) => 'i',
+            Samples::Float(_) => 'f',
+            Samples::Double(_) => 'd',
+            Samples::Ascii(_) => 'a',
+        }
+    }
+    /// Return a copy of the samples in `[i0, i1)`
+    pub fn slice(&self, i0: usize, i1: usize) -> Samples {
+        match *self {
+            Samples::Int(ref y) => Samples::Int(y[i0..i1].to_vec()),
+            Samples::Float(ref y) => Samples::Float(y[i0..i1].to_vec()),
+            Samples::Double(ref y) => Samples::Double(y[i0..i1].to_vec()),
+            Samples::Ascii(ref y) => Samples::Ascii(y[i0..i1].to_vec()),
+        }
+    }
+    /// Append samples of the same type, returns false if the types differ
+    pub fn extend(&mut self, other: &Samples) -> bool {
+        match (self, other) {
+            (Samples::Int(a), Samples::Int(b)) => a.extend_from_slice(b),
+            (Samples::Float(a), Samples::Float(b)) => a.extend_from_slice(b),
+            (Samples::Double(a), Samples::Double(b)) => a.extend_from_slice(b),
+            (Samples::Ascii(a), Samples::Ascii(b)) => a.extend_from_slice(b),
+            _ => return false,
+        }
+        true
+    }
+    /// Return the samples converted to f64
+    pub fn to_f64(&self) -> Vec<f64> {
+        match *self {
+            Samples::Int(ref y) => y.iter().map(|&i| i as f64).collect(),
+            Samples::Float(ref y) => y.iter().map(|&i| i as f64).collect(),
+            Samples::Double(ref y) => y.clone(),
+            Samples::Ascii(_) => vec![],
+        }
+    }
+    /// Return the default libmseed encoding for the sample type
+    pub fn default_encoding(&self) -> u32 {
+        match *self {
+            Samples::Int(_) => DE_STEIM2,
+            Samples::Float(_) => DE_FLOAT32,
+            Samples::Double(_) => DE_FLOAT64,
+            Samples::Ascii(_) => DE_ASCII,
+        }
+    }
+    /// Return true if the sample type can be packed with `encoding`
+    pub fn can_encode(&self, encoding: u32) -> bool {
+        match *self {
+            Samples::Int(_) => {
+                encoding == DE_STEIM1
+                    || encoding == DE_STEIM2
+                    || encoding == DE_INT32
+                    || encoding == DE_INT16
+            }
+            Samples::Float(_) => encoding == DE_FLOAT32,
+            Samples::Double(_) => encoding == DE_FLOAT64,
+            Samples::Ascii(_) => encoding == DE_ASCII,
+        }
+    }
+    fn size_of(&self) -> usize {
+        match *self {
+            Samples::Int(_) => 4,
+            Samples::Float(_) => 4,
+            Samples::Double(_) => 8,
+            Samples::Ascii(_) => 1,
+        }
+    }
+    fn as_ptr(&self) -> *const c_void {
+        match *self {
+            Samples::Int(ref y) => y.as_ptr() as *const c_void,
+            Samples::Float(ref y) => y.as_ptr() as *const c_void,
+            Samples::Double(ref y) => y.as_ptr() as *const c_void,
+            Samples::Ascii(ref y) => y.as_ptr() as *const c_void,
+        }
+    }
+}
+
+impl<'a> From<Data<'a>> for Samples {
+    fn from(data: Data<'a>) -> Samples {
+        match data {
+            Data::Int(y) => Samples::Int(y.to_vec()),
+            Data::Float(y) => Samples::Float(y.to_vec()),
+            Data::Double(y) => Samples::Double(y.to_vec()),
+            Data::Ascii(y) => Samples::Ascii(y.to_vec()),
+        }
+    }
+}
+
+/// Contiguous time series for a single channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub network: String,
+    pub station: String,
+    pub location: String,
+    pub channel: String,
+    /// Data quality code
+    pub quality: char,
+    /// Time of the first sample
+    pub start: DateTime<Utc>,
+    /// Sample rate in Hz
+    pub samprate: f64,
+    pub data: Samples,
+}
+
+impl Trace {
+    /// Create a trace from the data samples of a record
+    ///
+    /// Returns None if the record has no decoded data samples.  The start
+    /// time includes the blockette 1001 microseconds.
+    ///
+    /// ```
+    /// # use miniseed::{ms_record, Trace};
+    /// let rec = ms_record::read("tests/sample.miniseed");
+    /// let tr = Trace::from_record(&rec).unwrap();
+    /// assert_eq!(tr.npts(), 206);
+    /// assert_eq!(tr.end(), rec.end());
+    /// ```
+    pub fn from_record(rec: &ms_record) -> Option<Trace> {
+        if rec.ptr().datasamples.is_null() {
+            return None;
+        }
+        let data = Samples::from(rec.data()?);
+        Some(Trace {
+            network: rec.network(),
+            station: rec.station(),
+            location: rec.location(),
+            channel: rec.channel(),
+            quality: rec.dataquality().chars().next().unwrap_or('D'),
+            start: hptime_to_utc(rec.ptr().starttime),
+            samprate: rec.ptr().samprate,
+            data,
+        })
+    }
+    /// Merge records into contiguous traces
+    ///
+    /// Records are sorted by id and start time, and a record is appended
+    /// to the previous trace if the id, quality, sample rate and sample
+    /// type match and it starts within half a sample of the next
+    /// expected sample time
+    pub fn from_records(recs: &[ms_record]) -> Vec<Trace> {
+        let mut traces: Vec<Trace> = recs.iter().filter_map(Trace::from_record).collect();
+        traces.sort_by(|a, b| a.cmp_id_time(b));
+        Trace::merge(traces)
+    }
+    /// Merge sorted traces which are contiguous
+    pub fn merge(traces: Vec<Trace>) -> Vec<Trace> {
+        let mut out: Vec<Trace> = vec![];
+        for tr in traces {
+            if let Some(last) = out.last_mut() {
+                if last.is_contiguous(&tr) && last.data.extend(&tr.data) {
+                    continue;
+                }
+            }
+            out.push(tr);
+        }
+        out
+    }
+    /// Return true if `other` continues this trace without a gap or overlap
+    pub fn is_contiguous(&self, other: &Trace) -> bool {
+        if self.id() != other.id()
+            || self.quality != other.quality
+            || self.data.dtype() != other.data.dtype()
+            || (self.samprate - other.samprate).abs() > 1e-4 * self.samprate
+        {
+            return false;
+        }
+        let gap = other.start.signed_duration_since(self.end1());
+        let gap = gap.num_microseconds().unwrap_or(i64::MAX).abs() as f64 / 1e6;
+        gap <= 0.5 * self.delta()
+    }
+    /// Return the unique identifier `NET_STA_LOC_CHA`
+    pub fn id(&self) -> String {
+        format!(
+            "{}_{}_{}_{}",
+            self.network, self.station, self.location, self.channel
+        )
+    }
+    /// Return the number of samples
+    pub fn npts(&self) -> usize {
+        self.data.len()
+    }
+    /// Return the sample interval
+    pub fn delta(&self) -> f64 {
+        1.0 / self.samprate
+    }
+    /// Return the time of sample `i`
+    pub fn time_of(&self, i: usize) -> DateTime<Utc> {
+        self.start + Duration::microseconds((i as f64 * self.delta() * 1e6).round() as i64)
+    }
+    /// Return the time of the last sample
+    pub fn end(&self) -> DateTime<Utc> {
+        self.time_of(self.npts().max(1) - 1)
+    }
+    /// Return the time of the next sample beyond the trace
+    pub fn end1(&self) -> DateTime<Utc> {
+        self.time_of(self.npts())
+    }
+    /// Compare by id and start time
+    pub fn cmp_id_time(&self, other: &Trace) -> Ordering {
+        self.id()
+            .cmp(&other.id())
+            .then(self.start.cmp(&other.start))
+    }
+    /// Pack the trace into records of length `reclen` bytes
+    ///
+    /// `encoding` is a libmseed data encoding, e.g. `DE_STEIM2`, and
+    /// must be compatible with the sample type.  Each element of the
+    /// returned vector is a single encoded record.
+    pub fn pack_bytes(&self, reclen: usize, encoding: u32) -> Vec<Vec<u8>> {
+        self.pack_template(reclen, encoding, None)
+    }
+    /// Pack the trace into records of length `reclen` bytes using the
+    /// header of `rec` as a template
+    ///
+    /// The activity, I/O and data quality flags, the time correction and
+    /// blockette 1001 timing quality of `rec` are preserved.  Identifiers,
+    /// quality code, start time and sample rate are taken from the trace.
+    ///
+    /// ```
+    /// # use miniseed::{ms_record, Trace, DE_STEIM1};
+    /// let rec = ms_record::read("tests/sample.miniseed");
+    /// let tr = Trace::from_record(&rec).unwrap();
+    /// let bufs = tr.pack_like(&rec, 1024, DE_STEIM1);
+    /// assert_eq!(bufs.len(), 1);
+    /// assert_eq!(bufs[0].len(), 1024);
+    /// ```
+    pub fn pack_like(&self, rec: &ms_record, reclen: usize, encoding: u32) -> Vec<Vec<u8>> {
+        self.pack_template(reclen, encoding, Some(rec))
+    }
+    fn pack_template(
+        &self,
+        reclen: usize,
+        encoding: u32,
+        template: Option<&ms_record>,
+    ) -> Vec<Vec<u8>> {
+        if !self.data.can_encode(encoding) {
+            panic!(
+                "encoding {} is incompatible with sample type '{}'",
+                encoding,
+                self.data.dtype()
+            );
+        }
+        let mut out: Vec<Vec<u8>> = vec![];
+        if self.data.is_empty() {
+            return out;
+        }
+        let n = self.npts();
+        let nbytes = n * self.data.size_of();
+        let retcode = unsafe {
+            let msr = msr_init(std::ptr::null_mut());
+            copy_str(&mut (*msr).network, &self.network);
+            copy_str(&mut (*msr).station, &self.station);
+            copy_str(&mut (*msr).location, &self.location);
+            copy_str(&mut (*msr).channel, &self.channel);
+            (*msr).dataquality = self.quality as u8 as c_char;
+            (*msr).starttime = utc_to_hptime(&self.start) as hptime_t;
+            (*msr).samprate = self.samprate;
+            (*msr).reclen = reclen as i32;
+            (*msr).encoding = encoding as i8;
+            (*msr).byteorder = 1;
+            (*msr).sequence_number = 1;
+            // Sample memory is released by msr_free()
+            let samples = libc::malloc(nbytes);
+            std::ptr::copy_nonoverlapping(
+                self.data.as_ptr() as *const u8,
+                samples as *mut u8,
+                nbytes,
+            );
+            (*msr).datasamples = samples;
+            (*msr).numsamples = n as i64;
+            (*msr).samplecnt = n as i64;
+            (*msr).sampletype = self.data.dtype() as u8 as c_char;
+            if let Some(rec) = template {
+                // Header and blockette memory is released by msr_free()
+                let m = rec.ptr();
+                if !m.fsdh.is_null() {
+                    let fsdh = libc::malloc(std::mem::size_of::<fsdh_s>()) as *mut fsdh_s;
+                    *fsdh = *m.fsdh;
+                    (*msr).fsdh = fsdh;
+                }
+                if !m.Blkt1001.is_null() {
+                    let mut b1001 = *m.Blkt1001;
+                    msr_addblockette(
+                        msr,
+                        (&mut b1001 as *mut blkt_1001_s) as *mut c_char,
+                        std::mem::size_of::<blkt_1001_s>() as c_int,
+                        1001,
+                        0,
+                    );
+                }
+            }
+            let ptr = (&mut out as *mut Vec<Vec<u8>>) as *mut c_void;
+            let retcode = msr_pack(msr, Some(collect_handler), ptr, std::ptr::null_mut(), 1, 0);
+            let mut msr = msr;
+            msr_free(&mut msr);
+            retcode
+        };
+        if retcode < 0 {
+            panic!("msr_pack retcode: {}", retcode);
+        }
+        out
+    }
+    /// Pack the trace into records of length `reclen` bytes
+    ///
+    /// see pack_bytes()
+    pub fn pack(&self, reclen: usize, encoding: u32) -> Vec<ms_record> {
+        self.pack_bytes(reclen, encoding)
+            .iter()
+            .map(|buf| ms_record::parse(buf))
+            .collect()
+    }
+}
+
+unsafe extern "C" fn collect_handler(buffer: *mut c_char, buflen: c_int, ptr: *mut c_void) {
+    let out = ptr as *mut Vec<Vec<u8>>;
+    if let Some(o) = out.as_mut() {
+        let chars: &[c_char] = std::slice::from_raw_parts(buffer, buflen as usize);
+        let bytes = &*(chars as *const [c_char] as *const [u8]);
+        o.push(bytes.to_vec());
+    }
+}
+
+fn copy_str(dst: &mut [c_char], s: &str) {
+    let n = dst.len() - 1;
+    for (i, v) in dst.iter_mut().enumerate() {
+        *v = 0;
+        if i < n {
+            if let Some(&b) = s.as_bytes().get(i) {
+                *v = b as c_char;
+            }
+        }
+    }
+}
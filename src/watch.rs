@@ -0,0 +1,302 @@
+//! Watching a directory for new files
+//!
+//! `Watcher` polls a directory tree for miniSEED files, e.g. an inbox
+//! into which data files are dropped, and returns their records with
+//! the path of the file they came from.  A file is read once it is
+//! complete, i.e. its size and modification time have not changed for
+//! the settle time.  Files are first checked with a RecordReader and
+//! then read with ms_input.  Processed files can be moved to a done
+//! directory and files which are not miniSEED to a failed directory,
+//! keeping their paths relative to the watched directory.  Files which
+//! are not moved are only read once, unless they are removed and a file
+//! of the same name appears later.
+//!
+//! ```no_run
+//! use miniseed::watch::Watcher;
+//!
+//! let watcher = Watcher::new("inbox")
+//!     .with_pattern("*.mseed")
+//!     .with_done_dir("done")
+//!     .with_failed_dir("failed");
+//! for (path, rec) in watcher {
+//!     println!("{}: {}", path.display(), rec);
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use selection::wildcard_match;
+use {ms_input, ms_record, RecordReader, Selection};
+
+/// Size and modification time of a file, and when they were first seen
+struct Seen {
+    len: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Records of files appearing in a directory tree
+pub struct Watcher {
+    root: PathBuf,
+    pattern: Option<String>,
+    selection: Option<Selection>,
+    done: Option<PathBuf>,
+    failed: Option<PathBuf>,
+    poll: Duration,
+    settle: Duration,
+    idle_timeout: Option<Duration>,
+    seen: HashMap<PathBuf, Seen>,
+    processed: HashSet<PathBuf>,
+    queue: Vec<PathBuf>,
+    current: Option<(PathBuf, ms_input)>,
+}
+
+/// Move `path` under `root` to the same relative path under `dir`
+fn move_to(root: &Path, path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let dest = dir.join(rel);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(path, &dest).is_err() {
+        // e.g. across file systems
+        fs::copy(path, &dest)?;
+        fs::remove_file(path)?;
+    }
+    Ok(dest)
+}
+
+/// Return true if the file contains only miniSEED records
+fn is_mseed(path: &Path) -> bool {
+    let mut reader = match File::open(path) {
+        Ok(f) => RecordReader::new(f),
+        Err(_) => return false,
+    };
+    loop {
+        match reader.read_raw() {
+            Ok(Some(_)) => {}
+            Ok(None) => return reader.offset() > 0,
+            Err(_) => return false,
+        }
+    }
+}
+
+impl Watcher {
+    /// Watch the directory tree at `root`
+    ///
+    /// Files already in the tree are read as well as new ones
+    pub fn new<S: AsRef<Path>>(root: S) -> Watcher {
+        Watcher {
+            root: root.as_ref().to_path_buf(),
+            pattern: None,
+            selection: None,
+            done: None,
+            failed: None,
+            poll: Duration::from_secs(1),
+            settle: Duration::from_secs(2),
+            idle_timeout: None,
+            seen: HashMap::new(),
+            processed: HashSet::new(),
+            queue: vec![],
+            current: None,
+        }
+    }
+    /// Only read files whose name matches `pattern`, with `?` and `*`
+    /// wildcards
+    pub fn with_pattern(mut self, pattern: &str) -> Watcher {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+    /// Only return records matching the selection
+    pub fn with_selection(mut self, selection: Selection) -> Watcher {
+        self.selection = Some(selection);
+        self
+    }
+    /// Move files to `dir` after their records are returned
+    pub fn with_done_dir<S: AsRef<Path>>(mut self, dir: S) -> Watcher {
+        self.done = Some(dir.as_ref().to_path_buf());
+        self
+    }
+    /// Move files which are not miniSEED to `dir`
+    pub fn with_failed_dir<S: AsRef<Path>>(mut self, dir: S) -> Watcher {
+        self.failed = Some(dir.as_ref().to_path_buf());
+        self
+    }
+    /// Scan the directory tree every `interval`, 1 s by default
+    pub fn with_poll_interval(mut self, interval: Duration) -> Watcher {
+        self.poll = interval;
+        self
+    }
+    /// Read a file once it has not changed for `settle`, 2 s by default
+    pub fn with_settle_time(mut self, settle: Duration) -> Watcher {
+        self.settle = settle;
+        self
+    }
+    /// Stop after `timeout` without new files instead of waiting forever
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Watcher {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+    /// Return the path of the file being read
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|c| c.0.as_path())
+    }
+    // Return true if the path is in the done or failed directory
+    fn is_output(&self, path: &Path) -> bool {
+        let under = |d: &Option<PathBuf>| matches!(*d, Some(ref d) if path.starts_with(d));
+        under(&self.done) || under(&self.failed)
+    }
+    // Find the files in the tree
+    fn find_files(&self, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if self.is_output(&path) {
+                continue;
+            }
+            if path.is_dir() {
+                self.find_files(&path, files)?;
+                continue;
+            }
+            let name = path
+                .file_name()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let wanted = match self.pattern {
+                Some(ref p) => wildcard_match(p, &name),
+                None => true,
+            };
+            if wanted && !self.processed.contains(&path) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    // Queue the files which have settled, returning true if any were
+    fn poll_files(&mut self) -> io::Result<bool> {
+        // Forget files which are gone, so a new file of the same name is
+        // read
+        self.processed.retain(|p| p.exists());
+        let mut files = vec![];
+        match self.find_files(&self.root, &mut files) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        files.sort();
+        let now = Instant::now();
+        let mut seen = HashMap::new();
+        for path in files {
+            let meta = match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+            let (len, modified) = (meta.len(), meta.modified().ok());
+            let since = match self.seen.remove(&path) {
+                Some(ref s) if s.len == len && s.modified == modified => s.since,
+                _ => now,
+            };
+            if len > 0 && now.duration_since(since) >= self.settle {
+                self.queue.push(path);
+            } else {
+                seen.insert(
+                    path,
+                    Seen {
+                        len,
+                        modified,
+                        since,
+                    },
+                );
+            }
+        }
+        self.seen = seen;
+        Ok(!self.queue.is_empty())
+    }
+    // Finish the current file, moving it to the done directory
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some((path, input)) = self.current.take() {
+            drop(input);
+            match self.done {
+                Some(ref done) => {
+                    if let Err(e) = move_to(&self.root, &path, done) {
+                        // Do not read the file again
+                        self.processed.insert(path);
+                        return Err(e);
+                    }
+                }
+                None => {
+                    self.processed.insert(path);
+                }
+            }
+        }
+        Ok(())
+    }
+    // Start reading the next queued file, returning false if there is none
+    fn start(&mut self) -> io::Result<bool> {
+        while !self.queue.is_empty() {
+            let path = self.queue.remove(0);
+            if !is_mseed(&path) {
+                match self.failed {
+                    Some(ref failed) => {
+                        if let Err(e) = move_to(&self.root, &path, failed) {
+                            self.processed.insert(path);
+                            return Err(e);
+                        }
+                    }
+                    None => {
+                        self.processed.insert(path);
+                    }
+                }
+                continue;
+            }
+            let mut input = ms_input::open(&path);
+            if let Some(ref sel) = self.selection {
+                input = input.with_selection(sel.clone());
+            }
+            self.current = Some((path, input));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+    /// Return the next record and the path of its file, waiting for new
+    /// files
+    ///
+    /// Returns None if an idle timeout is set and no file is completed
+    /// before it expires
+    pub fn next_record(&mut self) -> io::Result<Option<(PathBuf, ms_record)>> {
+        let mut last = Instant::now();
+        loop {
+            if let Some((ref path, ref mut input)) = self.current {
+                if let Some(rec) = input.next() {
+                    return Ok(Some((path.clone(), rec)));
+                }
+            }
+            self.finish()?;
+            if self.start()? {
+                continue;
+            }
+            if self.poll_files()? {
+                last = Instant::now();
+                continue;
+            }
+            if let Some(timeout) = self.idle_timeout {
+                if last.elapsed() >= timeout {
+                    return Ok(None);
+                }
+            }
+            thread::sleep(self.poll);
+        }
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = (PathBuf, ms_record);
+    fn next(&mut self) -> Option<(PathBuf, ms_record)> {
+        self.next_record().ok().and_then(|r| r)
+    }
+}
@@ -0,0 +1,101 @@
+extern crate miniseed;
+
+use miniseed::archive::{Archive, ArchiveWriter, PathTemplate, Period};
+use miniseed::{ms_input, str_to_utc, Selection};
+
+use std::fs;
+
+#[test]
+fn template_period() {
+    assert_eq!(PathTemplate::new(PathTemplate::SDS).period(), Period::Day);
+    assert_eq!(PathTemplate::new("%n/%Y/%m/%n.%s").period(), Period::Month);
+    assert_eq!(PathTemplate::new("%n.%s.%Y%j%H").period(), Period::Hour);
+    assert_eq!(PathTemplate::new("%n.%s.mseed").period(), Period::Unbounded);
+
+    let t = str_to_utc("2010-12-31T23:59:59").unwrap();
+    assert_eq!(Period::Month.next(&t), str_to_utc("2011-01-01"));
+    assert_eq!(
+        Some(Period::Hour.start(&t)),
+        str_to_utc("2010-12-31T23:00:00")
+    );
+}
+
+#[test]
+fn template_render() {
+    let t = PathTemplate::new("%n/%s/%%%q.%l.%c.%Y.%j");
+    let when = str_to_utc("2010-02-27").unwrap();
+    assert_eq!(
+        t.render("IU", "ANMO", "", "BHZ", "M", Some(&when)),
+        "IU/ANMO/%M..BHZ.2010.058"
+    );
+    assert_eq!(
+        t.render("IU", "*", "*", "BH?", "?", None),
+        "IU/*/%?.*.BH?.*.*"
+    );
+}
+
+#[test]
+fn archive_hourly_roundtrip() {
+    let root = std::env::temp_dir().join("miniseed-archive-hourly");
+    let _ = fs::remove_dir_all(&root);
+    let archive = Archive::new(&root, PathTemplate::new("%n/%s/%n.%s.%l.%c.%Y.%j.%H"));
+
+    let mut out = ArchiveWriter::new(archive.clone());
+    for rec in ms_input::open("tests/multiple.seed") {
+        out.write(&rec).unwrap();
+    }
+    out.close().unwrap();
+
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_*_*", None, None);
+    let files = archive.all_files(&sel);
+    assert!(files.len() > 1);
+    let n: usize = files.iter().map(|f| ms_input::open(f).count()).sum();
+    assert_eq!(n, 1243);
+
+    let t0 = str_to_utc("2010-02-27T07:00:00").unwrap();
+    let t1 = str_to_utc("2010-02-27T07:30:00").unwrap();
+    assert_eq!(archive.files(&sel, &t0, &t1).len(), 2);
+    let tr = archive.traces(&sel, &t0, &t1);
+    assert_eq!(tr.len(), 1);
+    assert_eq!(tr[0].npts(), 36000);
+}
+
+#[test]
+fn archive_unbounded_split() {
+    // Without time tokens records are never split, so bytes are unchanged
+    let root = std::env::temp_dir().join("miniseed-archive-unbounded");
+    let _ = fs::remove_dir_all(&root);
+    let archive = Archive::new(&root, PathTemplate::new("%n.%s.%l.%c.mseed"));
+    let mut out = ArchiveWriter::new(archive).with_split(true);
+    for rec in ms_input::open("tests/multiple.seed") {
+        out.write(&rec).unwrap();
+    }
+    out.close().unwrap();
+    assert_eq!(
+        fs::read(root.join("IU.ANMO.00.BHZ.mseed")).unwrap(),
+        fs::read("tests/multiple.seed").unwrap()
+    );
+}
+
+#[test]
+fn archive_close_idle_reopens() {
+    let root = std::env::temp_dir().join("miniseed-archive-idle");
+    let _ = fs::remove_dir_all(&root);
+    let archive = Archive::new(&root, PathTemplate::new("%n.%s.%l.%c.mseed"));
+    let mut out = ArchiveWriter::new(archive).with_truncate(true);
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").take(2).collect();
+    out.write(&recs[0]).unwrap();
+    assert_eq!(out.paths().len(), 1);
+    assert_eq!(
+        out.close_idle(std::time::Duration::from_secs(0)).unwrap(),
+        1
+    );
+    assert_eq!(out.paths().len(), 1);
+
+    // Written again the file is appended to, not truncated
+    out.write(&recs[1]).unwrap();
+    out.close().unwrap();
+    assert_eq!(out.paths().len(), 1);
+    assert_eq!(ms_input::open(&out.paths()[0]).count(), 2);
+}
@@ -0,0 +1,109 @@
+extern crate chrono;
+extern crate miniseed;
+
+use chrono::Duration;
+use miniseed::clock::{ClockCorrection, CorrectionTable, Method, TIME_CORRECTION_APPLIED};
+use miniseed::header::RawHeader;
+use miniseed::{ms_input, ms_record, str_to_utc, Trace};
+
+use std::fs;
+
+#[test]
+fn clock_table() {
+    let table = CorrectionTable::parse(
+        "# clock drift
+         IU_ANMO_*_BH?  2010-02-27T00:00:00  0.0
+         IU_ANMO_*_BH?  2010-02-28T00:00:00  -0.864
+         *              2010-01-01           0.25",
+    )
+    .unwrap();
+    assert_eq!(table.corrections().len(), 2);
+    let c = table.find("IU_ANMO_00_BHZ").unwrap();
+    assert_eq!(c.points().len(), 2);
+    let t = str_to_utc("2010-02-27T12:00:00").unwrap();
+    assert!((c.offset_at(&t) + 0.432).abs() < 1e-9);
+    assert_eq!(table.find("PN_PPNAF_00_HHZ").unwrap().offset_at(&t), 0.25);
+
+    assert!(CorrectionTable::parse("* 2010-01-01").is_err());
+    assert!(CorrectionTable::parse("* 2010-13-01 1.0").is_err());
+    assert!(CorrectionTable::parse("* 2010-01-01 x").is_err());
+    assert!(CorrectionTable::new().find("IU_ANMO_00_BHZ").is_none());
+}
+
+#[test]
+fn clock_header() {
+    let mut table = CorrectionTable::new();
+    table.add(ClockCorrection::constant("IU_*", 0.5));
+
+    let dir = std::env::temp_dir().join("miniseed-clock");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("header.mseed");
+    let audit = table
+        .apply_file("tests/multiple.seed", &out, Method::Header)
+        .unwrap();
+    assert_eq!(audit.len(), 1243);
+    assert_eq!(audit[0].time_correct, Some((0, 5000)));
+    assert_eq!(
+        audit[0].corrected - audit[0].start,
+        Duration::milliseconds(500)
+    );
+    assert!(audit[0]
+        .to_string()
+        .starts_with("IU_ANMO_00_BHZ Header 2010-02-27T06:30:00.019538"));
+
+    // Start times are unchanged, readers apply the correction
+    let a: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    let b: Vec<_> = ms_input::open(&out).collect();
+    assert_eq!(a[10].start(), b[10].start());
+    assert_eq!(b[10].header().time_correct, 5000);
+
+    // Corrections accumulate
+    let rec = &b[0];
+    let (rec, audit) = table.apply_record(rec, Method::Header).unwrap();
+    assert_eq!(audit.unwrap().time_correct, Some((5000, 10000)));
+    assert_eq!(rec.header().time_correct, 10000);
+
+    // Shifting applies the header correction and the new correction
+    let (rec, audit) = table.apply_record(&rec, Method::Shift).unwrap();
+    let audit = audit.unwrap();
+    assert_eq!(audit.time_correct, Some((10000, 15000)));
+    assert_eq!(rec.start() - a[0].start(), Duration::milliseconds(1500));
+    let hdr = RawHeader::parse(rec.raw_bytes()).unwrap();
+    assert!(hdr.act_flags & TIME_CORRECTION_APPLIED != 0);
+    assert_eq!(hdr.corrected_start(), audit.corrected);
+
+    // Shifting an already shifted record adds only the new correction
+    let (rec, audit) = table.apply_record(&rec, Method::Shift).unwrap();
+    assert_eq!(audit.unwrap().time_correct, Some((15000, 20000)));
+    assert_eq!(rec.start() - a[0].start(), Duration::milliseconds(2000));
+    let hdr = RawHeader::parse(rec.raw_bytes()).unwrap();
+    assert_eq!(
+        hdr.corrected_start() - a[0].start(),
+        Duration::milliseconds(2000)
+    );
+
+    // The header method cannot be used once a correction is applied
+    assert!(table.apply_record(&rec, Method::Header).is_err());
+}
+
+#[test]
+fn clock_shift() {
+    let table = CorrectionTable::parse("PN_PPNAF_00_HHZ 2016-01-01 -1.25").unwrap();
+    let rec = ms_record::read("tests/sample.miniseed");
+    let (shifted, audit) = table.apply_record(&rec, Method::Shift).unwrap();
+    assert_eq!(audit.unwrap().offset, -1.25);
+    assert_eq!(shifted.start(), rec.start() - Duration::milliseconds(1250));
+    assert_eq!(shifted.npts(), rec.npts());
+    assert_eq!(shifted.data_i32(), rec.data_i32());
+
+    let other = CorrectionTable::parse("XX_* 2016-01-01 1.0").unwrap();
+    let (same, audit) = other.apply_record(&rec, Method::Shift).unwrap();
+    assert!(audit.is_none());
+    assert_eq!(same.raw_bytes(), rec.raw_bytes());
+
+    let mut tr = Trace::from_record(&rec).unwrap();
+    let audit = table.apply_trace(&mut tr).unwrap();
+    assert_eq!(audit.time_correct, None);
+    assert_eq!(tr.start, shifted.start());
+}
@@ -0,0 +1,42 @@
+extern crate miniseed;
+
+use miniseed::cut::{cut, cut_records, Boundary};
+use miniseed::{ms_input, str_to_utc, DE_STEIM2};
+
+#[test]
+fn cut_boundary() {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    let t0 = str_to_utc("2010-02-27T06:30:10.04").unwrap();
+    let t1 = str_to_utc("2010-02-27T06:30:20.04").unwrap();
+
+    let tr = cut(&recs, &t0, &t1, Boundary::Inclusive);
+    assert_eq!(tr.len(), 1);
+    assert_eq!(tr[0].npts(), 200);
+    // The blockette 1001 microseconds of the records are kept
+    assert_eq!(tr[0].start.to_string(), "2010-02-27 06:30:10.069538 UTC");
+
+    let tr = cut(&recs, &t0, &t1, Boundary::Nearest);
+    assert_eq!(tr.len(), 1);
+    assert_eq!(tr[0].npts(), 200);
+    assert_eq!(tr[0].start.to_string(), "2010-02-27 06:30:10.019538 UTC");
+}
+
+#[test]
+fn cut_outside() {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    let t0 = str_to_utc("2000-01-01").unwrap();
+    let t1 = str_to_utc("2000-01-02").unwrap();
+    assert!(cut(&recs, &t0, &t1, Boundary::Nearest).is_empty());
+}
+
+#[test]
+fn cut_repack() {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    let t0 = str_to_utc("2010-02-27T06:30:10").unwrap();
+    let t1 = str_to_utc("2010-02-27T06:31:10").unwrap();
+    let out = cut_records(&recs, &t0, &t1, Boundary::Inclusive, 512, Some(DE_STEIM2));
+    assert!(!out.is_empty());
+    assert_eq!(out.iter().map(|r| r.npts()).sum::<usize>(), 1200);
+    assert!(out[0].start() >= t0);
+    assert!(out[out.len() - 1].end() < t1);
+}
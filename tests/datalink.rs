@@ -0,0 +1,230 @@
+extern crate chrono;
+extern crate miniseed;
+
+use miniseed::datalink::{read_frame, DataLinkClient, DataLinkServer, Frame, FrameReader, Regex};
+use miniseed::{ms_input, ms_record};
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+fn records(n: usize) -> Vec<ms_record> {
+    ms_input::open("tests/multiple.seed").take(n).collect()
+}
+
+/// Start a server holding the first `n` records of multiple.seed
+fn server(n: usize) -> (DataLinkServer, String) {
+    let server = DataLinkServer::new();
+    for rec in records(n) {
+        server.insert_record(&rec);
+    }
+    let addr = server.spawn("127.0.0.1:0").unwrap().to_string();
+    (server, addr)
+}
+
+/// Run `f` in a thread, failing if it does not finish in 10 seconds
+fn within<T: Send + 'static, F: FnOnce() -> T + Send + 'static>(f: F) -> T {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || tx.send(f()).unwrap());
+    rx.recv_timeout(Duration::from_secs(10)).expect("timed out")
+}
+
+#[test]
+fn write_read() {
+    let (server, addr) = server(0);
+    let recs = records(10);
+    let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+    assert!(c.can_write());
+    assert_eq!(c.packet_size(), Some(512));
+    for (i, rec) in recs.iter().enumerate() {
+        assert_eq!(c.write(rec, true).unwrap(), Some(i as u64 + 1));
+    }
+    assert_eq!(server.len(), 10);
+    let pkt = c.read(5).unwrap();
+    assert_eq!(pkt.id, 5);
+    assert_eq!(pkt.stream_id, "IU_ANMO_00_BHZ/MSEED");
+    assert_eq!(pkt.start, recs[4].start());
+    assert_eq!(pkt.data, recs[4].raw_bytes());
+    assert!(c.read(99).is_err());
+}
+
+#[test]
+fn write_invalid() {
+    let (server, addr) = server(0);
+    let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+    let t = miniseed::str_to_utc("2020-01-01").unwrap();
+    assert!(c
+        .write_packet("XX_TEST__LOG/MSEED", &t, &t, b"not a record", true)
+        .is_err());
+    assert_eq!(
+        c.write_packet("XX_TEST__LOG/TEXT", &t, &t, b"hello", true)
+            .unwrap(),
+        Some(1)
+    );
+    let pkt = c.read(1).unwrap();
+    assert!(!pkt.is_mseed());
+    assert!(pkt.record().is_none());
+    assert_eq!(pkt.data, b"hello");
+
+    // A packet larger than the packet size is refused and the connection
+    // closed once its header is read
+    assert!(c
+        .write_packet("XX_TEST__LOG/MSEED", &t, &t, &[0u8; 1024], true)
+        .is_err());
+    assert!(c.read(1).is_err());
+    assert_eq!(server.len(), 1);
+}
+
+#[test]
+fn frame_limits() {
+    // The payload size is checked before the payload is read
+    assert!(Frame::parse(b"DL\x10OK 0 99999999999").is_err());
+    let mut r = FrameReader::new().with_max_payload(512);
+    r.feed(b"DL\x11WRITE a 0 0 N 512");
+    assert!(r.next_frame().unwrap().is_none());
+    let mut r = FrameReader::new().with_max_payload(512);
+    r.feed(b"DL\x11WRITE a 0 0 N 513");
+    assert!(r.next_frame().is_err());
+    let mut buf: &[u8] = b"DL\x10OK 0 99999999999";
+    assert!(read_frame(&mut buf).is_err());
+}
+
+#[test]
+fn stream_earliest() {
+    let (_server, addr) = server(20);
+    let ids = within(move || {
+        let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+        assert_eq!(c.match_streams("^IU_ANMO_00_BHZ/MSEED$").unwrap(), 1);
+        assert_eq!(c.position_earliest().unwrap(), 1);
+        c.stream().unwrap();
+        let ids: Vec<u64> = (0..20)
+            .map(|_| c.next_packet().unwrap().unwrap().id)
+            .collect();
+        c.end_stream().unwrap();
+        assert_eq!(c.next_packet().unwrap(), None);
+        ids
+    });
+    assert_eq!(ids, (1..=20).collect::<Vec<u64>>());
+}
+
+#[test]
+fn stream_new_packets() {
+    let (_server, addr) = server(5);
+    let mut c = DataLinkClient::connect(&addr, "reader").unwrap();
+    c.position_latest().unwrap();
+    c.stream().unwrap();
+    let recs = records(8);
+    let mut w = DataLinkClient::connect(&addr, "writer").unwrap();
+    for rec in &recs[5..] {
+        w.write(rec, true).unwrap();
+    }
+    let got = within(move || {
+        (0..3)
+            .map(|_| c.next_record().unwrap().unwrap())
+            .collect::<Vec<_>>()
+    });
+    for (a, b) in got.iter().zip(&recs[5..]) {
+        assert_eq!(a.raw_bytes(), b.raw_bytes());
+    }
+}
+
+#[test]
+fn position() {
+    let (_server, addr) = server(10);
+    let recs = records(10);
+    let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+    assert_eq!(c.position_set(3, None).unwrap(), 3);
+    let time = c.read(3).unwrap().time;
+    assert_eq!(c.position_set(3, Some(&time)).unwrap(), 3);
+    let wrong = time + chrono::Duration::seconds(1);
+    assert!(c.position_set(3, Some(&wrong)).is_err());
+    assert!(c.position_set(99, None).is_err());
+    assert_eq!(c.position_after(&recs[6].start()).unwrap(), 7);
+    let late = recs[9].end() + chrono::Duration::seconds(60);
+    assert!(c.position_after(&late).is_err());
+    assert_eq!(c.position_latest().unwrap(), 10);
+
+    // Streaming starts after the packet set
+    c.position_set(7, None).unwrap();
+    c.stream().unwrap();
+    let ids = within(move || {
+        (0..3)
+            .map(|_| c.next_packet().unwrap().unwrap().id)
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(ids, vec![8, 9, 10]);
+}
+
+#[test]
+fn reject() {
+    let server = DataLinkServer::new();
+    let t = miniseed::str_to_utc("2020-01-01").unwrap();
+    for name in &["XX_A__LOG/TEXT", "XX_B__LOG/TEXT", "XX_C__LOG/TEXT"] {
+        server.insert(name, &t, &t, b"log");
+    }
+    let addr = server.spawn("127.0.0.1:0").unwrap().to_string();
+    let names = within(move || {
+        let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+        assert_eq!(c.match_streams("LOG").unwrap(), 3);
+        assert_eq!(c.reject_streams("_B_").unwrap(), 1);
+        assert!(c.match_streams("(LOG").is_err());
+        c.position_earliest().unwrap();
+        c.stream().unwrap();
+        let names: Vec<String> = (0..2)
+            .map(|_| c.next_packet().unwrap().unwrap().stream_id)
+            .collect();
+        c.end_stream().unwrap();
+        assert_eq!(c.next_packet().unwrap(), None);
+        names
+    });
+    assert_eq!(names, vec!["XX_A__LOG/TEXT", "XX_C__LOG/TEXT"]);
+}
+
+#[test]
+fn info() {
+    let (_server, addr) = server(10);
+    let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+    let xml = c.info("STREAMS").unwrap();
+    assert!(xml.contains("TotalStreams=\"1\""));
+    assert!(xml.contains("Name=\"IU_ANMO_00_BHZ/MSEED\""));
+    assert!(xml.contains("LatestPacketID=\"10\""));
+    let xml = c.info("STATUS").unwrap();
+    assert!(xml.contains("TotalPackets=\"10\""));
+    assert!(c.info("CONNECTIONS").is_err());
+}
+
+#[test]
+fn capacity() {
+    let server = DataLinkServer::new().with_capacity(5);
+    for rec in records(8) {
+        server.insert_record(&rec);
+    }
+    assert_eq!(server.len(), 5);
+    let addr = server.spawn("127.0.0.1:0").unwrap().to_string();
+    let mut c = DataLinkClient::connect(&addr, "test").unwrap();
+    assert!(c.read(3).is_err());
+    assert_eq!(c.read(4).unwrap().id, 4);
+    assert_eq!(c.position_earliest().unwrap(), 4);
+}
+
+#[test]
+fn regex() {
+    let re = Regex::new("^IU_(ANMO|COLA)_[0-9]{2}_BH[^N]/MSEED$").unwrap();
+    assert!(re.is_match("IU_ANMO_00_BHZ/MSEED"));
+    assert!(re.is_match("IU_COLA_10_BHE/MSEED"));
+    assert!(!re.is_match("IU_COLA_10_BHN/MSEED"));
+    assert!(!re.is_match("IU_ANMO__BHZ/MSEED"));
+    assert!(!re.is_match("XIU_ANMO_00_BHZ/MSEED"));
+    assert!(Regex::new("ANMO").unwrap().is_match("IU_ANMO_00_BHZ/MSEED"));
+    assert!(Regex::new("^a*b+c?$").unwrap().is_match("bbb"));
+    assert!(!Regex::new("^a*b+c?$").unwrap().is_match("acc"));
+    assert!(Regex::new("a\\.b").unwrap().is_match("a.b"));
+    assert!(!Regex::new("a\\.b").unwrap().is_match("axb"));
+    assert!(Regex::new("[abc").is_err());
+    assert!(Regex::new("*a").is_err());
+    assert!(Regex::new("a)").is_err());
+    // Repeat counts are limited
+    assert!(Regex::new("^a{2,255}$").unwrap().is_match("aa"));
+    assert!(Regex::new("(.*){99999999}").is_err());
+    assert!(Regex::new("a{3,2}").is_err());
+}
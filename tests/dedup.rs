@@ -0,0 +1,135 @@
+extern crate chrono;
+extern crate miniseed;
+
+use chrono::Duration;
+use miniseed::dedup::{quality_rank, Dedup, Priority};
+use miniseed::{ms_input, ms_record, Samples, Trace, DE_STEIM2};
+
+use std::fs;
+
+// Records 5..15 of multiple.seed repacked with quality Q, starting `skip`
+// samples into record 5
+fn repacked(skip: usize) -> Vec<ms_record> {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed")
+        .skip(5)
+        .take(10)
+        .collect();
+    let mut tr = Trace::from_records(&recs).remove(0);
+    tr.quality = 'Q';
+    tr.start = tr.time_of(skip);
+    tr.data = tr.data.slice(skip, tr.npts());
+    tr.pack(512, DE_STEIM2)
+}
+
+#[test]
+fn dedup_quality() {
+    assert!(quality_rank('Q') > quality_rank('D'));
+    assert!(quality_rank('R') > quality_rank('M'));
+
+    let mut recs: Vec<_> = ms_input::open("tests/multiple.seed").take(10).collect();
+    recs.extend(ms_input::open("tests/multiple.seed").take(3));
+    let q = repacked(0);
+    let nq = q.len();
+    recs.extend(q);
+
+    let (out, stats) = Dedup::new().process(recs);
+    assert_eq!(stats.read, 13 + nq);
+    assert_eq!(stats.duplicates, 3);
+    assert_eq!(stats.pruned, 5);
+    assert_eq!(stats.written, 5 + nq);
+    assert_eq!(out.len(), 5 + nq);
+    assert!(out[..5].iter().all(|r| r.dataquality() == "M"));
+    assert!(out[5..].iter().all(|r| r.dataquality() == "Q"));
+    for w in out.windows(2) {
+        assert!(w[0].start() < w[1].start());
+    }
+}
+
+#[test]
+fn dedup_longest() {
+    // The longest record is kept regardless of quality
+    let mut recs: Vec<_> = ms_input::open("tests/multiple.seed").take(1).collect();
+    let mut tr = Trace::from_record(&recs[0]).unwrap();
+    tr.quality = 'Q';
+    tr.data = tr.data.slice(0, 100);
+    recs.extend(tr.pack(512, DE_STEIM2));
+
+    let (out, stats) = Dedup::new().with_priority(Priority::Longest).process(recs);
+    assert_eq!(stats.pruned, 1);
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].dataquality(), "M");
+}
+
+#[test]
+fn dedup_sample_prune() {
+    let mut recs: Vec<_> = ms_input::open("tests/multiple.seed").take(10).collect();
+    let q = repacked(20);
+    let q_start = q[0].start();
+    recs.extend(q);
+
+    let (out, stats) = Dedup::new().with_sample_prune(true).process(recs);
+    assert_eq!(stats.trimmed, 1);
+    assert_eq!(stats.pruned, 4);
+
+    let traces = Trace::from_records(&out);
+    let m: Vec<_> = traces.iter().filter(|t| t.quality == 'M').collect();
+    assert_eq!(m.len(), 1);
+    assert_eq!(m[0].end1(), q_start);
+    assert!(m[0].end() < q_start - Duration::milliseconds(25));
+
+    // Record level pruning keeps the partially overlapped record
+    let mut recs: Vec<_> = ms_input::open("tests/multiple.seed").take(10).collect();
+    recs.extend(repacked(20));
+    let (_, stats) = Dedup::new().process(recs);
+    assert_eq!(stats.trimmed, 0);
+    assert_eq!(stats.pruned, 4);
+}
+
+#[test]
+fn dedup_files() {
+    let dir = std::env::temp_dir().join("miniseed-dedup");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.mseed");
+    let files = [
+        "tests/multiple.seed",
+        "tests/multiple.seed",
+        "tests/sample.miniseed",
+    ];
+    let stats = Dedup::new().files(&files, &out).unwrap();
+    assert_eq!(stats.duplicates, 1243);
+    assert_eq!(stats.written, 1244);
+    let ids: Vec<_> = ms_input::open(&out).map(|r| r.id()).collect();
+    assert_eq!(ids.len(), 1244);
+    assert_eq!(ids[0], "IU_ANMO_00_BHZ");
+    assert_eq!(ids[1243], "PN_PPNAF_00_HHZ");
+
+    // Records which are not trimmed are written unchanged
+    let data = fs::read(&out).unwrap();
+    assert_eq!(
+        &data[..1243 * 512],
+        &fs::read("tests/multiple.seed").unwrap()[..]
+    );
+    assert_eq!(
+        &data[1243 * 512..],
+        &fs::read("tests/sample.miniseed").unwrap()[..]
+    );
+}
+
+#[test]
+fn dedup_interleaved() {
+    // A duplicate is found when a record with the same header but other
+    // data is sorted between the copies
+    let first = || ms_input::open("tests/multiple.seed").take(1);
+    let mut recs: Vec<_> = first().collect();
+    let mut tr = Trace::from_record(&recs[0]).unwrap();
+    tr.data = Samples::Int(vec![0; tr.npts()]);
+    let other = tr.pack(512, DE_STEIM2);
+    assert_eq!(other.len(), 1);
+    recs.extend(other);
+    recs.extend(first());
+
+    let (out, stats) = Dedup::new().process(recs);
+    assert_eq!(stats.duplicates, 1);
+    assert_eq!(out.len(), 1);
+}
@@ -0,0 +1,272 @@
+extern crate chrono;
+extern crate miniseed;
+
+use chrono::{DateTime, Duration, Utc};
+use miniseed::earthworm::tracebuf::{epoch_to_utc, packet_len, utc_to_epoch};
+use miniseed::earthworm::{TraceBuf, WaveServerClient};
+use miniseed::{ms_input, ms_record, str_to_utc, Samples, Trace};
+
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+fn t0() -> DateTime<Utc> {
+    str_to_utc("2020-01-01T00:00:00").unwrap()
+}
+
+/// Packet of 100 samples at 100 Hz starting `offset` seconds after t0
+fn packet(offset: i64) -> TraceBuf {
+    TraceBuf {
+        pinno: 1,
+        station: "TEST".to_string(),
+        network: "XX".to_string(),
+        channel: "HHZ".to_string(),
+        location: "".to_string(),
+        start: t0() + Duration::seconds(offset),
+        samprate: 100.0,
+        quality: [0, 0],
+        data: Samples::Int((0..100).map(|i| offset as i32 * 100 + i).collect()),
+    }
+}
+
+/// Tank of three packets with a gap from 2 to 3 s after t0
+fn tank() -> Vec<TraceBuf> {
+    vec![packet(0), packet(1), packet(3)]
+}
+
+fn handle(stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut out = stream;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let v: Vec<&str> = line.split_whitespace().collect();
+    let tank = tank();
+    let first = tank[0].start;
+    let last = tank[tank.len() - 1].end();
+    match v[0] {
+        "MENU:" => writeln!(
+            out,
+            "{} 1 TEST HHZ XX -- {:.6} {:.6} s4 ",
+            v[1],
+            utc_to_epoch(&first),
+            utc_to_epoch(&last)
+        ),
+        "GETSCNLRAW:" | "GETSCNL:" => {
+            let head = format!("{} 1 {} {} {} {}", v[1], v[2], v[3], v[4], v[5]);
+            if v[2..6] != ["TEST", "HHZ", "XX", "--"] {
+                return writeln!(out, "{} FN", head);
+            }
+            let start = epoch_to_utc(v[6].parse().unwrap());
+            let end = epoch_to_utc(v[7].parse().unwrap());
+            if end < first {
+                return writeln!(out, "{} FL s4 {:.6}", head, utc_to_epoch(&first));
+            }
+            if start > last {
+                return writeln!(out, "{} FR s4 {:.6}", head, utc_to_epoch(&last));
+            }
+            if v[0] == "GETSCNLRAW:" {
+                let data: Vec<u8> = tank
+                    .iter()
+                    .filter(|tb| tb.end() >= start && tb.start <= end)
+                    .flat_map(|tb| tb.to_bytes(tb.start != first).unwrap())
+                    .collect();
+                writeln!(
+                    out,
+                    "{} F s4 {:.6} {:.6} {}",
+                    head,
+                    utc_to_epoch(&start),
+                    utc_to_epoch(&end),
+                    data.len()
+                )?;
+                out.write_all(&data)
+            } else {
+                let i0 = ((start - first).num_milliseconds() + 9) / 10;
+                let i1 = (end - first).num_milliseconds() / 10;
+                let values: Vec<String> = (i0..=i1)
+                    .map(|i| {
+                        if (200..300).contains(&i) {
+                            v[8].to_string()
+                        } else {
+                            i.to_string()
+                        }
+                    })
+                    .collect();
+                writeln!(
+                    out,
+                    "{} F s4 {:.6} 100.0 {}",
+                    head,
+                    utc_to_epoch(&(first + Duration::milliseconds(i0 * 10))),
+                    values.join(" ")
+                )
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Start a mock wave server holding the tank
+fn server() -> WaveServerClient {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let _ = handle(stream.unwrap());
+        }
+    });
+    WaveServerClient::new(&addr).with_timeout(std::time::Duration::from_secs(10))
+}
+
+#[test]
+fn tracebuf_types() {
+    let mut tb = packet(0);
+    for data in [
+        Samples::Int(vec![-1, 0, 1 << 20]),
+        Samples::Float(vec![-1.5, 0.0, 2.25]),
+        Samples::Double(vec![-1.5, 0.0, 1e300]),
+    ] {
+        tb.data = data;
+        for &big in &[true, false] {
+            let buf = tb.to_bytes(big).unwrap();
+            assert_eq!(packet_len(&buf).unwrap(), buf.len());
+            assert_eq!(TraceBuf::parse(&buf).unwrap(), tb);
+        }
+    }
+
+    // 16 bit samples are read as integers
+    tb.data = Samples::Int(vec![0; 3]);
+    let mut buf = tb.to_bytes(false).unwrap()[..64].to_vec();
+    buf[57..59].copy_from_slice(b"i2");
+    buf.extend_from_slice(&[0xff, 0xff, 0x02, 0x00, 0x00, 0x80]);
+    assert_eq!(packet_len(&buf).unwrap(), 70);
+    let tb = TraceBuf::parse(&buf).unwrap();
+    assert_eq!(tb.data, Samples::Int(vec![-1, 2, -32768]));
+    assert_eq!(tb.location, "");
+    assert_eq!(&buf[52..55], b"--\0");
+
+    assert!(TraceBuf::parse(&buf[..68]).is_err());
+    buf[57..59].copy_from_slice(b"x4");
+    assert!(TraceBuf::parse(&buf).is_err());
+
+    // ASCII samples have no TRACEBUF2 data type
+    let mut tb = packet(0);
+    tb.data = Samples::Ascii(b"text".to_vec());
+    let err = tb.to_bytes(true).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn tracebuf_times() {
+    let tb = packet(0);
+    let buf = tb.to_bytes(true).unwrap();
+    let mut t = [0u8; 8];
+    t.copy_from_slice(&buf[16..24]);
+    assert_eq!(
+        f64::from_bits(u64::from_be_bytes(t)),
+        utc_to_epoch(&tb.end())
+    );
+    assert_eq!(tb.end(), t0() + Duration::milliseconds(990));
+    assert_eq!(tb.id(), "XX_TEST__HHZ");
+}
+
+#[test]
+fn trace_to_tracebuf() {
+    let tr = Trace {
+        data: Samples::Double((0..1200).map(f64::from).collect()),
+        ..packet(0).to_trace()
+    };
+    let tbs = TraceBuf::from_trace(&tr);
+    assert_eq!(tbs.len(), 3);
+    assert!(tbs
+        .iter()
+        .all(|tb| tb.to_bytes(true).unwrap().len() <= 4096));
+    assert_eq!(tbs[1].start, tr.time_of(504));
+    let merged = Trace::merge(tbs.iter().map(TraceBuf::to_trace).collect());
+    assert_eq!(merged, vec![tr]);
+}
+
+#[test]
+fn records() {
+    let recs: Vec<ms_record> = ms_input::open("tests/multiple.seed").take(5).collect();
+    let tr = Trace::from_records(&recs).remove(0);
+    let tbs: Vec<TraceBuf> = recs.iter().flat_map(TraceBuf::from_record).collect();
+    assert_eq!(tbs[0].id(), "IU_ANMO_00_BHZ");
+    assert_eq!(tbs[0].start, tr.start);
+    let out: Vec<ms_record> = tbs.iter().flat_map(|tb| tb.to_records(512, None)).collect();
+    let back = Trace::from_records(&out);
+    assert_eq!(back.len(), 1);
+    assert_eq!(back[0].data, tr.data);
+    assert_eq!(back[0].start, tr.start);
+}
+
+#[test]
+fn menu() {
+    let client = server();
+    let menu = client.menu().unwrap();
+    assert_eq!(menu.len(), 1);
+    assert_eq!(menu[0].id(), "XX_TEST__HHZ");
+    assert_eq!(menu[0].pinno, 1);
+    assert_eq!(menu[0].start, t0());
+    assert_eq!(menu[0].end, t0() + Duration::milliseconds(3990));
+    assert_eq!(menu[0].datatype, "s4");
+}
+
+#[test]
+fn get_raw() {
+    let client = server();
+    let start = t0() + Duration::milliseconds(500);
+    let end = t0() + Duration::milliseconds(1500);
+    let tbs = client.get_raw("XX_TEST__HHZ", &start, &end).unwrap();
+    assert_eq!(tbs, vec![packet(0), packet(1)]);
+    let err = server()
+        .with_max_bytes(100)
+        .get_raw("XX_TEST__HHZ", &start, &end)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let traces = client
+        .traces("XX_TEST__HHZ", &t0(), &(t0() + Duration::seconds(10)))
+        .unwrap();
+    assert_eq!(traces.len(), 2);
+    assert_eq!(traces[0].npts(), 200);
+    assert_eq!(traces[0].quality, 'D');
+    assert_eq!(traces[1].start, t0() + Duration::seconds(3));
+    assert_eq!(traces[1].data, packet(3).data);
+}
+
+#[test]
+fn get_ascii() {
+    let client = server();
+    let start = t0() + Duration::milliseconds(1995);
+    let end = t0() + Duration::milliseconds(3010);
+    let tr = client
+        .get_ascii("XX_TEST__HHZ", &start, &end, -1)
+        .unwrap()
+        .unwrap();
+    assert_eq!(tr.id(), "XX_TEST__HHZ");
+    assert_eq!(tr.start, t0() + Duration::seconds(2));
+    assert_eq!(tr.samprate, 100.0);
+    let mut expected = vec![-1; 100];
+    expected.extend(300..302);
+    assert_eq!(tr.data, Samples::Int(expected));
+}
+
+#[test]
+fn no_data() {
+    let client = server();
+    let before = t0() - Duration::seconds(10);
+    let after = t0() + Duration::seconds(10);
+    assert!(client
+        .get_raw("XX_TEST__HHZ", &before, &(before + Duration::seconds(1)))
+        .unwrap()
+        .is_empty());
+    assert!(client
+        .get_ascii("XX_TEST__HHZ", &after, &(after + Duration::seconds(1)), 0)
+        .unwrap()
+        .is_none());
+
+    let err = client.get_raw("XX_TEST__BHZ", &t0(), &after).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = client.get_raw("XX_TEST_HHZ", &t0(), &after).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
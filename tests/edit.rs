@@ -0,0 +1,141 @@
+extern crate chrono;
+extern crate miniseed;
+
+use chrono::Duration;
+use miniseed::edit::{HeaderEditor, RenameRules};
+use miniseed::header::RawHeader;
+use miniseed::{ms_input, ms_record, str_to_utc};
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+
+fn read(file: &str) -> Vec<u8> {
+    let mut buf = vec![];
+    File::open(file).unwrap().read_to_end(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn edit_fields() {
+    let orig = read("tests/sample.miniseed");
+    let mut buf = orig.clone();
+    {
+        let mut ed = HeaderEditor::new(&mut buf).unwrap();
+        ed.set_station("TEST").unwrap();
+        ed.set_channel("EHZ").unwrap();
+        ed.set_sequence_number(42).unwrap();
+        ed.set_act_flags(0x02);
+        ed.set_io_flags(0x20);
+        ed.set_dq_flags(0x80);
+        ed.set_time_correction(-1234);
+        assert!(ed.set_network("TOO").is_err());
+        assert!(ed.set_quality('X').is_err());
+        assert!(ed.set_sequence_number(1_000_000).is_err());
+
+        let h = ed.header();
+        assert_eq!(h.id(), "PN_TEST_00_EHZ");
+        assert_eq!(h.sequence_number, 42);
+        assert_eq!((h.act_flags, h.io_flags, h.dq_flags), (0x02, 0x20, 0x80));
+        assert_eq!(h.time_correct, -1234);
+    }
+    // Data frames are unchanged
+    let hdr = RawHeader::parse(&orig).unwrap();
+    let d = hdr.data_offset as usize;
+    assert_eq!(&buf[d..], &orig[d..]);
+    assert_eq!(&buf[20..30], &orig[20..30]);
+
+    let rec = ms_record::parse(&buf);
+    assert_eq!(rec.id(), "PN_TEST_00_EHZ");
+    assert_eq!(rec.sequence_number(), 42);
+
+    assert!(HeaderEditor::new(&mut buf[1..]).is_none());
+}
+
+#[test]
+fn edit_blockettes() {
+    // The fixture has blockettes 1000 and 1001 but no blockette 100
+    let mut buf = read("tests/sample.miniseed");
+    let hdr = RawHeader::parse(&buf).unwrap();
+    assert_eq!(hdr.blockettes(&buf), vec![(1000, 48), (1001, 56)]);
+    assert!(hdr.timing_quality.is_some());
+    assert!(hdr.b100_samprate.is_none());
+    {
+        let mut ed = HeaderEditor::new(&mut buf).unwrap();
+        ed.set_timing_quality(55).unwrap();
+        ed.set_b1001_usec(-7).unwrap();
+        let h = ed.header();
+        assert_eq!(h.timing_quality, Some(55));
+        assert_eq!(h.b1001_usec, Some(-7));
+        assert!(ed.set_b100_samprate(99.5).is_err());
+        let t = str_to_utc("2016-01-01T00:00:00.123456").unwrap();
+        assert_eq!(ed.set_start(&t), t);
+        let h = ed.header();
+        assert_eq!(h.b1001_usec, Some(56));
+        assert_eq!(h.start() + Duration::microseconds(56), t);
+    }
+    assert_eq!(&buf[64..], &read("tests/sample.miniseed")[64..]);
+
+    // Without blockette 1001, by ending the chain at blockette 1000
+    let mut buf = read("tests/sample.miniseed");
+    buf[39] = 1;
+    buf[50] = 0;
+    buf[51] = 0;
+    let hdr = RawHeader::parse(&buf).unwrap();
+    assert_eq!(hdr.blockettes(&buf), vec![(1000, 48)]);
+    assert_eq!(hdr.timing_quality, None);
+    let mut ed = HeaderEditor::new(&mut buf).unwrap();
+    assert!(ed.set_timing_quality(55).is_err());
+    assert!(ed.set_b1001_usec(-7).is_err());
+    assert_eq!(ed.header().timing_quality, None);
+
+    // The start time is rounded to 0.0001 seconds
+    let t = str_to_utc("2016-01-01T00:00:00.123456").unwrap();
+    let stored = ed.set_start(&t);
+    assert_eq!(stored, str_to_utc("2016-01-01T00:00:00.1235").unwrap());
+    assert_eq!(ed.header().start(), stored);
+    let t = str_to_utc("2016-01-01T00:00:59.999951").unwrap();
+    let stored = ed.set_start(&t);
+    assert_eq!(stored, str_to_utc("2016-01-01T00:01:00").unwrap());
+    assert_eq!(ed.header().start(), stored);
+}
+
+#[test]
+fn rename_rules() {
+    let rules = RenameRules::parse(
+        "# rename
+         IU ANMO 00 BH?  XX * -- *  Q
+         IU * * *        YY * * *",
+    )
+    .unwrap();
+    assert_eq!(rules.rules().len(), 2);
+    assert_eq!(rules.rules()[0].to[2], Some(String::new()));
+    assert!(RenameRules::parse("IU ANMO 00 BHZ XX").is_err());
+    assert!(RenameRules::parse("IU ANMO 00 BHZ XX * * * X").is_err());
+
+    let dir = std::env::temp_dir().join("miniseed-rename");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("renamed.mseed");
+    assert_eq!(rules.apply_file("tests/multiple.seed", &out).unwrap(), 1243);
+    let recs: Vec<_> = ms_input::open(&out).collect();
+    assert_eq!(recs.len(), 1243);
+    assert!(recs.iter().all(|r| r.id() == "XX_ANMO__BHZ"));
+    assert!(recs.iter().all(|r| r.dataquality() == "Q"));
+
+    // Only the headers differ
+    let a = read("tests/multiple.seed");
+    let b = read(out.to_str().unwrap());
+    assert_eq!(a.len(), b.len());
+    for k in 0..1243 {
+        let r = k * 512;
+        assert_eq!(&a[r + 64..r + 512], &b[r + 64..r + 512]);
+    }
+
+    let copy = dir.join("copy.mseed");
+    fs::copy("tests/multiple.seed", &copy).unwrap();
+    let rules = RenameRules::parse("IU * * * YY * * *").unwrap();
+    assert_eq!(rules.apply_in_place(&copy).unwrap(), 1243);
+    assert_eq!(ms_input::open(&copy).next().unwrap().id(), "YY_ANMO_00_BHZ");
+    assert_eq!(rules.apply_in_place(&copy).unwrap(), 0);
+}
@@ -0,0 +1,61 @@
+extern crate chrono;
+extern crate miniseed;
+
+use chrono::Duration;
+use miniseed::extract::{extract, Coverage, EventRequest};
+use miniseed::{str_to_utc, Selection};
+
+#[test]
+fn extract_report() {
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHZ", None, None);
+    sel.add("IU_ANMO_00_BHN", None, None);
+    let origin = str_to_utc("2010-02-27T06:30:00").unwrap();
+    let req = EventRequest::new(
+        "early",
+        origin,
+        Duration::seconds(60),
+        Duration::seconds(60),
+        sel,
+    );
+
+    let events = extract(&[req], &["tests/multiple.seed"]);
+    assert_eq!(events.len(), 1);
+    let ev = &events[0];
+    assert_eq!(ev.traces.len(), 1);
+    assert_eq!(ev.traces[0].npts(), 1200);
+    assert!(!ev.is_complete());
+
+    let bad = ev.incomplete();
+    assert_eq!(bad.len(), 2);
+    assert_eq!(bad[0].id, "IU_ANMO_00_BHZ");
+    match bad[0].coverage {
+        Coverage::Partial(ref gaps) => {
+            assert_eq!(gaps.len(), 1);
+            assert_eq!(gaps[0].0, origin - Duration::seconds(60));
+        }
+        _ => panic!("expected partial coverage"),
+    }
+    assert_eq!(bad[1].id, "IU_ANMO_00_BHN");
+    assert_eq!(bad[1].coverage, Coverage::Missing);
+}
+
+#[test]
+fn extract_write_error() {
+    // Buffered output is flushed and errors are returned
+    if !std::path::Path::new("/dev/full").exists() {
+        return;
+    }
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHZ", None, None);
+    let origin = str_to_utc("2010-02-27T06:30:00").unwrap();
+    let req = EventRequest::new(
+        "early",
+        origin,
+        Duration::seconds(60),
+        Duration::seconds(60),
+        sel,
+    );
+    let events = extract(&[req], &["tests/multiple.seed"]);
+    assert!(events[0].write("/dev/full", 512, None).is_err());
+}
@@ -0,0 +1,457 @@
+extern crate chrono;
+extern crate miniseed;
+
+use miniseed::fdsn::http::{self, Request, Url};
+use miniseed::fdsn::{DataselectClient, FdsnServer, RequestLine};
+use miniseed::header::RawHeader;
+use miniseed::sds::SdsArchive;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Request received by the stand-in server
+#[derive(Debug, Clone)]
+struct Received {
+    method: String,
+    target: String,
+    body: String,
+}
+
+/// Serve requests with `handler`, which returns the status and body, and
+/// return the base URL and the requests received
+fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<Received>>>)
+where
+    F: Fn(&Received) -> (u16, Vec<u8>) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let log = Arc::new(Mutex::new(vec![]));
+    let log2 = log.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut rd = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            rd.read_line(&mut line).unwrap();
+            let v: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+            let mut len = 0;
+            loop {
+                let mut h = String::new();
+                rd.read_line(&mut h).unwrap();
+                if h.trim().is_empty() {
+                    break;
+                }
+                if h.to_lowercase().starts_with("content-length:") {
+                    len = h[15..].trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0u8; len];
+            rd.read_exact(&mut body).unwrap();
+            let req = Received {
+                method: v[0].clone(),
+                target: v[1].clone(),
+                body: String::from_utf8(body).unwrap(),
+            };
+            let (status, body) = handler(&req);
+            log2.lock().unwrap().push(req);
+            // Send data chunked, other responses with a length
+            let resp = if status == 200 {
+                let mut r = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+                for c in body.chunks(1000) {
+                    r.extend(format!("{:x}\r\n", c.len()).as_bytes());
+                    r.extend(c);
+                    r.extend(b"\r\n");
+                }
+                r.extend(b"0\r\n\r\n");
+                r
+            } else {
+                let mut r = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .into_bytes();
+                r.extend(body);
+                r
+            };
+            stream.write_all(&resp).unwrap();
+        }
+    });
+    (url, log)
+}
+
+fn data(n: usize) -> Vec<u8> {
+    let buf = std::fs::read("tests/multiple.seed").unwrap();
+    buf[..512 * n].to_vec()
+}
+
+fn line(cha: &str) -> RequestLine {
+    RequestLine::parse(&format!(
+        "IU ANMO 00 {} 2010-02-27T06:30:00 2010-02-27T07:30:00",
+        cha
+    ))
+    .unwrap()
+}
+
+#[test]
+fn get() {
+    let (url, log) = serve(|_| (200, data(10)));
+    let client = DataselectClient::new(&url).unwrap();
+    let recs: Vec<_> = client.get(&line("BHZ")).unwrap().unwrap().collect();
+    assert_eq!(recs.len(), 10);
+    let log = log.lock().unwrap();
+    assert_eq!(log[0].method, "GET");
+    assert!(log[0].target.starts_with(
+        "/fdsnws/dataselect/1/query?net=IU&sta=ANMO&loc=00&cha=BHZ&start=2010-02-27T06:30:00"
+    ));
+}
+
+#[test]
+fn post_bulk() {
+    let (url, log) = serve(|_| (200, data(20)));
+    let client = DataselectClient::new(&format!("{}/", url))
+        .unwrap()
+        .with_quality("B");
+    let lines = vec![line("BHZ"), line("BHN"), line("BHE")];
+    let n = client.post(&lines).unwrap().unwrap().count();
+    assert_eq!(n, 20);
+    let log = log.lock().unwrap();
+    assert_eq!(log[0].method, "POST");
+    assert_eq!(log[0].target, "/fdsnws/dataselect/1/query");
+    let body: Vec<&str> = log[0].body.lines().collect();
+    assert_eq!(body.len(), 4);
+    assert_eq!(body[0], "quality=B");
+    assert_eq!(
+        body[2],
+        "IU ANMO 00 BHN 2010-02-27T06:30:00.000000 2010-02-27T07:30:00.000000"
+    );
+}
+
+#[test]
+fn no_data() {
+    let (url, _) = serve(|r| {
+        if r.method == "GET" {
+            (204, vec![])
+        } else {
+            (404, b"No data".to_vec())
+        }
+    });
+    let client = DataselectClient::new(&url).unwrap();
+    assert!(client.get(&line("BHZ")).unwrap().is_none());
+    assert!(client.post(&[line("BHZ"), line("BHN")]).unwrap().is_none());
+    assert_eq!(client.fetch(&[line("BHZ")]).count(), 0);
+}
+
+#[test]
+fn errors() {
+    let (url, _) = serve(|_| (400, b"Bad request".to_vec()));
+    let client = DataselectClient::new(&url).unwrap();
+    let err = client.get(&line("BHZ")).err().unwrap();
+    assert!(err.to_string().contains("400"));
+    assert!(err.to_string().contains("Bad request"));
+    assert!(client.fetch(&[line("BHZ")]).next_record().is_err());
+    assert!(DataselectClient::new("https://service.iris.edu").is_err());
+}
+
+#[test]
+fn split_requests() {
+    let (url, log) = serve(|_| (200, data(2)));
+    let client = DataselectClient::new(&url)
+        .unwrap()
+        .with_max_lines(2)
+        .with_max_span(chrono::Duration::minutes(30));
+    let lines = vec![line("BHZ"), line("BHN")];
+    assert_eq!(client.requests(&lines).len(), 2);
+    let mut fetch = client.fetch(&lines);
+    assert_eq!(fetch.by_ref().count(), 4);
+    assert_eq!(fetch.requests(), 2);
+    let log = log.lock().unwrap();
+    assert!(log.iter().all(|r| r.method == "POST"));
+    assert!(log[0]
+        .body
+        .contains("BHZ 2010-02-27T06:30:00.000000 2010-02-27T07:00:00.000000"));
+    assert!(log[0]
+        .body
+        .contains("BHZ 2010-02-27T07:00:00.000000 2010-02-27T07:30:00.000000"));
+}
+
+#[test]
+fn too_large() {
+    // Accept requests of at most one line and 15 minutes
+    let (url, log) = serve(|r| {
+        let param = |k: &str| {
+            let v = r.target.split(k).nth(1)?;
+            miniseed::str_to_utc(v.split('&').next()?)
+        };
+        match (param("start="), param("end=")) {
+            (Some(t0), Some(t1)) if t1 - t0 <= chrono::Duration::minutes(15) => (200, data(1)),
+            _ => (413, b"Request too large".to_vec()),
+        }
+    });
+    let client = DataselectClient::new(&url).unwrap();
+    let lines = vec![line("BHZ"), line("BHN")];
+    let mut fetch = client.fetch(&lines);
+    assert_eq!(fetch.by_ref().count(), 8);
+    // One POST, then for each line one hour, two half hours and four
+    // quarter hours requested with GET
+    assert_eq!(fetch.requests(), 1 + 2 * 7);
+    assert_eq!(log.lock().unwrap().len(), 15);
+}
+
+#[test]
+fn split_edges() {
+    // Return whole records overlapping the window, as a server which does
+    // not trim records
+    let buf = std::fs::read("tests/multiple.seed").unwrap();
+    let overlapping = move |t0, t1| -> Vec<u8> {
+        buf.chunks(512)
+            .filter(|r| {
+                let h = RawHeader::parse(r).unwrap();
+                h.start() <= t1 && h.end() >= t0
+            })
+            .flat_map(|r| r.to_vec())
+            .collect()
+    };
+    let line = line("BHZ");
+    let edge = miniseed::str_to_utc("2010-02-27T07:00:00").unwrap();
+    let expected = overlapping(line.start, line.end).len() / 512;
+    let straddling = overlapping(edge, edge).len() / 512;
+    assert_eq!(straddling, 1);
+    let (url, _) = serve(move |r| {
+        let param = |k: &str| {
+            let v = r.target.split(k).nth(1)?;
+            miniseed::str_to_utc(v.split('&').next()?)
+        };
+        match (param("start="), param("end=")) {
+            (Some(t0), Some(t1)) => (200, overlapping(t0, t1)),
+            _ => (400, vec![]),
+        }
+    });
+    let client = DataselectClient::new(&url)
+        .unwrap()
+        .with_max_span(chrono::Duration::minutes(30));
+    let mut fetch = client.fetch(&[line]);
+    assert_eq!(fetch.by_ref().count(), expected);
+    assert_eq!(fetch.requests(), 2);
+}
+
+/// Create an SDS archive holding multiple.seed
+fn sds_archive(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&root);
+    let sds = SdsArchive::new(&root);
+    let t = miniseed::str_to_utc("2010-02-27").unwrap();
+    let path = sds.path("IU", "ANMO", "00", "BHZ", &t);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::copy("tests/multiple.seed", &path).unwrap();
+    root
+}
+
+/// Serve an SDS archive holding multiple.seed
+fn fdsn_server(name: &str, f: fn(FdsnServer) -> FdsnServer) -> String {
+    let server = f(FdsnServer::from_sds(sds_archive(name)));
+    format!("http://{}", server.spawn("127.0.0.1:0").unwrap())
+}
+
+fn http_get(url: &str, target: &str) -> (u16, String) {
+    let url = Url::parse(url).unwrap();
+    let resp = http::request(&url, "GET", target, &[], None, None).unwrap();
+    (resp.status, resp.text().unwrap())
+}
+
+#[test]
+fn server_dataselect() {
+    let url = fdsn_server("miniseed-fdsn-dataselect", |s| s);
+    let client = DataselectClient::new(&url).unwrap();
+    let t1 = miniseed::str_to_utc("2010-02-27T06:31:00").unwrap();
+    let mut line = line("BHZ");
+    line.end = t1;
+    let recs: Vec<_> = client.get(&line).unwrap().unwrap().collect();
+    assert_eq!(recs.len(), 4);
+    // The last record is trimmed to the window
+    assert!(recs[3].end() < t1);
+    assert_eq!(recs.iter().map(|r| r.npts()).sum::<usize>(), 1200);
+
+    // Bulk request with a channel without data
+    let mut other = line.clone();
+    other.channel = "BHN".to_string();
+    assert_eq!(
+        client
+            .post(&[line.clone(), other.clone()])
+            .unwrap()
+            .unwrap()
+            .count(),
+        4
+    );
+    assert!(client.get(&other).unwrap().is_none());
+
+    let (status, _) = http_get(
+        &url,
+        "/fdsnws/dataselect/1/query?net=IU&cha=BHN&start=2010-02-27&end=2010-02-28&nodata=404",
+    );
+    assert_eq!(status, 404);
+    let (status, text) = http_get(&url, "/fdsnws/dataselect/1/query?net=IU");
+    assert_eq!(status, 400);
+    assert!(text.starts_with("Error 400: Bad Request"));
+    assert_eq!(http_get(&url, "/fdsnws/dataselect/1/version").1, "1.1.0");
+}
+
+#[test]
+fn server_too_large() {
+    let url = fdsn_server("miniseed-fdsn-too-large", |s| {
+        s.with_max_lines(1)
+            .with_max_span(chrono::Duration::minutes(30))
+    });
+    let client = DataselectClient::new(&url).unwrap();
+    let lines = vec![line("BHZ"), line("BHN")];
+    let mut fetch = client.fetch(&lines);
+    // The whole hour of BHZ in two half hour requests
+    assert_eq!(fetch.by_ref().map(|r| r.npts()).sum::<usize>(), 72000);
+    assert_eq!(fetch.requests(), 1 + 2 * 3);
+
+    // Lists of codes are checked before being expanded to every
+    // combination of them
+    let codes: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+    let codes = codes.join(",");
+    let target = format!(
+        "/fdsnws/dataselect/1/query?net={0}&sta={0}&loc={0}&cha={0}&start=2010-02-27T07:00:00&end=2010-02-27T07:10:00",
+        codes
+    );
+    assert_eq!(http_get(&url, &target).0, 413);
+}
+
+/// Writer failing once `left` bytes are written
+struct Short {
+    buf: Vec<u8>,
+    left: usize,
+}
+
+impl Write for Short {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.left == 0 {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        let n = buf.len().min(self.left);
+        self.buf.extend_from_slice(&buf[..n]);
+        self.left -= n;
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn server_write_error() {
+    let server = FdsnServer::from_sds(sds_archive("miniseed-fdsn-write-error"));
+    let req = Request {
+        method: "GET".to_string(),
+        path: "/fdsnws/dataselect/1/query".to_string(),
+        query: http::parse_query("cha=BHZ&start=2010-02-27T07:00:00&end=2010-02-27T08:00:00"),
+        headers: vec![],
+        body: vec![],
+    };
+    // The response is cut short instead of ending with an error response
+    let mut w = Short {
+        buf: vec![],
+        left: 2000,
+    };
+    assert!(server.handle(&req, &mut w).is_err());
+    let text = String::from_utf8_lossy(&w.buf);
+    assert!(text.starts_with("HTTP/1.1 200 OK"));
+    assert!(!text.contains("Error 500"));
+}
+
+#[test]
+fn server_availability() {
+    let url = fdsn_server("miniseed-fdsn-availability", |s| s);
+    let (status, text) = http_get(&url, "/fdsnws/availability/1/extent?net=IU&sta=ANMO");
+    assert_eq!(status, 200);
+    let v: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        v[0],
+        "#Network Station Location Channel Quality SampleRate Earliest Latest Updated TimeSpans Restriction"
+    );
+    assert!(v[1].starts_with(
+        "IU ANMO 00 BHZ M 20 2010-02-27T06:30:00.019500Z 2010-02-27T10:30:00.019500Z "
+    ));
+    assert!(v[1].ends_with(" 1 OPEN"));
+
+    let (_, text) = http_get(
+        &url,
+        "/fdsnws/availability/1/query?cha=BHZ&start=2010-02-27T07:00:00&end=2010-02-27T08:00:00&format=json",
+    );
+    assert!(text.contains(
+        "\"datasources\":[{\"network\":\"IU\",\"station\":\"ANMO\",\"location\":\"00\",\"channel\":\"BHZ\",\
+         \"quality\":\"M\",\"samplerate\":20,\
+         \"timespans\":[[\"2010-02-27T07:00:00.000000Z\",\"2010-02-27T08:00:00.000000Z\"]]}]"
+    ));
+
+    let (_, text) = http_get(
+        &url,
+        "/fdsnws/availability/1/query?merge=quality,samplerate&format=geocsv",
+    );
+    let v: Vec<&str> = text.lines().collect();
+    assert_eq!(v[0], "#dataset: GeoCSV 2.0");
+    assert_eq!(v[4], "Network|Station|Location|Channel|Earliest|Latest");
+    assert_eq!(
+        v[5],
+        "IU|ANMO|00|BHZ|2010-02-27T06:30:00.019500Z|2010-02-27T10:30:00.019500Z"
+    );
+
+    assert_eq!(http_get(&url, "/fdsnws/availability/1/query?net=XX").0, 204);
+    assert_eq!(
+        http_get(&url, "/fdsnws/availability/1/query?format=xml").0,
+        400
+    );
+    assert_eq!(http_get(&url, "/fdsnws/station/1/query").0, 404);
+}
+
+#[test]
+fn server_limits() {
+    let url = fdsn_server("miniseed-fdsn-limits", |s| {
+        s.with_timeout(std::time::Duration::from_millis(100))
+    });
+    let addr = Url::parse(&url).unwrap();
+    let addr = format!("{}:{}", addr.host, addr.port);
+
+    // A huge body is refused without being read
+    let mut s = std::net::TcpStream::connect(&addr).unwrap();
+    write!(
+        s,
+        "POST /fdsnws/dataselect/1/query HTTP/1.1\r\nContent-Length: 999999999999\r\n\r\n"
+    )
+    .unwrap();
+    let mut resp = String::new();
+    BufReader::new(&s).read_line(&mut resp).unwrap();
+    assert!(resp.starts_with("HTTP/1.1 413 "));
+
+    // As are long request lines and many or long headers
+    let reply = |req: String| {
+        let mut s = std::net::TcpStream::connect(&addr).unwrap();
+        s.write_all(req.as_bytes()).unwrap();
+        let mut resp = String::new();
+        BufReader::new(&s).read_line(&mut resp).unwrap();
+        resp
+    };
+    let long = "a".repeat(http::MAX_LINE);
+    let resp = reply(format!("GET /{} HTTP/1.1\r\n\r\n", long));
+    assert!(resp.starts_with("HTTP/1.1 414 "));
+    let resp = reply(format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", long));
+    assert!(resp.starts_with("HTTP/1.1 431 "));
+    let resp = reply(format!(
+        "GET / HTTP/1.1\r\n{}\r\n",
+        "X: a\r\n".repeat(http::MAX_HEADERS + 1)
+    ));
+    assert!(resp.starts_with("HTTP/1.1 431 "));
+
+    // An idle connection is closed
+    let s = std::net::TcpStream::connect(&addr).unwrap();
+    s.set_read_timeout(Some(std::time::Duration::from_secs(10)))
+        .unwrap();
+    let mut buf = vec![];
+    assert_eq!((&s).read_to_end(&mut buf).unwrap(), 0);
+}
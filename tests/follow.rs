@@ -0,0 +1,175 @@
+extern crate miniseed;
+
+use miniseed::follow::Follow;
+use miniseed::{ms_input, Selection};
+
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The first `n` records of multiple.seed
+fn records(n: usize) -> Vec<Vec<u8>> {
+    let buf = fs::read("tests/multiple.seed").unwrap();
+    buf.chunks(512).take(n).map(|r| r.to_vec()).collect()
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("miniseed-follow");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn append(path: &Path, data: &[u8]) {
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    f.write_all(data).unwrap();
+}
+
+fn follow(path: &Path) -> Follow {
+    Follow::new(path)
+        .with_poll_interval(Duration::from_millis(5))
+        .with_idle_timeout(Duration::from_millis(500))
+}
+
+#[test]
+fn growing() {
+    let path = temp_file("growing.mseed");
+    let recs = records(10);
+    let data = recs.concat();
+    let p = path.clone();
+    let writer = thread::spawn(move || {
+        // Records are written in pieces which split records
+        for piece in data.chunks(300) {
+            append(&p, piece);
+            thread::sleep(Duration::from_millis(10));
+        }
+    });
+    let mut f = follow(&path);
+    let mut out = vec![];
+    while let Some(raw) = f.read_raw().unwrap() {
+        out.push(raw);
+    }
+    writer.join().unwrap();
+    assert_eq!(out, recs);
+    assert_eq!(f.offset(), 10 * 512);
+}
+
+#[test]
+fn from_end() {
+    let path = temp_file("from_end.mseed");
+    let recs = records(7);
+    append(&path, &recs[..5].concat());
+    let mut f = follow(&path).from_end();
+    assert_eq!(f.read_raw().unwrap(), None);
+    append(&path, &recs[5..].concat());
+    assert_eq!(f.read_raw().unwrap(), Some(recs[5].clone()));
+    assert_eq!(f.read_raw().unwrap(), Some(recs[6].clone()));
+    assert_eq!(f.offset(), 7 * 512);
+}
+
+#[test]
+fn truncated() {
+    let path = temp_file("truncated.mseed");
+    let recs = records(6);
+    append(&path, &recs[..3].concat());
+    let mut f = follow(&path);
+    for rec in &recs[..3] {
+        assert_eq!(f.read_raw().unwrap().as_ref(), Some(rec));
+    }
+    assert_eq!(f.read_raw().unwrap(), None);
+    fs::write(&path, recs[3..5].concat()).unwrap();
+    assert_eq!(f.read_raw().unwrap(), Some(recs[3].clone()));
+    assert_eq!(f.read_raw().unwrap(), Some(recs[4].clone()));
+    assert_eq!(f.offset(), 2 * 512);
+}
+
+#[test]
+fn rotated() {
+    let path = temp_file("rotated.mseed");
+    let old = temp_file("rotated.mseed.1");
+    let recs = records(6);
+    append(&path, &recs[..2].concat());
+    let mut f = follow(&path);
+    assert_eq!(f.read_raw().unwrap(), Some(recs[0].clone()));
+    // The rest of the old file is read before the new one
+    append(&path, &recs[2]);
+    fs::rename(&path, &old).unwrap();
+    append(&path, &recs[3..].concat());
+    let mut out = vec![];
+    while let Some(raw) = f.read_raw().unwrap() {
+        out.push(raw);
+    }
+    assert_eq!(out, recs[1..].to_vec());
+}
+
+#[test]
+fn rotated_held_back() {
+    // Records without a blockette 1000 are held back until the next
+    // record starts, or the file is rotated
+    let path = temp_file("held_back.mseed");
+    let old = temp_file("held_back.mseed.1");
+    let recs: Vec<Vec<u8>> = records(6)
+        .into_iter()
+        .map(|mut r| {
+            r[39] = 0;
+            r[46] = 0;
+            r[47] = 0;
+            r
+        })
+        .collect();
+    append(&path, &recs[..3].concat());
+    let mut f = follow(&path);
+    assert_eq!(f.read_raw().unwrap(), Some(recs[0].clone()));
+    assert_eq!(f.read_raw().unwrap(), Some(recs[1].clone()));
+    fs::rename(&path, &old).unwrap();
+    append(&path, &recs[3..].concat());
+    let mut out = vec![];
+    while let Some(raw) = f.read_raw().unwrap() {
+        out.push(raw);
+    }
+    // The last record of the new file is still held back
+    assert_eq!(out, recs[2..5].to_vec());
+}
+
+#[test]
+fn not_yet_created() {
+    let path = temp_file("created.mseed");
+    let recs = records(2);
+    let mut f = follow(&path).from_end();
+    assert_eq!(f.read_raw().unwrap(), None);
+    append(&path, &recs.concat());
+    assert_eq!(f.read_raw().unwrap(), Some(recs[0].clone()));
+}
+
+#[test]
+fn input() {
+    let path = temp_file("input.mseed");
+    let recs = records(4);
+    append(&path, &recs.concat());
+
+    let mut input = ms_input::open(&path);
+    input.seek_to_offset(1024);
+    let mut f = input
+        .follow()
+        .with_poll_interval(Duration::from_millis(5))
+        .with_idle_timeout(Duration::from_millis(100));
+    assert_eq!(f.path(), path.as_path());
+    assert_eq!(f.read_raw().unwrap(), Some(recs[2].clone()));
+
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHN", None, None);
+    let mut f = ms_input::open(&path)
+        .with_selection(sel)
+        .follow()
+        .with_idle_timeout(Duration::from_millis(100));
+    assert_eq!(f.read_raw().unwrap(), None);
+    assert_eq!(f.offset(), 4 * 512);
+}
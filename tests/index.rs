@@ -0,0 +1,80 @@
+extern crate miniseed;
+
+use miniseed::index::MsIndex;
+use miniseed::{ms_input, str_to_utc, Selection};
+
+use std::fs;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn index_build() {
+    let idx = MsIndex::build("tests/multiple.seed").unwrap();
+    assert_eq!(idx.len(), 1243);
+    let e = &idx.entries()[1];
+    assert_eq!(e.offset, 512);
+    assert_eq!(e.reclen, 512);
+    assert_eq!(e.id, "IU_ANMO_00_BHZ");
+    assert_eq!(e.quality, 'M');
+    assert_eq!(e.nsamples, 368);
+
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    for (e, r) in idx.entries().iter().zip(recs.iter()) {
+        assert_eq!(e.start, r.start());
+        assert_eq!(e.end, r.end());
+    }
+
+    // Records of impossible lengths are skipped
+    let dir = std::env::temp_dir().join("miniseed-index-reclen");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("bad.seed");
+    let mut buf = fs::read("tests/multiple.seed").unwrap();
+    buf.truncate(1024);
+    buf[54] = 30;
+    fs::write(&file, &buf).unwrap();
+    let idx = MsIndex::build(&file).unwrap();
+    assert_eq!(idx.len(), 1);
+    assert_eq!(idx.entries()[0].offset, 512);
+}
+
+#[test]
+fn index_sidecar() {
+    let dir = std::env::temp_dir().join("miniseed-index");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("multiple.seed");
+    fs::copy("tests/multiple.seed", &file).unwrap();
+
+    assert!(MsIndex::load(&file).is_err());
+    let idx = MsIndex::open(&file).unwrap();
+    assert!(MsIndex::sidecar(&file).exists());
+    let idx2 = MsIndex::load(&file).unwrap();
+    assert_eq!(idx.entries(), idx2.entries());
+
+    // The index is rebuilt when the file is modified, even if its size
+    // is unchanged
+    let sidecar = MsIndex::sidecar(&file);
+    let text = fs::read_to_string(&sidecar).unwrap();
+    let lines: Vec<&str> = text.lines().take(2).collect();
+    fs::write(&sidecar, lines.join("\n")).unwrap();
+    assert_eq!(MsIndex::open(&file).unwrap().len(), 1);
+    fs::File::options()
+        .write(true)
+        .open(&file)
+        .unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1 << 30))
+        .unwrap();
+    assert_eq!(MsIndex::open(&file).unwrap().len(), 1243);
+
+    let mut sel = Selection::new();
+    sel.add(
+        "IU_*",
+        str_to_utc("2010-02-27T08:00:00"),
+        str_to_utc("2010-02-27T08:01:00"),
+    );
+    let recs = idx2.records(&sel).unwrap();
+    assert!(!recs.is_empty());
+    for r in &recs {
+        assert!(sel.matches(r));
+    }
+}
@@ -0,0 +1,45 @@
+extern crate miniseed;
+
+use miniseed::merge::MergeInputs;
+use miniseed::ms_input;
+
+#[test]
+fn merge_sorted() {
+    let files = [
+        "tests/multiple.seed",
+        "tests/xff1a5c9523c74070823fb5bbc45df592",
+        "tests/xff46a8796af748f6aec5ba82c90445d5",
+        "tests/xff5ed77dda384bb087b21f93f4dd5415",
+        "tests/xffba7d5cfef54987b022bc6e313d0d6e",
+        "tests/xff00b5d8b3124f1aa2de549070709634",
+    ];
+    let mut merged = MergeInputs::from_files(&files);
+    assert_eq!(merged.len(), 6);
+    let t0 = merged.peek_time().unwrap();
+    let recs: Vec<_> = merged.collect();
+    assert_eq!(recs.len(), 1243 + 5);
+    assert_eq!(recs[0].start(), t0);
+    assert_eq!(recs[0].id(), "50_5720_02_BHZ");
+    assert_eq!(recs[1].id(), "50_5720_02_BHX");
+    for w in recs.windows(2) {
+        assert!((w[0].start(), w[0].id()) <= (w[1].start(), w[1].id()));
+    }
+}
+
+#[test]
+fn merge_ties() {
+    // Identical inputs are interleaved record by record
+    let inputs = vec![
+        ms_input::open("tests/multiple.seed"),
+        ms_input::open("tests/multiple.seed"),
+    ];
+    let recs: Vec<_> = MergeInputs::new(inputs).take(6).collect();
+    for k in 0..3 {
+        assert_eq!(recs[2 * k].start(), recs[2 * k + 1].start());
+    }
+    assert!(recs[1].start() < recs[2].start());
+
+    let mut empty = MergeInputs::new(vec![]);
+    assert!(empty.is_empty());
+    assert!(empty.next().is_none());
+}
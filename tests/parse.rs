@@ -12,3 +12,82 @@ fn parse() {
     let r = ms_record::parse(&buf);
     println!("{}", r);
 }
+
+fn seedlink_packets() -> Vec<u8> {
+    use std::io::Read;
+    let mut buf = vec![];
+    for file in &[
+        "tests/ff00b5d8b3124f1aa2de549070709634",
+        "tests/ff1a5c9523c74070823fb5bbc45df592",
+        "tests/ff46a8796af748f6aec5ba82c90445d5",
+        "tests/ff5ed77dda384bb087b21f93f4dd5415",
+        "tests/ffba7d5cfef54987b022bc6e313d0d6e",
+    ] {
+        std::fs::File::open(file)
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+    }
+    buf
+}
+
+#[test]
+fn parse_seedlink_stream() {
+    use miniseed::seedlink::PacketReader;
+    let buf = seedlink_packets();
+    let mut reader = PacketReader::new();
+    let mut ids = vec![];
+    for chunk in buf.chunks(100) {
+        reader.feed(chunk);
+        while let Some((_, rec)) = reader.next_record().unwrap() {
+            ids.push(rec.id());
+        }
+    }
+    assert!(reader.buffered().is_empty());
+
+    // A packet whose record cannot be decoded gives an error and is
+    // skipped
+    let mut bad = buf[..1040].to_vec();
+    bad[8 + 52] = 99;
+    reader.feed(&bad);
+    assert!(reader.next_record().is_err());
+    assert_eq!(reader.next_record().unwrap().unwrap().1.id(), ids[1]);
+    assert!(reader.buffered().is_empty());
+    assert_eq!(
+        ids,
+        vec![
+            "PN_PPNAF_00_HHZ",
+            "50_5720_02_BHZ",
+            "50_5720_02_BHX",
+            "YW_507_01_HH2",
+            "PO_CHGQ__HHE"
+        ]
+    );
+}
+
+#[test]
+fn parse_seedlink_packet() {
+    use miniseed::seedlink::SeedLinkPacket;
+    let buf = seedlink_packets();
+    let (pkt, n) = SeedLinkPacket::frame(&buf).unwrap().unwrap();
+    assert_eq!(pkt.sequence(), Some(0x95FCE3));
+    assert_eq!(n, 520);
+    assert!(SeedLinkPacket::frame(&buf[..519]).unwrap().is_none());
+    assert!(SeedLinkPacket::parse(&buf[..7]).unwrap().is_none());
+
+    let mut info = buf[..520].to_vec();
+    info[..8].copy_from_slice(b"SLINFO *");
+    assert_eq!(
+        SeedLinkPacket::parse(&info).unwrap(),
+        Some(SeedLinkPacket::Info(true))
+    );
+
+    let mut bad = buf[..520].to_vec();
+    bad[2] = b'X';
+    assert!(SeedLinkPacket::parse(&bad).is_err());
+    bad[..2].copy_from_slice(b"XL");
+    assert!(SeedLinkPacket::parse(&bad).is_err());
+    let mut bad = buf[..520].to_vec();
+    bad[14] = b'X';
+    assert!(SeedLinkPacket::frame(&bad).is_err());
+}
@@ -0,0 +1,57 @@
+extern crate miniseed;
+
+use miniseed::{ms_input, ms_output, ms_record, Selection, Trace, DE_STEIM2};
+
+use std::fs;
+
+#[test]
+fn raw_bytes_read() {
+    let data = fs::read("tests/multiple.seed").unwrap();
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    for (k, rec) in recs.iter().enumerate() {
+        assert_eq!(rec.raw_bytes(), &data[k * 512..(k + 1) * 512]);
+    }
+
+    // Records read header-only through a selection
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHZ", None, None);
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed")
+        .with_selection(sel)
+        .take(3)
+        .collect();
+    assert_eq!(recs[2].raw_bytes(), &data[1024..1536]);
+    assert_eq!(recs[2].npts(), ms_record::parse(&data[1024..1536]).npts());
+
+    let mut input = ms_input::open("tests/multiple.seed");
+    let rec = input.nth_record(100).unwrap();
+    assert_eq!(rec.raw_bytes(), &data[100 * 512..101 * 512]);
+
+    let sl = fs::read("tests/ff00b5d8b3124f1aa2de549070709634").unwrap();
+    let rec = ms_record::parse(&sl[8..]);
+    assert_eq!(rec.raw_bytes(), &sl[8..]);
+}
+
+#[test]
+fn passthrough() {
+    let dir = std::env::temp_dir().join("miniseed-raw");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let file = dir.join("copy.mseed");
+    let mut out = ms_output::open(&file).unwrap().with_passthrough(true);
+    for rec in ms_input::open("tests/sample.miniseed") {
+        out.write(&rec).unwrap();
+    }
+    let rec = ms_record::read("tests/sample.miniseed");
+    let packed = Trace::from_record(&rec).unwrap().pack(1024, DE_STEIM2);
+    out.write(&packed[0]).unwrap();
+    out.write_raw(rec.raw_bytes()).unwrap();
+    out.flush().unwrap();
+
+    let a = fs::read("tests/sample.miniseed").unwrap();
+    let b = fs::read(&file).unwrap();
+    assert_eq!(b.len(), 512 + 1024 + 512);
+    assert_eq!(&b[..512], &a[..]);
+    assert_eq!(&b[512..1536], packed[0].raw_bytes());
+    assert_eq!(&b[1536..], &a[..]);
+}
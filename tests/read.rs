@@ -26,8 +26,57 @@ fn read_multiple() {
     }
 
     // Sequence Number is incorrect, but everything else is "ok"
-    let mut out = ms_output::open("tests/multiple_out.seed").unwrap();
+    let path = std::env::temp_dir().join("miniseed-multiple-out.seed");
+    let mut out = ms_output::open(&path).unwrap();
     for m in &ms {
-        out.write(m);
+        out.write(m).unwrap();
     }
 }
+
+// Reader returning at most the given number of bytes per read
+struct Chunks<'a>(&'a [u8], usize);
+
+impl<'a> std::io::Read for Chunks<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.1).min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+#[test]
+fn read_stream() {
+    let buf = std::fs::read("tests/multiple.seed").unwrap();
+    // Deliver the bytes in small pieces as a network connection might
+    let reader = miniseed::RecordReader::new(Chunks(&buf[..], 100));
+    let recs: Vec<_> = reader.collect();
+    let expected: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    assert_eq!(recs.len(), 1243);
+    for (a, b) in recs.iter().zip(&expected) {
+        assert_eq!(a.raw_bytes(), b.raw_bytes());
+    }
+
+    let mut sel = miniseed::Selection::new();
+    sel.add(
+        "IU_ANMO_00_BHZ",
+        None,
+        miniseed::str_to_utc("2010-02-27T06:31:00"),
+    );
+    let reader = miniseed::RecordReader::new(&buf[..]).with_selection(sel);
+    assert_eq!(reader.count(), 4);
+
+    let mut reader = miniseed::RecordReader::new(&buf[100..]);
+    assert!(reader.read_raw().is_err());
+    let mut reader = miniseed::RecordReader::new(&buf[..600]);
+    assert_eq!(reader.read_raw().unwrap().unwrap().len(), 512);
+    assert!(reader.read_raw().is_err());
+    assert_eq!(reader.offset(), 512);
+
+    // A record which cannot be decoded gives an error
+    let mut bad = buf[..1024].to_vec();
+    bad[52] = 99;
+    let mut reader = miniseed::RecordReader::new(&bad[..]);
+    assert!(reader.next_record().is_err());
+    assert!(reader.next_record().unwrap().is_some());
+}
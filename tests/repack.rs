@@ -0,0 +1,69 @@
+extern crate miniseed;
+
+use miniseed::header::RawHeader;
+use miniseed::repack::Repacker;
+use miniseed::{ms_input, ms_record, Trace, DE_INT32, DE_STEIM1, DE_STEIM2};
+
+use std::fs;
+
+#[test]
+fn repack_reclen() {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").collect();
+    let before = Trace::from_records(&recs);
+    let usec = |r: &ms_record| RawHeader::parse(r.raw_bytes()).unwrap().b1001_usec;
+    let (start, us) = (recs[0].ptr().starttime, usec(&recs[0]));
+    assert_eq!(us, Some(38));
+    let (out, stats) = Repacker::new(4096, DE_STEIM2).repack(recs);
+    assert_eq!(stats.records_in, 1243);
+    assert_eq!(stats.bytes_in, 1243 * 512);
+    assert_eq!(stats.bytes_out, out.len() * 4096);
+    assert!(stats.ratio() > 1.0);
+    assert!(out.iter().all(|r| r.reclen() == 4096));
+    assert!(out.iter().all(|r| r.encoding() == DE_STEIM2));
+    assert!(out.iter().all(|r| r.dataquality() == "M"));
+    // The blockette 1001 microseconds of the start time are kept
+    assert_eq!(out[0].ptr().starttime, start);
+    assert_eq!(usec(&out[0]), us);
+
+    // Samples and timing are unchanged
+    let after = Trace::from_records(&out);
+    assert_eq!(before, after);
+    assert_eq!(stats.samples, after[0].npts());
+}
+
+#[test]
+fn repack_encoding() {
+    let recs: Vec<_> = ms_input::open("tests/multiple.seed").take(20).collect();
+    let before = Trace::from_records(&recs);
+    let (out, stats) = Repacker::new(512, DE_STEIM1).repack(recs);
+    assert!(out.iter().all(|r| r.encoding() == DE_STEIM1));
+    assert!(stats.ratio() < 1.0);
+    assert_eq!(Trace::from_records(&out), before);
+
+    let recs: Vec<_> = ms_input::open("tests/sample.miniseed").collect();
+    let (out, _) = Repacker::new(1024, DE_INT32).repack(recs);
+    assert_eq!(out.len(), 1);
+    assert_eq!(
+        out[0].header().act_flags,
+        ms_input::open("tests/sample.miniseed")
+            .next()
+            .unwrap()
+            .header()
+            .act_flags
+    );
+}
+
+#[test]
+fn repack_files() {
+    let dir = std::env::temp_dir().join("miniseed-repack");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("out.mseed");
+    let files = ["tests/multiple.seed", "tests/sample.miniseed"];
+    let stats = Repacker::new(4096, DE_STEIM2).files(&files, &out).unwrap();
+    assert_eq!(stats.records_in, 1244);
+    assert_eq!(fs::metadata(&out).unwrap().len() as usize, stats.bytes_out);
+    let ids: Vec<_> = ms_input::open(&out).map(|r| r.id()).collect();
+    assert_eq!(ids.len(), stats.records_out);
+    assert_eq!(ids.last().unwrap(), "PN_PPNAF_00_HHZ");
+}
@@ -0,0 +1,135 @@
+extern crate miniseed;
+
+use miniseed::sds::SdsArchive;
+use miniseed::{str_to_utc, Selection};
+
+use std::fs;
+use std::path::PathBuf;
+
+fn archive(name: &str) -> SdsArchive {
+    let root: PathBuf = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&root);
+    let sds = SdsArchive::new(&root);
+    let t = str_to_utc("2010-02-27").unwrap();
+    let path = sds.path("IU", "ANMO", "00", "BHZ", &t);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::copy("tests/multiple.seed", &path).unwrap();
+    sds
+}
+
+#[test]
+fn sds_read() {
+    let sds = archive("miniseed-sds-read");
+    let mut sel = Selection::new();
+    sel.add("IU_*_*_BH?", None, None);
+    let t0 = str_to_utc("2010-02-27T06:30:00").unwrap();
+    let t1 = str_to_utc("2010-02-27T06:31:00").unwrap();
+
+    assert_eq!(sds.day_files(&sel, &t0, &t1).len(), 1);
+    assert_eq!(sds.records(&sel, &t0, &t1).len(), 4);
+
+    let tr = sds.traces(&sel, &t0, &t1);
+    assert_eq!(tr.len(), 1);
+    assert_eq!(tr[0].npts(), 1200);
+}
+
+#[test]
+fn sds_missing() {
+    let sds = archive("miniseed-sds-missing");
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_10_BHZ", None, None);
+    let t0 = str_to_utc("2010-02-27T06:30:00").unwrap();
+    let t1 = str_to_utc("2010-02-27T06:31:00").unwrap();
+    assert!(sds.day_files(&sel, &t0, &t1).is_empty());
+    assert!(sds.traces(&sel, &t0, &t1).is_empty());
+}
+
+#[test]
+fn sds_write() {
+    use miniseed::ms_input;
+    use miniseed::sds::SdsWriter;
+
+    let root = std::env::temp_dir().join("miniseed-sds-write");
+    let _ = fs::remove_dir_all(&root);
+    let mut sds = SdsWriter::new(&root).with_max_open(1);
+    for rec in ms_input::open("tests/multiple.seed") {
+        sds.write(&rec).unwrap();
+    }
+    assert_eq!(sds.num_open(), 1);
+    assert_eq!(
+        sds.close_idle(std::time::Duration::from_secs(0)).unwrap(),
+        1
+    );
+    sds.close().unwrap();
+
+    let t = str_to_utc("2010-02-27").unwrap();
+    let path = SdsArchive::new(&root).path("IU", "ANMO", "00", "BHZ", &t);
+    assert_eq!(ms_input::open(path).count(), 1243);
+}
+
+#[test]
+fn output_errors() {
+    use miniseed::{ms_input, ms_output};
+
+    // Write errors are returned rather than panicking in the pack handler
+    if !std::path::Path::new("/dev/full").exists() {
+        return;
+    }
+    let mut out = ms_output::open("/dev/full").unwrap();
+    let failed = ms_input::open("tests/multiple.seed").any(|rec| out.write(&rec).is_err());
+    assert!(failed || out.flush().is_err());
+}
+
+#[test]
+fn sds_split_midnight() {
+    use miniseed::header::RawHeader;
+    use miniseed::sds::SdsWriter;
+    use miniseed::{ms_input, ms_record, Samples, Trace, DE_STEIM1};
+
+    // 10 s on either side of midnight in one record, with the flags and
+    // timing quality of multiple.seed
+    let midnight = str_to_utc("2010-02-28").unwrap();
+    let tr = Trace {
+        network: "IU".to_string(),
+        station: "ANMO".to_string(),
+        location: "00".to_string(),
+        channel: "BHZ".to_string(),
+        quality: 'D',
+        start: str_to_utc("2010-02-27T23:59:50").unwrap(),
+        samprate: 20.0,
+        data: Samples::Int((0..400).collect()),
+    };
+    let template = ms_input::open("tests/multiple.seed").next().unwrap();
+    let recs: Vec<_> = tr
+        .pack_like(&template, 4096, DE_STEIM1)
+        .iter()
+        .map(|b| ms_record::parse(b))
+        .collect();
+    assert_eq!(recs.len(), 1);
+
+    let root = std::env::temp_dir().join("miniseed-sds-midnight");
+    let _ = fs::remove_dir_all(&root);
+    let mut sds = SdsWriter::new(&root).with_split_midnight(true);
+    sds.write(&recs[0]).unwrap();
+    sds.close().unwrap();
+
+    let sds = SdsArchive::new(&root);
+    let before = sds.path("IU", "ANMO", "00", "BHZ", &tr.start);
+    let after = sds.path("IU", "ANMO", "00", "BHZ", &midnight);
+    assert_ne!(before, after);
+    let a: Vec<_> = ms_input::open(&before).collect();
+    let b: Vec<_> = ms_input::open(&after).collect();
+    assert_eq!(a.iter().map(|r| r.npts()).sum::<usize>(), 200);
+    assert_eq!(b.iter().map(|r| r.npts()).sum::<usize>(), 200);
+    assert_eq!(a[0].start(), tr.start);
+    assert!(a.last().unwrap().end() < midnight);
+    assert_eq!(b[0].start(), midnight);
+    assert_eq!(b.last().unwrap().end(), tr.end());
+
+    // Split records keep the header flags
+    for rec in a.iter().chain(&b) {
+        let h = RawHeader::parse(rec.raw_bytes()).unwrap();
+        assert_eq!(h.io_flags, 0x20);
+        assert_eq!(h.timing_quality, Some(100));
+    }
+}
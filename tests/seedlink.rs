@@ -0,0 +1,347 @@
+extern crate miniseed;
+
+use miniseed::seedlink::{Mode, SeedLinkClient};
+use miniseed::{Samples, Trace, DE_ASCII};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn records(n: usize) -> Vec<Vec<u8>> {
+    let mut buf = vec![];
+    std::fs::File::open("tests/multiple.seed")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    buf.chunks(512).take(n).map(|c| c.to_vec()).collect()
+}
+
+fn info_packet(text: &str) -> Vec<u8> {
+    let tr = Trace {
+        network: "XX".to_string(),
+        station: "MOCK".to_string(),
+        location: "".to_string(),
+        channel: "LOG".to_string(),
+        quality: 'D',
+        start: miniseed::str_to_utc("2020-01-01").unwrap(),
+        samprate: 0.0,
+        data: Samples::Ascii(text.as_bytes().to_vec()),
+    };
+    let mut pkt = b"SLINFO  ".to_vec();
+    pkt.extend(&tr.pack_bytes(512, DE_ASCII)[0]);
+    pkt
+}
+
+/// Behaviour of the mock server
+#[derive(Clone)]
+struct Mock {
+    packets: Vec<Vec<u8>>,
+    /// Close the first connection after this many packets
+    drop_after: Option<usize>,
+    /// Wait for a keepalive before sending data
+    keepalive: bool,
+}
+
+/// Serve `conns` connections and return the address and the commands
+/// received
+fn serve(mock: Mock, conns: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let log = Arc::new(Mutex::new(vec![]));
+    let log2 = log.clone();
+    thread::spawn(move || {
+        for i in 0..conns {
+            let (stream, _) = listener.accept().unwrap();
+            session(stream, &mock, i == 0, &log2);
+        }
+    });
+    (addr, log)
+}
+
+fn session(mut stream: TcpStream, mock: &Mock, first: bool, log: &Arc<Mutex<Vec<String>>>) {
+    let mut rd = BufReader::new(stream.try_clone().unwrap());
+    let mut start = 0;
+    let mut waiting = false;
+    loop {
+        let mut line = String::new();
+        if rd.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        let line = line.trim_end().to_string();
+        log.lock().unwrap().push(line.clone());
+        let v: Vec<&str> = line.split_whitespace().collect();
+        let send_data = match v[0] {
+            "HELLO" => {
+                stream
+                    .write_all(b"SeedLink v3.1 (mock)\r\nmock server\r\n")
+                    .unwrap();
+                false
+            }
+            "STATION" | "SELECT" => {
+                stream.write_all(b"OK\r\n").unwrap();
+                false
+            }
+            "DATA" | "FETCH" => {
+                if v.len() > 1 {
+                    start = usize::from_str_radix(v[1], 16).unwrap();
+                }
+                stream.write_all(b"OK\r\n").unwrap();
+                false
+            }
+            "INFO" => {
+                stream.write_all(&info_packet("<seedlink/>")).unwrap();
+                waiting
+            }
+            "END" => {
+                waiting = mock.keepalive && first;
+                !waiting
+            }
+            _ => return,
+        };
+        if send_data {
+            let end = match mock.drop_after {
+                Some(n) if first => n,
+                _ => mock.packets.len(),
+            };
+            for i in start..end {
+                stream.write_all(format!("SL{:06X}", i).as_bytes()).unwrap();
+                stream.write_all(&mock.packets[i]).unwrap();
+            }
+            if end < mock.packets.len() {
+                return;
+            }
+            stream.write_all(b"END").unwrap();
+        }
+    }
+}
+
+fn client(addr: &str) -> SeedLinkClient {
+    SeedLinkClient::new(addr)
+        .with_station("IU", "ANMO", &["00BHZ.D"])
+        .with_mode(Mode::Fetch)
+        .with_backoff(Duration::from_millis(10), Duration::from_millis(50))
+        .with_retries(2)
+}
+
+#[test]
+fn fetch() {
+    let mock = Mock {
+        packets: records(10),
+        drop_after: None,
+        keepalive: false,
+    };
+    let (addr, log) = serve(mock, 1);
+    let mut c = client(&addr);
+    let mut seqs = vec![];
+    while let Some((seq, rec)) = c.next_record().unwrap() {
+        assert_eq!(rec.id(), "IU_ANMO_00_BHZ");
+        seqs.push(seq);
+    }
+    assert_eq!(seqs, (0..10).collect::<Vec<u32>>());
+    assert_eq!(c.server_id(), Some("SeedLink v3.1 (mock)"));
+    assert_eq!(c.stations()[0].sequence, Some(9));
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["HELLO", "STATION ANMO IU", "SELECT 00BHZ.D", "FETCH", "END"]
+    );
+}
+
+#[test]
+fn reconnect() {
+    let mock = Mock {
+        packets: records(10),
+        drop_after: Some(4),
+        keepalive: false,
+    };
+    let (addr, log) = serve(mock, 2);
+    let recs: Vec<_> = client(&addr).collect();
+    assert_eq!(recs.len(), 10);
+    let log = log.lock().unwrap();
+    let fetch: Vec<&String> = log.iter().filter(|l| l.starts_with("FETCH")).collect();
+    assert_eq!(fetch.len(), 2);
+    assert_eq!(fetch[0], "FETCH");
+    assert!(fetch[1].starts_with("FETCH 000004 2010,02,27,06,"));
+}
+
+#[test]
+fn state_file() {
+    let state = std::env::temp_dir().join("miniseed-seedlink.state");
+    let _ = std::fs::remove_file(&state);
+    let mock = Mock {
+        packets: records(10),
+        drop_after: None,
+        keepalive: false,
+    };
+    let (addr, log) = serve(mock, 2);
+
+    let mut c = client(&addr).with_state_file(&state);
+    for _ in 0..3 {
+        c.next_record().unwrap().unwrap();
+    }
+    c.close().unwrap();
+    let mut s = String::new();
+    std::fs::File::open(&state)
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    assert!(s.starts_with("IU ANMO 2 2010,02,27,06,"));
+
+    let mut c = client(&addr).with_state_file(&state);
+    let (seq, _) = c.next_record().unwrap().unwrap();
+    assert_eq!(seq, 3);
+    let log = log.lock().unwrap();
+    assert!(log.iter().any(|l| l.starts_with("FETCH 000003 ")));
+
+    // Lines of other stations are kept
+    std::fs::write(&state, "XX OTHER 5\nIU ANMO 7\n").unwrap();
+    let mut c = client(&addr).with_state_file(&state);
+    c.load_state().unwrap();
+    c.save_state().unwrap();
+    let s = std::fs::read_to_string(&state).unwrap();
+    assert_eq!(s, "XX OTHER 5\nIU ANMO 7\n");
+
+    // A state file which cannot be loaded is not replaced
+    std::fs::write(&state, "IU ANMO x\n").unwrap();
+    let mut c = client(&addr).with_state_file(&state);
+    assert!(c.next_record().is_err());
+    c.close().unwrap();
+    assert!(c.save_state().is_err());
+    let s = std::fs::read_to_string(&state).unwrap();
+    assert_eq!(s, "IU ANMO x\n");
+}
+
+#[test]
+fn keepalive() {
+    let mock = Mock {
+        packets: records(2),
+        drop_after: None,
+        keepalive: true,
+    };
+    let (addr, log) = serve(mock, 1);
+    let recs: Vec<_> = client(&addr)
+        .with_keepalive(Duration::from_millis(200))
+        .collect();
+    assert_eq!(recs.len(), 2);
+    assert!(log.lock().unwrap().iter().any(|l| l == "INFO ID"));
+}
+
+#[test]
+fn info() {
+    let mock = Mock {
+        packets: vec![],
+        drop_after: None,
+        keepalive: false,
+    };
+    let (addr, _) = serve(mock, 2);
+    let c = SeedLinkClient::new(&addr);
+    let (id, org) = c.hello().unwrap();
+    assert_eq!(id, "SeedLink v3.1 (mock)");
+    assert_eq!(org, "mock server");
+    assert_eq!(c.info("ID").unwrap(), "<seedlink/>");
+}
+
+#[test]
+fn retries() {
+    let addr = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let mut c = client(&addr);
+    assert!(c.next_record().is_err());
+}
+
+fn server() -> String {
+    use miniseed::seedlink::SeedLinkServer;
+    let server = SeedLinkServer::from_files(&["tests/multiple.seed"]).with_organization("test");
+    server.spawn("127.0.0.1:0").unwrap().to_string()
+}
+
+#[test]
+fn server_fetch() {
+    let addr = server();
+    let mut c = client(&addr);
+    let mut n = 0;
+    while let Some((seq, rec)) = c.next_record().unwrap() {
+        assert_eq!(seq, n);
+        assert_eq!(rec.id(), "IU_ANMO_00_BHZ");
+        n += 1;
+    }
+    assert_eq!(n, 1243);
+}
+
+#[test]
+fn server_selectors() {
+    let addr = server();
+    let c = SeedLinkClient::new(&addr)
+        .with_station("IU", "ANMO", &["BHN"])
+        .with_mode(Mode::Fetch)
+        .with_retries(0);
+    assert_eq!(c.count(), 0);
+    let c = SeedLinkClient::new(&addr)
+        .with_station("IU", "ANMO", &["??BH?.D", "!10BHZ"])
+        .with_mode(Mode::Fetch)
+        .with_retries(0);
+    assert_eq!(c.count(), 1243);
+
+    let mut c = SeedLinkClient::new(&addr)
+        .with_station("XX", "ANMO", &[])
+        .with_mode(Mode::Fetch)
+        .with_retries(0);
+    assert!(c.next_record().is_err());
+}
+
+#[test]
+fn server_time_window() {
+    let addr = server();
+    let t0 = miniseed::str_to_utc("2010-02-27T06:30:00").unwrap();
+    let t1 = miniseed::str_to_utc("2010-02-27T06:31:00").unwrap();
+    let c = client(&addr).with_mode(Mode::Time(t0, Some(t1)));
+    assert_eq!(c.count(), 4);
+}
+
+#[test]
+fn server_resume() {
+    let state = std::env::temp_dir().join("miniseed-seedlink-server.state");
+    std::fs::write(&state, "IU ANMO 1000\n").unwrap();
+    let addr = server();
+    let mut c = client(&addr).with_state_file(&state);
+    let (seq, _) = c.next_record().unwrap().unwrap();
+    assert_eq!(seq, 1001);
+    assert_eq!(c.count(), 241);
+}
+
+#[test]
+fn server_info() {
+    let addr = server();
+    let c = SeedLinkClient::new(&addr);
+    let (id, org) = c.hello().unwrap();
+    assert!(id.starts_with("SeedLink v3.1"));
+    assert_eq!(org, "test");
+    assert!(c.info("ID").unwrap().contains("organization=\"test\""));
+    let xml = c.info("STREAMS").unwrap();
+    assert!(xml.contains("<station name=\"ANMO\" network=\"IU\""));
+    assert!(xml.contains("end_seq=\"0004DA\""));
+    assert!(xml.contains(
+        "location=\"00\" seedname=\"BHZ\" type=\"D\" begin_time=\"2010/02/27 06:30:00.0195\""
+    ));
+}
+
+#[test]
+fn server_long_line() {
+    // A command line without an end closes the session once too long
+    let mut s = TcpStream::connect(server()).unwrap();
+    s.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    s.write_all(&[b'A'; 2048]).unwrap();
+    let mut buf = vec![];
+    assert_eq!(s.read_to_end(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn server_replay() {
+    use miniseed::seedlink::SeedLinkServer;
+    let server = SeedLinkServer::from_files(&["tests/multiple.seed"]).with_replay(1.0);
+    let addr = server.spawn("127.0.0.1:0").unwrap().to_string();
+    assert_eq!(client(&addr).count(), 1);
+}
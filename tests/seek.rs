@@ -0,0 +1,42 @@
+extern crate miniseed;
+
+use miniseed::{ms_input, str_to_utc};
+
+#[test]
+fn seek_offsets() {
+    let mut input = ms_input::open("tests/multiple.seed");
+    let mut offsets = vec![];
+    let mut starts = vec![];
+    while let Some(rec) = input.next() {
+        offsets.push(input.current_offset().unwrap());
+        starts.push(rec.start());
+    }
+    assert_eq!(offsets.len(), 1243);
+    assert_eq!(offsets[1242], 1242 * 512);
+
+    // Seek backwards, including to the start of the file
+    for &i in &[700, 3, 0, 1242] {
+        input.seek_to_offset(offsets[i]);
+        assert_eq!(input.next().unwrap().start(), starts[i]);
+        assert_eq!(input.next().map(|r| r.start()), starts.get(i + 1).cloned());
+    }
+}
+
+#[test]
+fn seek_nth_and_time() {
+    let mut input = ms_input::open("tests/multiple.seed");
+    assert_eq!(input.fixed_reclen(), Some(512));
+    let starts: Vec<_> = ms_input::open("tests/multiple.seed")
+        .map(|r| r.start())
+        .collect();
+    assert_eq!(input.nth_record(1000).unwrap().start(), starts[1000]);
+    assert_eq!(input.nth_record(10).unwrap().start(), starts[10]);
+
+    assert_eq!(input.seek_to_time(&starts[500]), Some(500));
+    assert_eq!(input.next().unwrap().start(), starts[500]);
+    assert_eq!(
+        input.seek_to_time(&str_to_utc("2000-01-01").unwrap()),
+        Some(0)
+    );
+    assert_eq!(input.seek_to_time(&str_to_utc("2020-01-01").unwrap()), None);
+}
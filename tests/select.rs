@@ -0,0 +1,38 @@
+extern crate miniseed;
+
+use miniseed::{ms_input, str_to_utc, Selection};
+
+#[test]
+fn select_time_window() {
+    let mut sel = Selection::new();
+    sel.add(
+        "IU_ANMO_00_BH?",
+        str_to_utc("2010-02-27T06:30:00"),
+        str_to_utc("2010-02-27T06:31:00"),
+    );
+    let input = ms_input::open("tests/multiple.seed").with_selection(sel);
+    let ms: Vec<_> = input.collect();
+    assert_eq!(ms.len(), 4);
+    for m in &ms {
+        assert_eq!(m.id(), "IU_ANMO_00_BHZ");
+        assert!(m.npts() > 0);
+    }
+}
+
+#[test]
+fn select_quality() {
+    let sel = Selection::parse("IU ANMO 00 BHZ D\n").unwrap();
+    let input = ms_input::open("tests/multiple.seed").with_selection(sel);
+    assert_eq!(input.count(), 0);
+
+    let sel = Selection::parse("IU ANMO 00 BHZ M\n").unwrap();
+    let input = ms_input::open("tests/multiple.seed").with_selection(sel);
+    assert_eq!(input.count(), 1243);
+}
+
+#[test]
+fn select_parse_errors() {
+    assert!(Selection::parse("IU ANMO").is_err());
+    assert!(Selection::parse("IU ANMO 00 BHZ DQ").is_err());
+    assert!(Selection::parse("IU ANMO 00 BHZ D 2010-99-01").is_err());
+}
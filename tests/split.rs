@@ -0,0 +1,84 @@
+extern crate miniseed;
+
+use miniseed::archive::Period;
+use miniseed::split::Splitter;
+use miniseed::{ms_input, Trace};
+
+use std::fs;
+
+#[test]
+fn split_channel() {
+    let dir = std::env::temp_dir().join("miniseed-split-channel");
+    let _ = fs::remove_dir_all(&dir);
+    let mut split = Splitter::new(&dir, "%n.%s.%l.%c.mseed");
+    assert_eq!(split.period(), Period::Unbounded);
+    for file in &[
+        "tests/sample.miniseed",
+        "tests/multiple.seed",
+        "tests/xff5ed77dda384bb087b21f93f4dd5415",
+    ] {
+        split.split(ms_input::open(file)).unwrap();
+    }
+    split.close().unwrap();
+    assert_eq!(split.paths().len(), 3);
+    assert_eq!(
+        ms_input::open(dir.join("IU.ANMO.00.BHZ.mseed")).count(),
+        1243
+    );
+
+    // Files are truncated when first written to
+    let mut split = Splitter::new(&dir, "%n.%s.%l.%c.mseed");
+    split
+        .split(ms_input::open("tests/sample.miniseed"))
+        .unwrap();
+    split.close().unwrap();
+    assert_eq!(ms_input::open(&split.paths()[0]).count(), 1);
+}
+
+#[test]
+fn split_hour_cut() {
+    let dir = std::env::temp_dir().join("miniseed-split-hour");
+    let _ = fs::remove_dir_all(&dir);
+    let mut split = Splitter::new(&dir, "%Y/%j/%n.%s.%l.%c.%H")
+        .with_cut(true)
+        .with_max_open(2);
+    assert_eq!(split.period(), Period::Hour);
+    assert_eq!(
+        split.split(ms_input::open("tests/multiple.seed")).unwrap(),
+        1243
+    );
+    split.close().unwrap();
+
+    let npts: usize = ms_input::open("tests/multiple.seed")
+        .map(|r| r.npts())
+        .sum();
+    let mut total = 0;
+    for path in split.paths() {
+        let recs: Vec<_> = ms_input::open(path).collect();
+        let t0 = Period::Hour.start(&recs[0].start());
+        for r in &recs {
+            assert!(r.start() >= t0);
+            assert!(r.end() < Period::Hour.next(&t0).unwrap());
+        }
+        total += recs.iter().map(|r| r.npts()).sum::<usize>();
+    }
+    assert_eq!(total, npts);
+    assert_eq!(split.paths()[0], dir.join("2010/058/IU.ANMO.00.BHZ.06"));
+}
+
+#[test]
+fn split_day_period() {
+    // An explicit period overrides the template, records are not cut
+    // at hour boundaries
+    let dir = std::env::temp_dir().join("miniseed-split-day");
+    let _ = fs::remove_dir_all(&dir);
+    let mut split = Splitter::new(&dir, "%n.%s.%l.%c.%Y.%j.%H")
+        .with_period(Period::Day)
+        .with_cut(true);
+    assert_eq!(split.period(), Period::Day);
+    split.split(ms_input::open("tests/multiple.seed")).unwrap();
+    split.close().unwrap();
+    let recs: Vec<_> = split.paths().iter().flat_map(ms_input::open).collect();
+    assert_eq!(recs.len(), 1243);
+    assert_eq!(Trace::from_records(&recs).len(), 1);
+}
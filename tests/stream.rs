@@ -0,0 +1,89 @@
+#![cfg(feature = "async")]
+
+extern crate futures;
+extern crate miniseed;
+
+use futures::executor::block_on;
+use futures::{stream, SinkExt, StreamExt};
+use miniseed::{ms_input, ms_record, RecordReader, RecordSink, RecordStream, Selection};
+
+use std::fs;
+use std::io;
+
+fn bytes() -> Vec<u8> {
+    fs::read("tests/multiple.seed").unwrap()
+}
+
+#[test]
+fn read_stream() {
+    let buf = bytes();
+    let recs: Vec<ms_record> = block_on(RecordStream::new(&buf[..]).map(Result::unwrap).collect());
+    let expected: Vec<ms_record> = ms_input::open("tests/multiple.seed").collect();
+    assert_eq!(recs.len(), 1243);
+    assert_eq!(recs.len(), expected.len());
+    for (a, b) in recs.iter().zip(expected.iter()) {
+        assert_eq!(a.raw_bytes(), b.raw_bytes());
+    }
+}
+
+#[test]
+fn read_raw() {
+    let buf = bytes();
+    let mut s = RecordStream::new(&buf[..]);
+    let mut n = 0;
+    while let Some(raw) = block_on(futures::future::poll_fn(|cx| s.poll_raw(cx))) {
+        assert_eq!(raw.unwrap(), &buf[n * 512..(n + 1) * 512]);
+        n += 1;
+    }
+    assert_eq!(n, 1243);
+    assert_eq!(s.offset(), buf.len() as u64);
+}
+
+#[test]
+fn same_as_reader() {
+    // A stream truncated in the fourth record
+    let buf = bytes();
+    let buf = &buf[..512 * 3 + 300];
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHZ", None, None);
+    let a: Vec<io::Result<ms_record>> =
+        block_on(RecordStream::new(buf).with_selection(sel.clone()).collect());
+    let mut reader = RecordReader::new(buf).with_selection(sel);
+    assert_eq!(a.len(), 4);
+    for r in &a[..3] {
+        let raw = reader.read_raw().unwrap().unwrap();
+        assert_eq!(r.as_ref().unwrap().raw_bytes(), &raw[..]);
+    }
+    assert_eq!(
+        a[3].as_ref().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(
+        reader.read_raw().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn invalid() {
+    let buf = vec![b'x'; 1024];
+    let mut s = RecordStream::new(&buf[..]);
+    let err = block_on(s.next()).unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(block_on(s.next()).is_none());
+}
+
+#[test]
+fn write_sink() {
+    let buf = bytes();
+    let recs = RecordReader::new(&buf[..]).map(Ok);
+    let mut sink = RecordSink::new(Vec::new()).with_capacity(1024);
+    block_on(stream::iter(recs).forward(&mut sink)).unwrap();
+    assert_eq!(sink.into_inner(), buf);
+
+    let mut sink = RecordSink::new(Vec::new());
+    let rec = ms_input::open("tests/multiple.seed").next().unwrap();
+    block_on(sink.feed(rec)).unwrap();
+    block_on(sink.flush()).unwrap();
+    assert_eq!(sink.into_inner(), &buf[..512]);
+}
@@ -0,0 +1,145 @@
+extern crate miniseed;
+
+use miniseed::watch::Watcher;
+use miniseed::Selection;
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Empty directory for a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("miniseed-watch").join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The first `n` records of multiple.seed
+fn records(n: usize) -> Vec<u8> {
+    let buf = fs::read("tests/multiple.seed").unwrap();
+    buf[..512 * n].to_vec()
+}
+
+fn watcher(dir: &PathBuf) -> Watcher {
+    Watcher::new(dir)
+        .with_poll_interval(Duration::from_millis(10))
+        .with_settle_time(Duration::from_millis(50))
+        .with_idle_timeout(Duration::from_millis(500))
+}
+
+#[test]
+fn existing_and_new() {
+    let dir = temp_dir("existing");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("a.mseed"), records(2)).unwrap();
+    fs::write(dir.join("sub/b.mseed"), records(3)).unwrap();
+    fs::write(dir.join("notes.txt"), "not data").unwrap();
+
+    let d = dir.clone();
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        fs::write(d.join("c.mseed"), records(1)).unwrap();
+    });
+    let recs: Vec<(PathBuf, _)> = watcher(&dir).with_pattern("*.mseed").collect();
+    writer.join().unwrap();
+    let paths: Vec<PathBuf> = recs.iter().map(|r| r.0.clone()).collect();
+    let mut expected = vec![dir.join("a.mseed"); 2];
+    expected.extend(vec![dir.join("sub/b.mseed"); 3]);
+    expected.push(dir.join("c.mseed"));
+    assert_eq!(paths, expected);
+    assert_eq!(recs[0].1.raw_bytes(), &records(1)[..]);
+}
+
+#[test]
+fn done_and_failed() {
+    let dir = temp_dir("moved");
+    let inbox = dir.join("inbox");
+    let done = dir.join("done");
+    let failed = dir.join("failed");
+    fs::create_dir_all(inbox.join("sub")).unwrap();
+    fs::write(inbox.join("sub/a.mseed"), records(2)).unwrap();
+    fs::write(inbox.join("bad.mseed"), vec![b'x'; 1024]).unwrap();
+
+    let mut w = watcher(&inbox)
+        .with_done_dir(&done)
+        .with_failed_dir(&failed);
+    while w.next_record().unwrap().is_some() {}
+    assert!(!inbox.join("sub/a.mseed").exists());
+    assert_eq!(fs::read(done.join("sub/a.mseed")).unwrap(), records(2));
+    assert!(!inbox.join("bad.mseed").exists());
+    assert!(failed.join("bad.mseed").exists());
+}
+
+#[test]
+fn output_inside_root() {
+    let dir = temp_dir("inside");
+    fs::write(dir.join("a.mseed"), records(1)).unwrap();
+    let mut w = watcher(&dir).with_done_dir(dir.join("done"));
+    let mut n = 0;
+    while w.next_record().unwrap().is_some() {
+        n += 1;
+    }
+    assert_eq!(n, 1);
+    assert!(dir.join("done/a.mseed").exists());
+}
+
+#[test]
+fn incomplete() {
+    // A file still being written is read once it settles
+    let dir = temp_dir("incomplete");
+    let path = dir.join("a.mseed");
+    let p = path.clone();
+    let writer = thread::spawn(move || {
+        let data = records(4);
+        for i in 1..=4 {
+            fs::write(&p, &data[..512 * i]).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        }
+    });
+    let mut w = watcher(&dir).with_settle_time(Duration::from_millis(150));
+    let mut n = 0;
+    while let Some((p, _)) = w.next_record().unwrap() {
+        assert_eq!(p, path);
+        n += 1;
+    }
+    writer.join().unwrap();
+    assert_eq!(n, 4);
+}
+
+#[test]
+fn selection() {
+    let dir = temp_dir("selection");
+    fs::write(dir.join("a.mseed"), records(2)).unwrap();
+    let mut sel = Selection::new();
+    sel.add("IU_ANMO_00_BHN", None, None);
+    let mut w = watcher(&dir).with_selection(sel);
+    assert!(w.next_record().unwrap().is_none());
+    assert_eq!(w.current_path(), None);
+}
+
+#[test]
+fn same_name_again() {
+    // A file moved to the done directory does not hide a later file of
+    // the same name
+    let dir = temp_dir("again");
+    let inbox = dir.join("inbox");
+    let done = dir.join("done");
+    fs::create_dir_all(&inbox).unwrap();
+    fs::write(inbox.join("a.mseed"), records(2)).unwrap();
+
+    let mut w = watcher(&inbox).with_done_dir(&done);
+    let mut n = 0;
+    while n < 2 && w.next_record().unwrap().is_some() {
+        n += 1;
+    }
+    assert_eq!(n, 2);
+    fs::write(inbox.join("a.mseed"), records(3)).unwrap();
+    while w.next_record().unwrap().is_some() {
+        n += 1;
+    }
+    assert_eq!(n, 5);
+    assert!(!inbox.join("a.mseed").exists());
+    assert_eq!(fs::read(done.join("a.mseed")).unwrap(), records(3));
+}